@@ -227,6 +227,7 @@ HOST=0.0.0.0
 PORT=$APP_PORT
 BASE_URL=$BASE_URL
 DATABASE_PATH=$DATA_DIR/ping0.db
+TRUSTED_PROXIES=127.0.0.1,::1
 ENVV
 }
 
@@ -316,6 +317,9 @@ server {
         proxy_set_header X-Real-IP \$remote_addr;
         proxy_set_header X-Forwarded-For \$proxy_add_x_forwarded_for;
         proxy_set_header X-Forwarded-Proto \$scheme;
+        # ping0 trusts this proxy, so don't pass on client-supplied forwarding headers it doesn't set
+        proxy_set_header CF-Connecting-IP "";
+        proxy_set_header Forwarded "";
         proxy_read_timeout 90s;
     }
 }
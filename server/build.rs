@@ -0,0 +1,10 @@
+// The S3 storage integration test (tests/storage.rs) runs when PING0_TEST_S3_ENDPOINT points at a test
+// bucket's endpoint, e.g. a local MinIO, and is ignored otherwise.
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(s3_test)");
+    println!("cargo::rerun-if-changed=build.rs");
+    println!("cargo::rerun-if-env-changed=PING0_TEST_S3_ENDPOINT");
+    if std::env::var_os("PING0_TEST_S3_ENDPOINT").is_some_and(|v| !v.is_empty()) {
+        println!("cargo::rustc-cfg=s3_test");
+    }
+}
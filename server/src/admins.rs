@@ -0,0 +1,383 @@
+// Admin panel users. Every session belongs to one user, and what a user may do depends on their role.
+// New users join through a single-use invite link created by an owner.
+// Sessions end on the server after SESSION_IDLE_TIMEOUT_SECS without use or SESSION_MAX_AGE_SECS after
+// login, whichever comes first, regardless of what the browser does with the cookie.
+
+use rand::{distributions::Alphanumeric, Rng};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::apikeys::{hash_token, Scope};
+use crate::handlers::now_secs;
+
+const INVITE_TOKEN_LEN: usize = 32;
+const INVITE_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+const MIN_PASSWORD_LEN: usize = 8;
+const SESSION_TOKEN_LEN: usize = 48;
+// last_seen_at is only written when it is at least this old, so browsing doesn't write on every request
+const SESSION_TOUCH_SECS: i64 = 60;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    // Everything, including users and API keys
+    Owner,
+    // Review, re-expire and delete items
+    Moderator,
+    // Read-only: items and their stats
+    Viewer,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::Moderator, Role::Viewer];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Moderator => "moderator",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|role| role.as_str() == s.trim())
+    }
+
+    pub fn rank(self) -> u8 {
+        match self {
+            Role::Owner => 2,
+            Role::Moderator => 1,
+            Role::Viewer => 0,
+        }
+    }
+
+    pub fn at_least(self, min: Role) -> bool {
+        self.rank() >= min.rank()
+    }
+
+    // API scopes a browser session of this role has
+    pub fn allows(self, scope: Scope) -> bool {
+        match self {
+            Role::Owner => true,
+            Role::Moderator => matches!(scope, Scope::Read | Scope::Delete),
+            Role::Viewer => scope == Scope::Read,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AdminUser {
+    pub id: i64,
+    pub username: String,
+    pub role: Role,
+    // Session the request was made with
+    pub session_id: i64,
+}
+
+pub enum AccountError {
+    // Shown to the user on the page that submitted the form
+    Rejected(String),
+    Internal(anyhow::Error),
+}
+
+impl From<rusqlite::Error> for AccountError {
+    fn from(e: rusqlite::Error) -> Self {
+        AccountError::Internal(e.into())
+    }
+}
+
+fn rejected<T>(message: impl Into<String>) -> Result<T, AccountError> {
+    Err(AccountError::Rejected(message.into()))
+}
+
+pub fn validate_credentials(username: &str, password: &str) -> Result<(), String> {
+    if username.trim().is_empty() {
+        return Err("Username is required".to_string());
+    }
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(format!("Password must be at least {} characters", MIN_PASSWORD_LEN));
+    }
+    Ok(())
+}
+
+pub fn admin_exists(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT EXISTS (SELECT 1 FROM admin)", [], |r| r.get(0))
+}
+
+fn username_taken(conn: &Connection, username: &str) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT EXISTS (SELECT 1 FROM admin WHERE username = ?1)", params![username.trim()], |r| r.get(0))
+}
+
+// Checks a new account's credentials and hashes the password, before any database work
+pub async fn hash_new_password(username: &str, password: String) -> Result<String, AccountError> {
+    validate_credentials(username, &password).map_err(AccountError::Rejected)?;
+    crate::password::hash_password_async(password).await.map_err(|e| AccountError::Internal(anyhow::Error::msg(e)))
+}
+
+// `hash` is the Argon2 hash of a password that passed validate_credentials
+pub fn insert_user(conn: &Connection, username: &str, hash: &str, role: Role) -> Result<i64, AccountError> {
+    if username_taken(conn, username)? {
+        return rejected(format!("Username '{}' is taken", username.trim()));
+    }
+    conn.execute(
+        "INSERT INTO admin (username, password_hash, salt, role, created_at) VALUES (?1, ?2, '', ?3, ?4)",
+        params![username.trim(), hash, role.as_str(), now_secs()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+// ---------- Sessions ----------
+
+pub fn env_secs(name: &str, default: i64) -> i64 {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok()).filter(|v: &i64| *v > 0).unwrap_or(default)
+}
+
+pub fn session_idle_timeout() -> i64 {
+    env_secs("SESSION_IDLE_TIMEOUT_SECS", 24 * 60 * 60)
+}
+
+pub fn session_max_age() -> i64 {
+    env_secs("SESSION_MAX_AGE_SECS", 30 * 24 * 60 * 60)
+}
+
+// Start a session for a user who just logged in. `previous` is the session cookie the browser sent,
+// if any; it is ended so a token planted before login can't ride along.
+pub fn create_session(conn: &Connection, user_id: i64, previous: Option<&str>, ip: Option<&str>, user_agent: Option<&str>) -> rusqlite::Result<String> {
+    if let Some(previous) = previous {
+        conn.execute("DELETE FROM sessions WHERE token = ?1", params![previous])?;
+    }
+    let token: String = rand::thread_rng().sample_iter(&Alphanumeric).take(SESSION_TOKEN_LEN).map(char::from).collect();
+    let now = now_secs();
+    conn.execute(
+        "INSERT INTO sessions (token, created_at, user_id, last_seen_at, ip, user_agent) VALUES (?1, ?2, ?3, ?2, ?4, ?5)",
+        params![token, now, user_id, ip, user_agent],
+    )?;
+    Ok(token)
+}
+
+pub fn end_session(conn: &Connection, token: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM sessions WHERE token = ?1", params![token])?;
+    Ok(())
+}
+
+fn session_expired(now: i64, created_at: i64, last_seen_at: i64) -> bool {
+    now - created_at >= session_max_age() || now - last_seen_at >= session_idle_timeout()
+}
+
+// The active user a session token belongs to. Expired sessions are deleted on the spot.
+pub fn session_user(conn: &Connection, token: Option<&str>) -> Option<AdminUser> {
+    let token = token?;
+    let row = conn
+        .query_row(
+            "SELECT s.rowid, s.created_at, COALESCE(s.last_seen_at, s.created_at), a.id, a.username, a.role
+             FROM sessions s JOIN admin a ON a.id = s.user_id
+             WHERE s.token = ?1 AND a.disabled_at IS NULL",
+            params![token],
+            |r| Ok((r.get::<_, i64>(0)?, r.get::<_, i64>(1)?, r.get::<_, i64>(2)?, r.get::<_, i64>(3)?, r.get::<_, String>(4)?, r.get::<_, String>(5)?)),
+        )
+        .optional();
+    let (session_id, created_at, last_seen_at, id, username, role) = match row {
+        Ok(Some(row)) => row,
+        Ok(None) => return None,
+        Err(e) => { tracing::error!("session lookup: {}", e); return None; }
+    };
+    let now = now_secs();
+    if session_expired(now, created_at, last_seen_at) {
+        let _ = end_session(conn, token);
+        return None;
+    }
+    if now - last_seen_at >= SESSION_TOUCH_SECS {
+        let _ = conn.execute("UPDATE sessions SET last_seen_at = ?1 WHERE rowid = ?2", params![now, session_id]);
+    }
+    Some(AdminUser { id, username, role: Role::parse(&role)?, session_id })
+}
+
+pub struct SessionRow {
+    pub id: i64,
+    pub username: String,
+    pub created_at: i64,
+    pub last_seen_at: i64,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+// Unexpired sessions: all of them for owners, otherwise only the actor's own
+pub fn list_sessions(conn: &Connection, actor: &AdminUser) -> rusqlite::Result<Vec<SessionRow>> {
+    let owner_filter = (actor.role != Role::Owner).then_some(actor.id);
+    let now = now_secs();
+    let mut stmt = conn.prepare(
+        "SELECT s.rowid, a.username, s.created_at, COALESCE(s.last_seen_at, s.created_at), s.ip, s.user_agent
+         FROM sessions s JOIN admin a ON a.id = s.user_id
+         WHERE (?1 IS NULL OR s.user_id = ?1) AND a.disabled_at IS NULL
+           AND s.created_at > ?2 AND COALESCE(s.last_seen_at, s.created_at) > ?3
+         ORDER BY COALESCE(s.last_seen_at, s.created_at) DESC",
+    )?;
+    let rows = stmt.query_map(params![owner_filter, now - session_max_age(), now - session_idle_timeout()], |r| Ok(SessionRow {
+        id: r.get(0)?,
+        username: r.get(1)?,
+        created_at: r.get(2)?,
+        last_seen_at: r.get(3)?,
+        ip: r.get(4)?,
+        user_agent: r.get(5)?,
+    }))?;
+    rows.collect()
+}
+
+// End one session; owners may end anyone's, other users only their own
+pub fn revoke_session(conn: &Connection, actor: &AdminUser, id: i64) -> rusqlite::Result<bool> {
+    let owner_filter = (actor.role != Role::Owner).then_some(actor.id);
+    let n = conn.execute("DELETE FROM sessions WHERE rowid = ?1 AND (?2 IS NULL OR user_id = ?2)", params![id, owner_filter])?;
+    Ok(n > 0)
+}
+
+// End every session the actor can see except the one they are using
+pub fn revoke_other_sessions(conn: &Connection, actor: &AdminUser) -> rusqlite::Result<usize> {
+    let owner_filter = (actor.role != Role::Owner).then_some(actor.id);
+    conn.execute("DELETE FROM sessions WHERE rowid != ?1 AND (?2 IS NULL OR user_id = ?2)", params![actor.session_id, owner_filter])
+}
+
+// Used by the reaper
+pub fn purge_expired_sessions(conn: &Connection) -> rusqlite::Result<usize> {
+    let now = now_secs();
+    conn.execute(
+        "DELETE FROM sessions WHERE created_at <= ?1 OR COALESCE(last_seen_at, created_at) <= ?2",
+        params![now - session_max_age(), now - session_idle_timeout()],
+    )
+}
+
+pub struct UserRow {
+    pub id: i64,
+    pub username: String,
+    pub role: String,
+    pub created_at: Option<i64>,
+    pub disabled: bool,
+}
+
+pub fn list_users(conn: &Connection) -> rusqlite::Result<Vec<UserRow>> {
+    let mut stmt = conn.prepare("SELECT id, username, role, created_at, disabled_at IS NOT NULL FROM admin ORDER BY id")?;
+    let rows = stmt.query_map([], |r| Ok(UserRow {
+        id: r.get(0)?,
+        username: r.get(1)?,
+        role: r.get(2)?,
+        created_at: r.get(3)?,
+        disabled: r.get(4)?,
+    }))?;
+    rows.collect()
+}
+
+// Owners other than `id` that can still log in; there must always be at least one
+pub fn other_active_owners(conn: &Connection, id: i64) -> rusqlite::Result<i64> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM admin WHERE role = 'owner' AND disabled_at IS NULL AND id != ?1",
+        params![id],
+        |r| r.get(0),
+    )
+}
+
+fn user_exists(conn: &Connection, id: i64) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT EXISTS (SELECT 1 FROM admin WHERE id = ?1)", params![id], |r| r.get(0))
+}
+
+pub fn set_role(conn: &mut Connection, actor: &AdminUser, id: i64, role: Role) -> Result<(), AccountError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if !user_exists(&tx, id)? {
+        return rejected("No such user");
+    }
+    if role != Role::Owner && other_active_owners(&tx, id)? == 0 {
+        return rejected("There must be at least one active owner");
+    }
+    tx.execute("UPDATE admin SET role = ?1 WHERE id = ?2", params![role.as_str(), id])?;
+    tx.commit()?;
+    tracing::info!("Admin '{}' set the role of user {} to {}", actor.username, id, role.as_str());
+    Ok(())
+}
+
+// Disabling a user also ends all of their sessions
+pub fn set_disabled(conn: &mut Connection, actor: &AdminUser, id: i64, disabled: bool) -> Result<(), AccountError> {
+    if disabled && id == actor.id {
+        return rejected("You can't disable yourself");
+    }
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if !user_exists(&tx, id)? {
+        return rejected("No such user");
+    }
+    if disabled {
+        if other_active_owners(&tx, id)? == 0 {
+            return rejected("There must be at least one active owner");
+        }
+        tx.execute("UPDATE admin SET disabled_at = COALESCE(disabled_at, ?1) WHERE id = ?2", params![now_secs(), id])?;
+        tx.execute("DELETE FROM sessions WHERE user_id = ?1", params![id])?;
+    } else {
+        tx.execute("UPDATE admin SET disabled_at = NULL WHERE id = ?1", params![id])?;
+    }
+    tx.commit()?;
+    tracing::info!("Admin '{}' {} user {}", actor.username, if disabled { "disabled" } else { "enabled" }, id);
+    Ok(())
+}
+
+// ---------- Invites ----------
+
+// Returns the invite token; only its hash is stored
+pub fn create_invite(conn: &Connection, actor: &AdminUser, role: Role) -> rusqlite::Result<String> {
+    let token: String = rand::thread_rng().sample_iter(&Alphanumeric).take(INVITE_TOKEN_LEN).map(char::from).collect();
+    let now = now_secs();
+    conn.execute(
+        "INSERT INTO admin_invites (token_hash, role, created_by, created_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![hash_token(&token), role.as_str(), actor.id, now, now + INVITE_TTL_SECS],
+    )?;
+    tracing::info!("Admin '{}' invited a new {}", actor.username, role.as_str());
+    Ok(token)
+}
+
+pub struct InviteRow {
+    pub id: i64,
+    pub role: String,
+    pub created_by: Option<String>,
+    pub expires_at: i64,
+}
+
+// Invites that are neither used nor expired
+pub fn list_invites(conn: &Connection) -> rusqlite::Result<Vec<InviteRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT i.id, i.role, a.username, i.expires_at FROM admin_invites i LEFT JOIN admin a ON a.id = i.created_by
+         WHERE i.accepted_by IS NULL AND i.expires_at > ?1 ORDER BY i.created_at DESC",
+    )?;
+    let rows = stmt.query_map(params![now_secs()], |r| Ok(InviteRow {
+        id: r.get(0)?,
+        role: r.get(1)?,
+        created_by: r.get(2)?,
+        expires_at: r.get(3)?,
+    }))?;
+    rows.collect()
+}
+
+pub fn revoke_invite(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM admin_invites WHERE id = ?1 AND accepted_by IS NULL", params![id])?;
+    Ok(())
+}
+
+fn pending_invite(conn: &Connection, token: &str) -> rusqlite::Result<Option<(i64, Role)>> {
+    let row: Option<(i64, String)> = conn
+        .query_row(
+            "SELECT id, role FROM admin_invites WHERE token_hash = ?1 AND accepted_by IS NULL AND expires_at > ?2",
+            params![hash_token(token.trim()), now_secs()],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .optional()?;
+    Ok(row.and_then(|(id, role)| Role::parse(&role).map(|role| (id, role))))
+}
+
+// Role an invite token grants, if it can still be used
+pub fn invite_role(conn: &Connection, token: &str) -> rusqlite::Result<Option<Role>> {
+    Ok(pending_invite(conn, token)?.map(|(_, role)| role))
+}
+
+pub fn accept_invite(conn: &mut Connection, token: &str, username: &str, hash: &str) -> Result<(), AccountError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let Some((invite_id, role)) = pending_invite(&tx, token)? else {
+        return rejected("This invite is invalid, expired or already used");
+    };
+    let user_id = insert_user(&tx, username, hash, role)?;
+    tx.execute("UPDATE admin_invites SET accepted_by = ?1 WHERE id = ?2", params![user_id, invite_id])?;
+    tx.commit()?;
+    tracing::info!("'{}' joined as {} through an invite", username.trim(), role.as_str());
+    Ok(())
+}
@@ -0,0 +1,298 @@
+// Click analytics: one row per visit of /s/:code.
+// Raw IPs are never stored; visitors are identified by a hash salted with a random value that
+// is replaced every UTC day, so hashes can't be linked across days or reversed afterwards.
+// With GEOIP_DB_PATH set, the country and region are resolved from a local .mmdb file.
+
+use axum::http::{header, HeaderMap};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use sha2::{Digest, Sha256};
+use maxminddb::{geoip2, MaxMindDBError};
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use crate::handlers::now_secs;
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+// Current (day, salt); the database copy lets restarts keep counting the same visitors
+static SALT: Mutex<Option<(i64, Vec<u8>)>> = Mutex::new(None);
+
+// Link-preview crawlers (chat apps, social networks) fetch pages without a human looking at them
+pub fn is_link_preview(ua: &str) -> bool {
+    const MARKERS: &[&str] = &["bot", "facebookexternalhit", "whatsapp", "skypeuripreview", "embedly", "iframely", "crawler", "spider", "preview"];
+    let ua = ua.to_ascii_lowercase();
+    MARKERS.iter().any(|m| ua.contains(m))
+}
+
+// Anything that isn't a person in a browser, including scripted HTTP clients
+pub fn is_bot(ua: &str) -> bool {
+    const TOOLS: &[&str] = &["curl/", "wget/", "python-requests", "go-http-client"];
+    let lower = ua.to_ascii_lowercase();
+    ua.trim().is_empty() || is_link_preview(ua) || TOOLS.iter().any(|m| lower.contains(m))
+}
+
+// Coarse browser/client family; order matters because most UAs claim to be several browsers
+pub fn ua_family(ua: &str) -> &'static str {
+    const FAMILIES: &[(&str, &str)] = &[
+        ("discordbot", "Discord"),
+        ("telegrambot", "Telegram"),
+        ("slackbot", "Slack"),
+        ("twitterbot", "Twitter"),
+        ("facebookexternalhit", "Facebook"),
+        ("whatsapp", "WhatsApp"),
+        ("linkedinbot", "LinkedIn"),
+        ("googlebot", "Googlebot"),
+        ("bingbot", "Bingbot"),
+        ("curl/", "curl"),
+        ("wget/", "Wget"),
+        ("python-requests", "Python"),
+        ("edg/", "Edge"),
+        ("opr/", "Opera"),
+        ("samsungbrowser", "Samsung Internet"),
+        ("firefox/", "Firefox"),
+        ("fxios/", "Firefox"),
+        ("crios/", "Chrome"),
+        ("chrome/", "Chrome"),
+        ("safari/", "Safari"),
+    ];
+    let ua = ua.to_ascii_lowercase();
+    FAMILIES.iter().find(|(marker, _)| ua.contains(marker)).map(|(_, family)| *family).unwrap_or(if is_bot(&ua) { "Other bot" } else { "Other" })
+}
+
+// Offline IP geolocation from a MaxMind GeoLite2/GeoIP2 or DB-IP database (country or city edition)
+pub struct GeoIp(maxminddb::Reader<Vec<u8>>);
+
+impl GeoIp {
+    pub fn from_env() -> anyhow::Result<Option<GeoIp>> {
+        let Some(path) = std::env::var("GEOIP_DB_PATH").ok().filter(|p| !p.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let reader = maxminddb::Reader::open_readfile(&path)
+            .map_err(|e| anyhow::anyhow!("GEOIP_DB_PATH {}: {}", path, e))?;
+        tracing::info!("GeoIP database: {} ({})", path, reader.metadata.database_type);
+        Ok(Some(GeoIp(reader)))
+    }
+
+    // (ISO country code, region name); both None for private or unknown addresses
+    fn lookup(&self, ip: IpAddr) -> (Option<String>, Option<String>) {
+        let city: geoip2::City = match self.0.lookup(ip) {
+            Ok(city) => city,
+            Err(MaxMindDBError::AddressNotFoundError(_)) => return (None, None),
+            Err(e) => { tracing::warn!("geoip lookup: {}", e); return (None, None); }
+        };
+        let country = city.country.and_then(|c| c.iso_code).map(str::to_string);
+        let region = city.subdivisions.and_then(|subs| subs.into_iter().next()).and_then(|sub| {
+            sub.names.as_ref().and_then(|names| names.get("en").copied()).or(sub.iso_code).map(str::to_string)
+        });
+        (country, region)
+    }
+}
+
+fn referrer_host(headers: &HeaderMap) -> Option<String> {
+    let referer = headers.get(header::REFERER)?.to_str().ok()?;
+    url::Url::parse(referer).ok()?.host_str().map(|h| h.to_ascii_lowercase())
+}
+
+// The lock only guards the cached copy: it is never held across the database write, which may have to
+// wait for other writers. A click from just before midnight that gets here after the rotation is hashed
+// with the new salt, since the old one is gone.
+fn daily_salt(conn: &mut Connection, day: i64) -> rusqlite::Result<Vec<u8>> {
+    if let Some((d, salt)) = SALT.lock().unwrap().as_ref()
+        && *d >= day {
+            return Ok(salt.clone());
+        }
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let stored: Option<String> = tx
+        .query_row("SELECT value FROM settings WHERE key = 'click_salt'", [], |r| r.get(0))
+        .optional()?;
+    let (day, salt) = match stored.as_deref().and_then(|v| v.split_once(':')).and_then(|(d, s)| Some((d.parse::<i64>().ok()?, s))) {
+        Some((d, hex_salt)) if d >= day => (d, hex::decode(hex_salt).unwrap_or_default()),
+        _ => {
+            // Overwriting yesterday's salt is what makes old hashes unlinkable
+            let salt: [u8; 32] = rand::random();
+            tx.execute(
+                "INSERT INTO settings (key, value) VALUES ('click_salt', ?1) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![format!("{}:{}", day, hex::encode(salt))],
+            )?;
+            (day, salt.to_vec())
+        }
+    };
+    tx.commit()?;
+    let mut cached = SALT.lock().unwrap();
+    if cached.as_ref().is_none_or(|(d, _)| *d <= day) {
+        *cached = Some((day, salt.clone()));
+    }
+    Ok(salt)
+}
+
+pub fn record_click(conn: &mut Connection, geoip: Option<&GeoIp>, code: &str, headers: &HeaderMap, ip: Option<IpAddr>) -> rusqlite::Result<()> {
+    let ts = now_secs();
+    let ua = headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok()).unwrap_or("");
+    let ip_hash = match ip {
+        Some(ip) => {
+            let salt = daily_salt(conn, ts / DAY_SECS)?;
+            let mut hasher = Sha256::new();
+            hasher.update(&salt);
+            hasher.update(ip.to_string().as_bytes());
+            Some(hex::encode(&hasher.finalize()[..16]))
+        }
+        None => None,
+    };
+    let (country, region) = match (geoip, ip) {
+        (Some(geoip), Some(ip)) => geoip.lookup(ip),
+        _ => (None, None),
+    };
+    conn.execute(
+        "INSERT INTO clicks (ts, code, referrer_host, ua_family, is_bot, ip_hash, country, region) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![ts, code, referrer_host(headers), ua_family(ua), is_bot(ua), ip_hash, country, region],
+    )?;
+    Ok(())
+}
+
+pub struct ItemStats {
+    pub total: i64,
+    pub bots: i64,
+    pub unique_visitors: i64,
+    pub referrers: Vec<(String, i64)>,
+    pub families: Vec<(String, i64)>,
+    pub countries: Vec<(String, i64)>,
+    pub regions: Vec<(String, i64)>,
+    // (unix day, clicks) for the last `days` days, oldest first, including empty days
+    pub daily: Vec<(i64, i64)>,
+}
+
+pub fn item_stats(conn: &Connection, code: &str, days: i64) -> rusqlite::Result<ItemStats> {
+    let (total, bots): (i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(is_bot), 0) FROM clicks WHERE code = ?1",
+        params![code],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+    // Salts change daily, so this counts each visitor once per day they visited
+    let unique_visitors: i64 = conn.query_row(
+        "SELECT COUNT(DISTINCT ip_hash) FROM clicks WHERE code = ?1 AND is_bot = 0 AND ip_hash IS NOT NULL",
+        params![code],
+        |r| r.get(0),
+    )?;
+    let top = |sql: &str| -> rusqlite::Result<Vec<(String, i64)>> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params![code], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        rows.collect()
+    };
+    let referrers = top("SELECT COALESCE(referrer_host, '(direct)'), COUNT(*) AS n FROM clicks WHERE code = ?1 AND is_bot = 0 GROUP BY 1 ORDER BY n DESC LIMIT 10")?;
+    let families = top("SELECT ua_family, COUNT(*) AS n FROM clicks WHERE code = ?1 GROUP BY 1 ORDER BY n DESC LIMIT 10")?;
+    let countries = top("SELECT COALESCE(country, '(unknown)'), COUNT(*) AS n FROM clicks WHERE code = ?1 AND is_bot = 0 GROUP BY 1 ORDER BY n DESC")?;
+    let regions = top("SELECT country || ' / ' || region, COUNT(*) AS n FROM clicks WHERE code = ?1 AND is_bot = 0 AND country IS NOT NULL AND region IS NOT NULL GROUP BY 1 ORDER BY n DESC LIMIT 20")?;
+
+    let today = now_secs() / DAY_SECS;
+    let first = today - days + 1;
+    let mut daily: Vec<(i64, i64)> = (first..=today).map(|d| (d, 0)).collect();
+    let mut stmt = conn.prepare("SELECT ts / ?2 AS day, COUNT(*) FROM clicks WHERE code = ?1 AND is_bot = 0 AND ts >= ?3 GROUP BY day")?;
+    let rows = stmt.query_map(params![code, DAY_SECS, first * DAY_SECS], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, i64>(1)?)))?;
+    for (day, count) in rows.flatten() {
+        if let Some(slot) = daily.get_mut((day - first) as usize) {
+            slot.1 = count;
+        }
+    }
+    Ok(ItemStats { total, bots, unique_visitors, referrers, families, countries, regions, daily })
+}
+
+// "YYYY-MM-DD" for a unix day number (days since 1970-01-01, proleptic Gregorian calendar)
+pub fn day_label(day: i64) -> String {
+    let z = day + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn day_labels() {
+        for (day, label) in [
+            (-1, "1969-12-31"),
+            (0, "1970-01-01"),
+            (30, "1970-01-31"),
+            (31, "1970-02-01"),
+            (58, "1970-02-28"),
+            (59, "1970-03-01"),
+            (10_956, "1999-12-31"),
+            (10_957, "2000-01-01"),
+            // 2000 is a leap year (divisible by 400), 1900 and 2100 are not
+            (11_015, "2000-02-28"),
+            (11_016, "2000-02-29"),
+            (11_017, "2000-03-01"),
+            (-25_509, "1900-02-28"),
+            (-25_508, "1900-03-01"),
+            (47_540, "2100-02-28"),
+            (47_541, "2100-03-01"),
+            (19_416, "2023-02-28"),
+            (19_417, "2023-03-01"),
+            (19_781, "2024-02-28"),
+            (19_782, "2024-02-29"),
+            (19_783, "2024-03-01"),
+            (19_843, "2024-04-30"),
+            (19_844, "2024-05-01"),
+            (20_088, "2024-12-31"),
+            (20_089, "2025-01-01"),
+            (-719_162, "0001-01-01"),
+            (2_932_896, "9999-12-31"),
+        ] {
+            assert_eq!(day_label(day), label, "day {}", day);
+        }
+    }
+
+    // The only test touching SALT, which is shared by the whole process
+    #[test]
+    fn salt_rotation() {
+        let path = std::env::temp_dir().join(format!("ping0-analytics-{}.db", uuid::Uuid::new_v4()));
+        let mut conn = Connection::open(&path).unwrap();
+        crate::migrations::run(&mut conn).unwrap();
+        let stored = |conn: &Connection| -> String {
+            conn.query_row("SELECT value FROM settings WHERE key = 'click_salt'", [], |r| r.get(0)).unwrap()
+        };
+
+        let first = daily_salt(&mut conn, 20_000).unwrap();
+        assert_eq!(first.len(), 32);
+        assert_eq!(daily_salt(&mut conn, 20_000).unwrap(), first);
+        assert_eq!(stored(&conn), format!("20000:{}", hex::encode(&first)));
+
+        // A restart finds today's salt in the database
+        *SALT.lock().unwrap() = None;
+        assert_eq!(daily_salt(&mut conn, 20_000).unwrap(), first);
+
+        let second = daily_salt(&mut conn, 20_001).unwrap();
+        assert_ne!(second, first);
+        assert_eq!(stored(&conn), format!("20001:{}", hex::encode(&second)));
+        // A late click from the day before doesn't bring back a salt for it
+        assert_eq!(daily_salt(&mut conn, 20_000).unwrap(), second);
+        *SALT.lock().unwrap() = None;
+        assert_eq!(daily_salt(&mut conn, 20_000).unwrap(), second);
+        assert_eq!(stored(&conn), format!("20001:{}", hex::encode(&second)));
+
+        // Rotating waits for other writers without holding the lock, so other clicks aren't stuck behind it
+        let mut writer = Connection::open(&path).unwrap();
+        let busy = writer.transaction_with_behavior(TransactionBehavior::Immediate).unwrap();
+        let rotation = std::thread::spawn(move || {
+            conn.busy_timeout(Duration::from_secs(10)).unwrap();
+            daily_salt(&mut conn, 20_002)
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!rotation.is_finished());
+        assert!(SALT.try_lock().is_ok());
+        busy.commit().unwrap();
+        let third = rotation.join().unwrap().unwrap();
+        assert_ne!(third, second);
+        assert_eq!(SALT.lock().unwrap().as_ref().map(|(d, _)| *d), Some(20_002));
+        drop(writer);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,821 @@
+// Versioned JSON API: /api/v1/items
+//
+// Every error uses the same envelope, `{"error": {"code": "...", "message": "..."}}`, where `code`
+// is one of the stable identifiers below and `message` is for humans.
+// Callers authenticate with an API key (`Authorization: Bearer`) or the admin session cookie.
+
+use axum::extract::multipart::Field;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{FromRequest, Multipart, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use mime_guess::from_path as mime_from_path;
+use rusqlite::{params, OptionalExtension, ToSql};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::io;
+use std::path::Path as StdPath;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::apikeys::{self, AuthError, Caller, Scope};
+use crate::csrf;
+use crate::db::DbError;
+use crate::handlers::{
+    delete_item_files, delete_item_row, is_allowed_extension, qr_data_url, save_item, store_field, AppState,
+    InsertError, ItemOptions, MAX_FILE_SIZE,
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 200;
+
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorEnvelope<'a> {
+    error: ErrorBody<'a>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody<'a> {
+    // Stable identifier such as `not_found` or `alias_taken`
+    #[schema(example = "not_found")]
+    code: &'a str,
+    message: &'a str,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError { status, code, message: message.into() }
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_request", message)
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", "Item not found")
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "unauthorized", "Authentication required")
+    }
+
+    pub fn internal(context: &str, e: impl std::fmt::Display) -> Self {
+        tracing::error!("{}: {}", context, e);
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "Internal server error")
+    }
+
+    // 503 `unavailable` when the database is busy, `internal_error` otherwise
+    pub fn database(context: &str, e: impl Into<DbError>) -> Self {
+        let e = e.into();
+        if e.status() == StatusCode::SERVICE_UNAVAILABLE {
+            tracing::warn!("{}: {}", context, e);
+            return Self::new(StatusCode::SERVICE_UNAVAILABLE, "unavailable", e.public_message());
+        }
+        Self::internal(context, e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorEnvelope { error: ErrorBody { code: self.code, message: &self.message } };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<InsertError> for ApiError {
+    fn from(e: InsertError) -> Self {
+        match e {
+            InsertError::AliasTaken(_) => ApiError::new(StatusCode::CONFLICT, "alias_taken", e.to_string()),
+            InsertError::Db(e) => ApiError::database("insert item", e),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> Self {
+        ApiError::invalid_request(e.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(e: QueryRejection) -> Self {
+        ApiError::invalid_request(e.body_text())
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::InvalidKey => ApiError::new(StatusCode::UNAUTHORIZED, "invalid_api_key", "Unknown or revoked API key"),
+            AuthError::Db(e) => ApiError::database("authenticate", e),
+        }
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+fn scope_error(caller: &Caller, scope: Scope) -> ApiError {
+    match caller {
+        Caller::Anonymous => ApiError::unauthorized(),
+        _ => ApiError::new(StatusCode::FORBIDDEN, "insufficient_scope", format!("API key lacks the '{}' scope", scope.as_str())),
+    }
+}
+
+async fn authorize(state: &AppState, headers: &HeaderMap, scope: Scope) -> ApiResult<Caller> {
+    let caller = apikeys::identify(state, headers).await?;
+    if caller.allows(scope) { Ok(caller) } else { Err(scope_error(&caller, scope)) }
+}
+
+// Another site can make a browser send the session cookie, but can't know the admin panel's CSRF token,
+// so sessions need it to change anything. API keys are never sent by the browser on their own.
+fn check_csrf(state: &AppState, caller: &Caller, headers: &HeaderMap) -> ApiResult<()> {
+    if matches!(caller, Caller::Session(_)) && !csrf::header_valid(&state.cookie_secret, headers) {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "csrf_failed",
+            "Requests authenticated by the session cookie must send the X-CSRF-Token header of the admin panel",
+        ));
+    }
+    Ok(())
+}
+
+// `authorize` for requests that change something
+async fn authorize_change(state: &AppState, headers: &HeaderMap, scope: Scope) -> ApiResult<Caller> {
+    let caller = authorize(state, headers, scope).await?;
+    check_csrf(state, &caller, headers)?;
+    Ok(caller)
+}
+
+fn validate_url(url: &str) -> ApiResult<()> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Ok(())
+    } else {
+        Err(ApiError::new(StatusCode::BAD_REQUEST, "invalid_url", "URL must start with http:// or https://"))
+    }
+}
+
+// ---------- Response types ----------
+
+#[derive(Serialize, ToSchema)]
+pub struct ItemResponse {
+    pub code: String,
+    pub kind: String,
+    pub short_url: String,
+    // Target of a link item
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<FileResponse>,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub max_views: Option<i64>,
+    pub view_count: i64,
+    pub burn_after_reading: bool,
+    pub password_protected: bool,
+    // API key that created the item
+    pub api_key_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qr_code_data: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FileResponse {
+    pub key: String,
+    pub url: String,
+    pub mime: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ItemListResponse {
+    pub items: Vec<ItemResponse>,
+    pub total: i64,
+    pub limit: u32,
+    pub offset: u32,
+    pub next_offset: Option<u32>,
+}
+
+const ITEM_COLUMNS: &str = "code, kind, value, created_at, expires_at, max_views, view_count, burn_after_reading, password_hash IS NOT NULL, api_key_id";
+
+fn item_from_row(base_url: &str, r: &rusqlite::Row<'_>) -> rusqlite::Result<ItemResponse> {
+    let code: String = r.get(0)?;
+    let kind: String = r.get(1)?;
+    let value: String = r.get(2)?;
+    let (url, file) = match value.strip_prefix("file:") {
+        Some(key) if kind == "file" => (None, Some(FileResponse {
+            key: key.to_string(),
+            url: format!("{}/files/{}", base_url, key),
+            mime: mime_from_path(key).first_or_octet_stream().to_string(),
+        })),
+        _ => (Some(value), None),
+    };
+    Ok(ItemResponse {
+        short_url: format!("{}/s/{}", base_url, code),
+        code,
+        kind,
+        url,
+        file,
+        created_at: r.get(3)?,
+        expires_at: r.get(4)?,
+        max_views: r.get(5)?,
+        view_count: r.get(6)?,
+        burn_after_reading: r.get(7)?,
+        password_protected: r.get(8)?,
+        api_key_id: r.get(9)?,
+        qr_code_data: None,
+    })
+}
+
+// Items of other keys are reported as missing to callers restricted to their own items
+async fn load_item(state: &AppState, caller: &Caller, code: &str) -> ApiResult<ItemResponse> {
+    let (base_url, code, owner) = (state.base_url.clone(), code.to_string(), caller.item_owner_filter());
+    state.db.query(move |conn| {
+        let sql = format!("SELECT {} FROM items WHERE code = ?1 AND (?2 IS NULL OR api_key_id = ?2)", ITEM_COLUMNS);
+        conn.query_row(&sql, params![code, owner], |r| item_from_row(&base_url, r)).optional()
+    })
+    .await
+    .map_err(|e| ApiError::database("load item", e))?
+    .ok_or_else(ApiError::not_found)
+}
+
+// ---------- Create ----------
+
+// JSON body for creating a link item; files are created with a multipart body instead
+#[derive(Deserialize, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateItemRequest {
+    pub url: String,
+    pub alias: Option<String>,
+    pub expires_in: Option<String>,
+    pub max_views: Option<i64>,
+    #[serde(default)]
+    pub burn_after_reading: bool,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub qr: bool,
+}
+
+async fn options_from_request(req: &CreateItemRequest) -> ApiResult<ItemOptions> {
+    let mut opts = ItemOptions::default();
+    let fields = [
+        ("alias", req.alias.clone()),
+        ("expires_in", req.expires_in.clone()),
+        ("max_views", req.max_views.map(|n| n.to_string())),
+        ("burn_after_reading", Some(req.burn_after_reading.to_string())),
+        ("password", req.password.clone()),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            opts.apply_field(name, &value).await.map_err(ApiError::invalid_request)?;
+        }
+    }
+    Ok(opts)
+}
+
+// POST /api/v1/items — JSON for links, multipart (`file` plus the same option fields) for files
+#[utoipa::path(
+    post,
+    path = "/api/v1/items",
+    tag = "items",
+    request_body = CreateItemRequest,
+    responses(
+        (status = 201, description = "Item created", body = ItemResponse),
+        (status = 400, description = "Invalid URL, option or file type", body = ErrorEnvelope),
+        (status = 401, description = "Authentication required", body = ErrorEnvelope),
+        (status = 403, description = "API key lacks the create scope", body = ErrorEnvelope),
+        (status = 409, description = "Alias already taken", body = ErrorEnvelope),
+        (status = 413, description = "File too large, or larger than the hourly upload quota", body = ErrorEnvelope),
+        (status = 429, description = "Rate limit exceeded; see `Retry-After`", body = ErrorEnvelope),
+    ),
+    security((), ("bearer" = []), ("session" = []))
+)]
+pub async fn create_item(State(state): State<AppState>, req: Request) -> ApiResult<(StatusCode, Json<ItemResponse>)> {
+    let caller = apikeys::identify(&state, req.headers()).await?;
+    check_csrf(&state, &caller, req.headers())?;
+    if !apikeys::may_create(&caller) {
+        return Err(scope_error(&caller, Scope::Create));
+    }
+    let is_multipart = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("multipart/form-data"));
+    let (code, qr) = if is_multipart {
+        let multipart = Multipart::from_request(req, &state).await.map_err(|e| ApiError::invalid_request(e.body_text()))?;
+        create_file_item(&state, &caller, multipart).await?
+    } else {
+        let Json(body) = Json::<CreateItemRequest>::from_request(req, &state).await?;
+        validate_url(&body.url)?;
+        let mut opts = options_from_request(&body).await?;
+        opts.api_key_id = caller.api_key_id();
+        (save_item(&state, "url", body.url, opts).await?, body.qr)
+    };
+    let mut item = load_item(&state, &caller, &code).await?;
+    if qr {
+        item.qr_code_data = qr_data_url(&state.base_url, &item.short_url);
+    }
+    Ok((StatusCode::CREATED, Json(item)))
+}
+
+async fn save_file_field(state: &AppState, field: Field<'_>) -> ApiResult<String> {
+    let filename = field.file_name().unwrap_or("file").to_string();
+    let ext = StdPath::new(&filename).extension().and_then(|e| e.to_str()).unwrap_or("bin").to_string();
+    if !is_allowed_extension(&ext) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "unsupported_file_type", format!("File type '.{}' not allowed", ext)));
+    }
+    let key = format!("{}.{}", Uuid::new_v4(), ext);
+    match store_field(state.storage.as_ref(), &key, field).await {
+        Ok(_) => Ok(key),
+        Err(e) if e.kind() == io::ErrorKind::FileTooLarge => Err(ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "file_too_large",
+            format!("File too large. Max size: {}MB", MAX_FILE_SIZE / 1024 / 1024),
+        )),
+        Err(e) => Err(ApiError::internal("store upload", e)),
+    }
+}
+
+async fn create_file_item(state: &AppState, caller: &Caller, mut multipart: Multipart) -> ApiResult<(String, bool)> {
+    let mut saved: Option<String> = None;
+    let mut opts = ItemOptions { api_key_id: caller.api_key_id(), ..Default::default() };
+    let mut qr = false;
+    let mut error: Option<ApiError> = None;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => { error = Some(ApiError::invalid_request(e.body_text())); break; }
+        };
+        let name = field.name().unwrap_or("").to_string();
+        match name.as_str() {
+            "file" if saved.is_none() => match save_file_field(state, field).await {
+                Ok(key) => saved = Some(key),
+                Err(e) => { error = Some(e); break; }
+            },
+            "qr" => qr = field.text().await.is_ok_and(|v| matches!(v.trim(), "true" | "on" | "1")),
+            n if ItemOptions::is_option_field(n) => {
+                let text = field.text().await.unwrap_or_default();
+                if let Err(e) = opts.apply_field(n, &text).await { error = Some(ApiError::invalid_request(e)); break; }
+            }
+            _ => {}
+        }
+    }
+    let Some(key) = saved else {
+        return Err(error.unwrap_or_else(|| ApiError::invalid_request("Missing 'file' field")));
+    };
+    let inserted = match error {
+        Some(e) => Err(e),
+        None => save_item(state, "file", format!("file:{}", key), opts).await.map_err(ApiError::from),
+    };
+    match inserted {
+        Ok(code) => Ok((code, qr)),
+        Err(e) => {
+            let _ = state.storage.delete(&key).await;
+            Err(e)
+        }
+    }
+}
+
+// ---------- Read ----------
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/items/{code}",
+    tag = "items",
+    params(("code" = String, Path, description = "Short code or alias")),
+    responses(
+        (status = 200, description = "The item", body = ItemResponse),
+        (status = 401, description = "Authentication required", body = ErrorEnvelope),
+        (status = 403, description = "API key lacks the read scope", body = ErrorEnvelope),
+        (status = 404, description = "No such item", body = ErrorEnvelope),
+    ),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn get_item(State(state): State<AppState>, Path(code): Path<String>, headers: HeaderMap) -> ApiResult<Json<ItemResponse>> {
+    let caller = authorize(&state, &headers, Scope::Read).await?;
+    load_item(&state, &caller, &code).await.map(Json)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListQuery {
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    // 'url' or 'file'
+    pub kind: Option<String>,
+    // Substring of the code or target
+    pub q: Option<String>,
+    // Only items that have (true) or haven't (false) expired
+    pub expired: Option<bool>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/items",
+    tag = "items",
+    params(ListQuery),
+    responses(
+        (status = 200, description = "A page of items, newest first", body = ItemListResponse),
+        (status = 400, description = "Invalid query", body = ErrorEnvelope),
+        (status = 401, description = "Authentication required", body = ErrorEnvelope),
+        (status = 403, description = "API key lacks the read scope", body = ErrorEnvelope),
+    ),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn list_items(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    query: Result<Query<ListQuery>, QueryRejection>,
+) -> ApiResult<Json<ItemListResponse>> {
+    let caller = authorize(&state, &headers, Scope::Read).await?;
+    let Query(query) = query?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0);
+
+    let mut filters: Vec<&str> = Vec::new();
+    let mut args: Vec<Box<dyn ToSql + Send>> = Vec::new();
+    if let Some(key_id) = caller.item_owner_filter() {
+        filters.push("api_key_id = ?");
+        args.push(Box::new(key_id));
+    }
+    if let Some(kind) = query.kind {
+        if kind != "url" && kind != "file" {
+            return Err(ApiError::invalid_request("kind must be 'url' or 'file'"));
+        }
+        filters.push("kind = ?");
+        args.push(Box::new(kind));
+    }
+    if let Some(q) = query.q.filter(|q| !q.is_empty()) {
+        filters.push("(instr(code, ?) > 0 OR instr(value, ?) > 0)");
+        args.push(Box::new(q.clone()));
+        args.push(Box::new(q));
+    }
+    match query.expired {
+        Some(true) => filters.push("(expires_at IS NOT NULL AND expires_at <= strftime('%s','now'))"),
+        Some(false) => filters.push("(expires_at IS NULL OR expires_at > strftime('%s','now'))"),
+        None => {}
+    }
+    let where_sql = if filters.is_empty() { String::new() } else { format!("WHERE {}", filters.join(" AND ")) };
+
+    let base_url = state.base_url.clone();
+    let (total, items) = state.db.query(move |conn| {
+        let arg_refs: Vec<&dyn ToSql> = args.iter().map(|a| a.as_ref() as &dyn ToSql).collect();
+        let total: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM items {}", where_sql), arg_refs.as_slice(), |r| r.get(0))?;
+        let sql = format!("SELECT {} FROM items {} ORDER BY created_at DESC, code LIMIT {} OFFSET {}", ITEM_COLUMNS, where_sql, limit, offset);
+        let items = conn
+            .prepare(&sql)
+            .and_then(|mut stmt| stmt.query_map(arg_refs.as_slice(), |r| item_from_row(&base_url, r))?.collect::<rusqlite::Result<Vec<_>>>())?;
+        Ok((total, items))
+    })
+    .await
+    .map_err(|e| ApiError::database("list items", e))?;
+    let next_offset = (i64::from(offset) + items.len() as i64) < total;
+    Ok(Json(ItemListResponse {
+        next_offset: next_offset.then(|| offset + items.len() as u32),
+        items,
+        total,
+        limit,
+        offset,
+    }))
+}
+
+// ---------- Update ----------
+
+// Distinguishes a missing field (leave unchanged) from an explicit null (clear)
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateItemRequest {
+    // New target; link items only
+    pub url: Option<String>,
+    // Duration from now ("1h", "7d", ...) or "never"
+    pub expires_in: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<i64>)]
+    pub max_views: Option<Option<i64>>,
+    // Turning it on also limits views to one unless the item already has a limit
+    pub burn_after_reading: Option<bool>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    pub password: Option<Option<String>>,
+}
+
+// Changing an item takes the same scope as creating one
+#[utoipa::path(
+    patch,
+    path = "/api/v1/items/{code}",
+    tag = "items",
+    params(("code" = String, Path, description = "Short code or alias")),
+    request_body = UpdateItemRequest,
+    responses(
+        (status = 200, description = "The updated item", body = ItemResponse),
+        (status = 400, description = "Invalid field", body = ErrorEnvelope),
+        (status = 401, description = "Authentication required", body = ErrorEnvelope),
+        (status = 403, description = "API key lacks the create scope", body = ErrorEnvelope),
+        (status = 404, description = "No such item", body = ErrorEnvelope),
+    ),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn update_item(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    headers: HeaderMap,
+    body: Result<Json<UpdateItemRequest>, JsonRejection>,
+) -> ApiResult<Json<ItemResponse>> {
+    let caller = authorize_change(&state, &headers, Scope::Create).await?;
+    let Json(body) = body?;
+    let item = load_item(&state, &caller, &code).await?;
+
+    let mut sets: Vec<&str> = Vec::new();
+    let mut args: Vec<Box<dyn ToSql + Send>> = Vec::new();
+    if let Some(url) = body.url {
+        if item.kind != "url" {
+            return Err(ApiError::invalid_request("Only link items have a url"));
+        }
+        validate_url(&url)?;
+        sets.push("value = ?");
+        args.push(Box::new(url));
+    }
+    if let Some(expires_in) = body.expires_in {
+        let mut opts = ItemOptions::default();
+        opts.apply_field("expires_in", &expires_in).await.map_err(ApiError::invalid_request)?;
+        sets.push("expires_at = ?");
+        args.push(Box::new(opts.expires_at));
+    }
+    if let Some(max_views) = body.max_views {
+        if max_views.is_some_and(|n| n <= 0) {
+            return Err(ApiError::invalid_request("max_views must be positive"));
+        }
+        sets.push("max_views = ?");
+        // Like on create, burning without a limit means after the first view
+        args.push(Box::new(if body.burn_after_reading == Some(true) { max_views.or(Some(1)) } else { max_views }));
+    } else if body.burn_after_reading == Some(true) {
+        sets.push("max_views = COALESCE(max_views, 1)");
+    }
+    if let Some(burn) = body.burn_after_reading {
+        sets.push("burn_after_reading = ?");
+        args.push(Box::new(burn));
+    }
+    if let Some(password) = body.password {
+        let hash = match password.filter(|p| !p.is_empty()) {
+            Some(p) => Some(crate::password::hash_password_async(p).await.map_err(|e| ApiError::internal("hash password", e))?),
+            None => None,
+        };
+        sets.push("password_hash = ?");
+        args.push(Box::new(hash));
+    }
+    if !sets.is_empty() {
+        args.push(Box::new(code.clone()));
+        let sql = format!("UPDATE items SET {} WHERE code = ?", sets.join(", "));
+        state.db.query(move |conn| {
+            let arg_refs: Vec<&dyn ToSql> = args.iter().map(|a| a.as_ref() as &dyn ToSql).collect();
+            conn.execute(&sql, arg_refs.as_slice())
+        })
+        .await
+        .map_err(|e| ApiError::database("update item", e))?;
+    }
+    load_item(&state, &caller, &code).await.map(Json)
+}
+
+// ---------- Delete ----------
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/items/{code}",
+    tag = "items",
+    params(("code" = String, Path, description = "Short code or alias")),
+    responses(
+        (status = 204, description = "Item and its file deleted"),
+        (status = 401, description = "Authentication required", body = ErrorEnvelope),
+        (status = 403, description = "API key lacks the delete scope", body = ErrorEnvelope),
+        (status = 404, description = "No such item", body = ErrorEnvelope),
+    ),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn delete_item(State(state): State<AppState>, Path(code): Path<String>, headers: HeaderMap) -> ApiResult<StatusCode> {
+    let caller = authorize_change(&state, &headers, Scope::Delete).await?;
+    let item = load_item(&state, &caller, &code).await?;
+    let value = match &item.file {
+        Some(file) => format!("file:{}", file.key),
+        None => item.url.clone().unwrap_or_default(),
+    };
+    delete_item_files(state.storage.as_ref(), &item.kind, &value).await;
+    state.db.query(move |conn| delete_item_row(conn, &code))
+        .await
+        .map_err(|e| ApiError::database("delete item", e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ---------- Stats ----------
+
+#[derive(Serialize, ToSchema)]
+pub struct CountResponse {
+    pub name: String,
+    pub clicks: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DayResponse {
+    pub day: String,
+    pub clicks: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StatsResponse {
+    pub code: String,
+    pub total: i64,
+    pub bots: i64,
+    pub unique_visitors: i64,
+    pub referrers: Vec<CountResponse>,
+    pub clients: Vec<CountResponse>,
+    pub countries: Vec<CountResponse>,
+    pub regions: Vec<CountResponse>,
+    pub daily: Vec<DayResponse>,
+}
+
+// Days included in the daily click histogram
+const STATS_DAYS: i64 = 30;
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/items/{code}/stats",
+    tag = "items",
+    params(("code" = String, Path, description = "Short code or alias")),
+    responses(
+        (status = 200, description = "Click statistics", body = StatsResponse),
+        (status = 401, description = "Authentication required", body = ErrorEnvelope),
+        (status = 403, description = "API key lacks the read scope", body = ErrorEnvelope),
+        (status = 404, description = "No such item", body = ErrorEnvelope),
+    ),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn item_stats(State(state): State<AppState>, Path(code): Path<String>, headers: HeaderMap) -> ApiResult<Json<StatsResponse>> {
+    let caller = authorize(&state, &headers, Scope::Read).await?;
+    load_item(&state, &caller, &code).await?;
+    let lookup = code.clone();
+    let stats = state.db.query(move |conn| crate::analytics::item_stats(conn, &lookup, STATS_DAYS))
+        .await
+        .map_err(|e| ApiError::database("item stats", e))?;
+    let counts = |v: Vec<(String, i64)>| v.into_iter().map(|(name, clicks)| CountResponse { name, clicks }).collect();
+    Ok(Json(StatsResponse {
+        code,
+        total: stats.total,
+        bots: stats.bots,
+        unique_visitors: stats.unique_visitors,
+        referrers: counts(stats.referrers),
+        clients: counts(stats.families),
+        countries: counts(stats.countries),
+        regions: counts(stats.regions),
+        daily: stats.daily.into_iter().map(|(day, clicks)| DayResponse { day: crate::analytics::day_label(day), clicks }).collect(),
+    }))
+}
+
+// ---------- API keys (admin scope) ----------
+
+#[derive(Serialize, ToSchema)]
+pub struct KeyResponse {
+    pub id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    pub item_count: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedKeyResponse {
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<String>,
+    // Only returned once, at creation
+    pub token: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RevokeKeyQuery {
+    // Also delete every item created with the key
+    #[serde(default)]
+    pub delete_items: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/keys",
+    tag = "keys",
+    responses(
+        (status = 200, description = "All API keys, including revoked ones", body = [KeyResponse]),
+        (status = 401, description = "Authentication required", body = ErrorEnvelope),
+        (status = 403, description = "API key lacks the admin scope", body = ErrorEnvelope),
+    ),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn list_keys(State(state): State<AppState>, headers: HeaderMap) -> ApiResult<Json<Vec<KeyResponse>>> {
+    authorize(&state, &headers, Scope::Admin).await?;
+    let keys = state.db.query(|conn| apikeys::list_keys(conn))
+        .await
+        .map_err(|e| ApiError::database("list keys", e))?;
+    Ok(Json(keys.into_iter().map(|k| KeyResponse {
+        id: k.id,
+        name: k.name,
+        prefix: k.prefix,
+        scopes: k.scopes.split(',').filter(|s| !s.is_empty()).map(str::to_string).collect(),
+        created_at: k.created_at,
+        last_used_at: k.last_used_at,
+        revoked_at: k.revoked_at,
+        item_count: k.item_count,
+    }).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/keys",
+    tag = "keys",
+    request_body = CreateKeyRequest,
+    responses(
+        (status = 201, description = "Key created; the token is not shown again", body = CreatedKeyResponse),
+        (status = 400, description = "Missing name or unknown scope", body = ErrorEnvelope),
+        (status = 401, description = "Authentication required", body = ErrorEnvelope),
+        (status = 403, description = "API key lacks the admin scope", body = ErrorEnvelope),
+    ),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn create_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<Json<CreateKeyRequest>, JsonRejection>,
+) -> ApiResult<(StatusCode, Json<CreatedKeyResponse>)> {
+    authorize_change(&state, &headers, Scope::Admin).await?;
+    let Json(body) = body?;
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::invalid_request("name is required"));
+    }
+    let mut scopes = Vec::new();
+    for s in &body.scopes {
+        let scope = Scope::parse(s).ok_or_else(|| ApiError::invalid_request(format!("Unknown scope '{}'", s)))?;
+        if !scopes.contains(&scope) { scopes.push(scope); }
+    }
+    if scopes.is_empty() {
+        return Err(ApiError::invalid_request("At least one scope is required"));
+    }
+    let (key_name, key_scopes) = (name.to_string(), scopes.clone());
+    let (id, token) = state.db.query(move |conn| apikeys::create_key(conn, &key_name, &key_scopes))
+        .await
+        .map_err(|e| ApiError::database("create key", e))?;
+    Ok((StatusCode::CREATED, Json(CreatedKeyResponse {
+        id,
+        name: name.to_string(),
+        scopes: scopes.iter().map(|s| s.as_str().to_string()).collect(),
+        token,
+    })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/keys/{id}",
+    tag = "keys",
+    params(("id" = i64, Path, description = "Key id"), RevokeKeyQuery),
+    responses(
+        (status = 204, description = "Key revoked"),
+        (status = 401, description = "Authentication required", body = ErrorEnvelope),
+        (status = 403, description = "API key lacks the admin scope", body = ErrorEnvelope),
+        (status = 404, description = "No such key", body = ErrorEnvelope),
+    ),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn revoke_key(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    query: Result<Query<RevokeKeyQuery>, QueryRejection>,
+) -> ApiResult<StatusCode> {
+    authorize_change(&state, &headers, Scope::Admin).await?;
+    let Query(query) = query?;
+    match apikeys::revoke_key(&state, id, query.delete_items).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::new(StatusCode::NOT_FOUND, "not_found", "API key not found")),
+        Err(e) => Err(ApiError::database("revoke key", e)),
+    }
+}
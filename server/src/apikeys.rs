@@ -0,0 +1,236 @@
+// API keys for programmatic access, sent as `Authorization: Bearer <token>`.
+// Tokens are random and long, so a plain SHA-256 of the token is stored (never the token itself).
+
+use axum::http::{header, HeaderMap};
+use axum_extra::headers::{Cookie, HeaderMapExt};
+use rand::{distributions::Alphanumeric, Rng};
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+use crate::admins::{self, Role};
+use crate::db::DbError;
+use crate::handlers::{delete_item_files, delete_item_row, now_secs, AppState};
+
+const TOKEN_PREFIX: &str = "p0_";
+const TOKEN_LEN: usize = 40;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scope {
+    // Create items
+    Create,
+    // Read items (and their stats)
+    Read,
+    // Delete items
+    Delete,
+    // Everything, on all items, including managing keys
+    Admin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [Scope::Create, Scope::Read, Scope::Delete, Scope::Admin];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Create => "create",
+            Scope::Read => "read",
+            Scope::Delete => "delete",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Scope> {
+        Scope::ALL.into_iter().find(|scope| scope.as_str() == s.trim())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub id: i64,
+    pub scopes: Vec<Scope>,
+}
+
+// Who is making a request
+pub enum Caller {
+    // Logged-in admin user (browser session); what it may do depends on the user's role
+    Session(Role),
+    Key(ApiKey),
+    Anonymous,
+}
+
+impl Caller {
+    pub fn allows(&self, scope: Scope) -> bool {
+        match self {
+            Caller::Session(role) => role.allows(scope),
+            Caller::Key(key) => key.scopes.contains(&scope) || key.scopes.contains(&Scope::Admin),
+            Caller::Anonymous => false,
+        }
+    }
+
+    // Sessions and admin keys see every item; other keys only the items they created
+    pub fn item_owner_filter(&self) -> Option<i64> {
+        match self {
+            Caller::Key(key) if !key.scopes.contains(&Scope::Admin) => Some(key.id),
+            _ => None,
+        }
+    }
+
+    pub fn api_key_id(&self) -> Option<i64> {
+        match self {
+            Caller::Key(key) => Some(key.id),
+            _ => None,
+        }
+    }
+}
+
+pub enum AuthError {
+    // A bearer token was sent but doesn't match an active key
+    InvalidKey,
+    Db(DbError),
+}
+
+impl From<DbError> for AuthError {
+    fn from(e: DbError) -> Self {
+        AuthError::Db(e)
+    }
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn parse_scopes(s: &str) -> Vec<Scope> {
+    s.split(',').filter_map(Scope::parse).collect()
+}
+
+pub fn format_scopes(scopes: &[Scope]) -> String {
+    scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(",")
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+fn find_key(conn: &Connection, token: &str) -> rusqlite::Result<Option<ApiKey>> {
+    let key = conn
+        .query_row(
+            "SELECT id, scopes FROM api_keys WHERE key_hash = ?1 AND revoked_at IS NULL",
+            params![hash_token(token)],
+            |r| Ok(ApiKey { id: r.get(0)?, scopes: parse_scopes(&r.get::<_, String>(1)?) }),
+        )
+        .optional()?;
+    if let Some(key) = &key {
+        conn.execute("UPDATE api_keys SET last_used_at = ?1 WHERE id = ?2", params![now_secs(), key.id])?;
+    }
+    Ok(key)
+}
+
+// Id of the active key a request's bearer token belongs to, without marking the key as used
+pub fn bearer_key_id(conn: &Connection, headers: &HeaderMap) -> rusqlite::Result<Option<i64>> {
+    let Some(token) = bearer_token(headers) else { return Ok(None) };
+    conn.query_row(
+        "SELECT id FROM api_keys WHERE key_hash = ?1 AND revoked_at IS NULL",
+        params![hash_token(token)],
+        |r| r.get(0),
+    )
+    .optional()
+}
+
+// Identify the caller from a bearer token, falling back to the admin session cookie
+pub async fn identify(state: &AppState, headers: &HeaderMap) -> Result<Caller, AuthError> {
+    if let Some(token) = bearer_token(headers) {
+        let token = token.to_string();
+        return match state.db.query(move |conn| find_key(conn, &token)).await? {
+            Some(key) => Ok(Caller::Key(key)),
+            None => Err(AuthError::InvalidKey),
+        };
+    }
+    let Some(session) = headers.typed_get::<Cookie>().and_then(|c| c.get("ping0_admin").map(str::to_string)) else {
+        return Ok(Caller::Anonymous);
+    };
+    match state.db.call(move |conn| admins::session_user(conn, Some(&session))).await? {
+        Some(user) => Ok(Caller::Session(user.role)),
+        None => Ok(Caller::Anonymous),
+    }
+}
+
+// Anonymous uploads can be turned off with ALLOW_ANONYMOUS_UPLOADS=false
+pub fn anonymous_uploads_allowed() -> bool {
+    std::env::var("ALLOW_ANONYMOUS_UPLOADS").map(|v| !matches!(v.trim().to_ascii_lowercase().as_str(), "false" | "0" | "no" | "off")).unwrap_or(true)
+}
+
+pub fn may_create(caller: &Caller) -> bool {
+    caller.allows(Scope::Create) || (matches!(caller, Caller::Anonymous) && anonymous_uploads_allowed())
+}
+
+// Returns the new key's id and its token; the token is only ever shown once
+pub fn create_key(conn: &Connection, name: &str, scopes: &[Scope]) -> rusqlite::Result<(i64, String)> {
+    let random: String = rand::thread_rng().sample_iter(&Alphanumeric).take(TOKEN_LEN).map(char::from).collect();
+    let token = format!("{}{}", TOKEN_PREFIX, random);
+    conn.execute(
+        "INSERT INTO api_keys (name, prefix, key_hash, scopes, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![name, &token[..TOKEN_PREFIX.len() + 6], hash_token(&token), format_scopes(scopes), now_secs()],
+    )?;
+    Ok((conn.last_insert_rowid(), token))
+}
+
+pub struct KeyRow {
+    pub id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    pub item_count: i64,
+}
+
+pub fn list_keys(conn: &Connection) -> rusqlite::Result<Vec<KeyRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT k.id, k.name, k.prefix, k.scopes, k.created_at, k.last_used_at, k.revoked_at,
+                (SELECT COUNT(*) FROM items i WHERE i.api_key_id = k.id)
+         FROM api_keys k ORDER BY k.created_at DESC, k.id DESC",
+    )?;
+    let rows = stmt.query_map([], |r| Ok(KeyRow {
+        id: r.get(0)?,
+        name: r.get(1)?,
+        prefix: r.get(2)?,
+        scopes: r.get(3)?,
+        created_at: r.get(4)?,
+        last_used_at: r.get(5)?,
+        revoked_at: r.get(6)?,
+        item_count: r.get(7)?,
+    }))?;
+    rows.collect()
+}
+
+// Revoke a key, optionally deleting every item it created. Returns false if the key doesn't exist.
+pub async fn revoke_key(state: &AppState, id: i64, delete_items: bool) -> Result<bool, DbError> {
+    let revoked = state
+        .db
+        .query(move |conn| conn.execute("UPDATE api_keys SET revoked_at = COALESCE(revoked_at, ?1) WHERE id = ?2", params![now_secs(), id]))
+        .await?;
+    if revoked == 0 {
+        return Ok(false);
+    }
+    if delete_items {
+        let items: Vec<(String, String, String)> = state
+            .db
+            .query(move |conn| {
+                let mut stmt = conn.prepare("SELECT code, kind, value FROM items WHERE api_key_id = ?1")?;
+                let rows = stmt.query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?;
+                rows.collect()
+            })
+            .await?;
+        for (code, kind, value) in &items {
+            delete_item_files(state.storage.as_ref(), kind, value).await;
+            let code = code.clone();
+            state.db.query(move |conn| delete_item_row(conn, &code)).await?;
+        }
+        tracing::info!("Revoked API key {} and deleted its {} items", id, items.len());
+    } else {
+        tracing::info!("Revoked API key {}", id);
+    }
+    Ok(true)
+}
@@ -0,0 +1,259 @@
+// The client's IP address. Behind a reverse proxy the TCP peer is the proxy, so when the peer is listed in
+// TRUSTED_PROXIES (comma-separated CIDRs, e.g. `127.0.0.1/32,::1`) the address is taken from the one
+// forwarding header named by FORWARDED_HEADER: `X-Forwarded-For` (default) or `Forwarded`. The other is
+// never read, so a proxy only has to control the header it is configured for. `CF-Connecting-IP` comes
+// first, but only with TRUST_CF_CONNECTING_IP set. In a forwarding chain the client is the nearest hop that
+// isn't a trusted proxy. Forwarding headers from any other peer are ignored.
+
+use anyhow::Context;
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::handlers::AppState;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ForwardedHeader {
+    #[default]
+    XForwardedFor,
+    // RFC 7239
+    Forwarded,
+}
+
+#[derive(Default)]
+pub struct TrustedProxies {
+    nets: Vec<IpNet>,
+    header: ForwardedHeader,
+    // Only proxies that always set CF-Connecting-IP themselves (Cloudflare) make it trustworthy
+    cf_connecting_ip: bool,
+}
+
+impl TrustedProxies {
+    pub fn from_env() -> anyhow::Result<TrustedProxies> {
+        TrustedProxies::from_vars(|name| std::env::var(name).ok())
+    }
+
+    // from_env with the variables looked up through `var`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> anyhow::Result<TrustedProxies> {
+        let Some(list) = var("TRUSTED_PROXIES") else { return Ok(TrustedProxies::default()) };
+        let mut nets = Vec::new();
+        for entry in list.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            // A bare address is a network of one
+            let net = match entry.parse::<IpAddr>() {
+                Ok(ip) => IpNet::from(ip),
+                Err(_) => entry.parse().with_context(|| format!("TRUSTED_PROXIES: '{}' is not an IP address or CIDR", entry))?,
+            };
+            nets.push(net);
+        }
+        let header = match var("FORWARDED_HEADER").map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("") | Some("x-forwarded-for") => ForwardedHeader::XForwardedFor,
+            Some("forwarded") => ForwardedHeader::Forwarded,
+            Some(other) => anyhow::bail!("FORWARDED_HEADER: expected 'x-forwarded-for' or 'forwarded', got '{}'", other),
+        };
+        let cf_connecting_ip = var("TRUST_CF_CONNECTING_IP")
+            .is_some_and(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"));
+        Ok(TrustedProxies { nets, header, cf_connecting_ip })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.nets.iter().any(|net| net.contains(&ip))
+    }
+
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = peer.to_canonical();
+        if !self.contains(peer) {
+            return peer;
+        }
+        let header = |name: &str| headers.get_all(name).iter().filter_map(|v| v.to_str().ok()).collect::<Vec<_>>().join(",");
+        if self.cf_connecting_ip
+            && let Some(ip) = headers.get("cf-connecting-ip").and_then(|v| v.to_str().ok()).and_then(|v| parse_addr(v.trim())) {
+            return ip;
+        }
+        let chain: Vec<Option<IpAddr>> = match self.header {
+            ForwardedHeader::XForwardedFor => header("x-forwarded-for").split(',').map(|hop| parse_addr(hop.trim())).collect(),
+            ForwardedHeader::Forwarded => header("forwarded")
+                .split(',')
+                .map(|element| {
+                    element
+                        .split(';')
+                        .filter_map(|pair| pair.split_once('='))
+                        .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                        .and_then(|(_, value)| parse_addr(value.trim().trim_matches('"')))
+                })
+                .collect(),
+        };
+        self.nearest_untrusted(chain).unwrap_or(peer)
+    }
+
+    // Walks a forwarding chain (client first) from the end: the first hop that isn't a trusted proxy. Stops
+    // at an entry that isn't an address, and answers with the last trusted hop when every hop is trusted.
+    fn nearest_untrusted(&self, chain: Vec<Option<IpAddr>>) -> Option<IpAddr> {
+        let mut nearest = None;
+        for hop in chain.into_iter().rev() {
+            let Some(ip) = hop else { break };
+            nearest = Some(ip);
+            if !self.contains(ip) {
+                break;
+            }
+        }
+        nearest
+    }
+}
+
+// "1.2.3.4", "1.2.3.4:5678", "2001:db8::1", "[2001:db8::1]" or "[2001:db8::1]:5678"
+fn parse_addr(s: &str) -> Option<IpAddr> {
+    let unbracketed = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')).unwrap_or(s);
+    let ip = unbracketed.parse::<IpAddr>().ok().or_else(|| s.parse::<SocketAddr>().ok().map(|a| a.ip()))?;
+    Some(ip.to_canonical())
+}
+
+// Extractor for the client address, with trusted proxies resolved
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(ConnectInfo(peer)) = parts.extensions.get::<ConnectInfo<SocketAddr>>() else {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Client address unavailable"));
+        };
+        Ok(ClientIp(state.trusted_proxies.client_ip(peer.ip(), &parts.headers)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderName, HeaderValue};
+
+    fn proxies(vars: &[(&str, &str)]) -> TrustedProxies {
+        let vars: Vec<(String, String)> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        TrustedProxies::from_vars(|name| vars.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone())).unwrap()
+    }
+
+    fn resolve(proxies: &TrustedProxies, peer: &str, headers: &[(&str, &str)]) -> String {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(HeaderName::from_bytes(name.as_bytes()).unwrap(), HeaderValue::from_str(value).unwrap());
+        }
+        proxies.client_ip(peer.parse().unwrap(), &map).to_string()
+    }
+
+    const PROXY: &str = "10.0.0.1";
+
+    #[test]
+    fn x_forwarded_for() {
+        let p = proxies(&[("TRUSTED_PROXIES", "10.0.0.0/8, ::1")]);
+        let cases = [
+            ("198.51.100.4", "198.51.100.4"),
+            // Hops appended by trusted proxies are skipped
+            ("198.51.100.4, 10.0.0.7, 10.0.0.3", "198.51.100.4"),
+            // Whatever the client put in front of its own address is not believed
+            ("6.6.6.6, 198.51.100.4", "198.51.100.4"),
+            ("6.6.6.6, 198.51.100.4, 10.0.0.7", "198.51.100.4"),
+            // Every hop trusted: the one furthest away
+            ("10.0.0.9, 10.0.0.7", "10.0.0.9"),
+            ("198.51.100.4:5678", "198.51.100.4"),
+            ("2001:db8::1", "2001:db8::1"),
+            ("[2001:db8::1]", "2001:db8::1"),
+            ("[2001:db8::1]:5678", "2001:db8::1"),
+            ("::ffff:198.51.100.4", "198.51.100.4"),
+            // Garbage stops the walk at the last trusted hop, or leaves the peer
+            ("198.51.100.4, garbage, 10.0.0.7", "10.0.0.7"),
+            ("garbage", PROXY),
+            ("", PROXY),
+        ];
+        for (xff, expected) in cases {
+            assert_eq!(resolve(&p, PROXY, &[("x-forwarded-for", xff)]), expected, "X-Forwarded-For: {}", xff);
+        }
+        // Repeated headers form one chain
+        assert_eq!(resolve(&p, PROXY, &[("x-forwarded-for", "6.6.6.6"), ("x-forwarded-for", "198.51.100.4")]), "198.51.100.4");
+        assert_eq!(resolve(&p, "::1", &[("x-forwarded-for", "198.51.100.4")]), "198.51.100.4");
+    }
+
+    #[test]
+    fn forwarded() {
+        let p = proxies(&[("TRUSTED_PROXIES", "10.0.0.0/8,::1"), ("FORWARDED_HEADER", "Forwarded")]);
+        let cases = [
+            ("for=198.51.100.4", "198.51.100.4"),
+            ("For=198.51.100.4;proto=https;by=10.0.0.1", "198.51.100.4"),
+            ("proto=https;for=198.51.100.4, for=10.0.0.7", "198.51.100.4"),
+            ("for=6.6.6.6, for=198.51.100.4", "198.51.100.4"),
+            ("for=\"198.51.100.4:80\"", "198.51.100.4"),
+            ("for=\"[::1]:80\", for=\"[2001:db8::1]:80\"", "2001:db8::1"),
+            ("for=\"[2001:db8::1]:80\", for=\"[::1]:80\"", "2001:db8::1"),
+            ("for=\"[2001:db8::1]\"", "2001:db8::1"),
+            // Obfuscated identifiers and "unknown" say nothing about the address
+            ("for=_hidden", PROXY),
+            ("for=unknown", PROXY),
+            ("for=198.51.100.4, for=_gateway, for=10.0.0.7", "10.0.0.7"),
+            ("proto=https", PROXY),
+        ];
+        for (forwarded, expected) in cases {
+            assert_eq!(resolve(&p, PROXY, &[("forwarded", forwarded)]), expected, "Forwarded: {}", forwarded);
+        }
+    }
+
+    #[test]
+    fn only_the_configured_header_is_read() {
+        let xff = proxies(&[("TRUSTED_PROXIES", "10.0.0.0/8")]);
+        let forwarded = proxies(&[("TRUSTED_PROXIES", "10.0.0.0/8"), ("FORWARDED_HEADER", "forwarded")]);
+        // The proxy sets one header; the client forged the other
+        let headers = [("x-forwarded-for", "198.51.100.4"), ("forwarded", "for=6.6.6.6")];
+        assert_eq!(resolve(&xff, PROXY, &headers), "198.51.100.4");
+        let headers = [("x-forwarded-for", "6.6.6.6"), ("forwarded", "for=198.51.100.4")];
+        assert_eq!(resolve(&forwarded, PROXY, &headers), "198.51.100.4");
+        assert_eq!(resolve(&xff, PROXY, &[("forwarded", "for=6.6.6.6")]), PROXY);
+        assert_eq!(resolve(&forwarded, PROXY, &[("x-forwarded-for", "6.6.6.6")]), PROXY);
+    }
+
+    #[test]
+    fn cf_connecting_ip_needs_opting_in() {
+        let headers = [("cf-connecting-ip", "6.6.6.6"), ("x-forwarded-for", "198.51.100.4")];
+        assert_eq!(resolve(&proxies(&[("TRUSTED_PROXIES", PROXY)]), PROXY, &headers), "198.51.100.4");
+        let off = proxies(&[("TRUSTED_PROXIES", PROXY), ("TRUST_CF_CONNECTING_IP", "false")]);
+        assert_eq!(resolve(&off, PROXY, &headers), "198.51.100.4");
+        for on in ["1", "true", "Yes", "on"] {
+            let p = proxies(&[("TRUSTED_PROXIES", PROXY), ("TRUST_CF_CONNECTING_IP", on)]);
+            assert_eq!(resolve(&p, PROXY, &headers), "6.6.6.6");
+            assert_eq!(resolve(&p, PROXY, &[("cf-connecting-ip", "2001:db8::1")]), "2001:db8::1");
+            // Unparsable falls through to the forwarding chain
+            assert_eq!(resolve(&p, PROXY, &[("cf-connecting-ip", "nope"), ("x-forwarded-for", "198.51.100.4")]), "198.51.100.4");
+        }
+    }
+
+    #[test]
+    fn untrusted_peers_are_taken_at_their_word() {
+        let headers = [("cf-connecting-ip", "6.6.6.6"), ("x-forwarded-for", "6.6.6.7"), ("forwarded", "for=6.6.6.8")];
+        for p in [
+            TrustedProxies::default(),
+            proxies(&[("TRUSTED_PROXIES", "10.0.0.0/8"), ("TRUST_CF_CONNECTING_IP", "1")]),
+            proxies(&[("TRUSTED_PROXIES", "10.0.0.0/8"), ("TRUST_CF_CONNECTING_IP", "1"), ("FORWARDED_HEADER", "forwarded")]),
+        ] {
+            assert_eq!(resolve(&p, "198.51.100.4", &headers), "198.51.100.4");
+            assert_eq!(resolve(&p, "::ffff:198.51.100.4", &headers), "198.51.100.4");
+            assert_eq!(resolve(&p, "2001:db8::1", &headers), "2001:db8::1");
+        }
+    }
+
+    #[test]
+    fn configuration() {
+        assert!(TrustedProxies::from_vars(|_| None).unwrap().nets.is_empty());
+        let p = proxies(&[("TRUSTED_PROXIES", " 127.0.0.1 , ::1,10.0.0.0/8,")]);
+        assert_eq!(p.nets.len(), 3);
+        assert_eq!(p.header, ForwardedHeader::XForwardedFor);
+        assert!(!p.cf_connecting_ip);
+        for bad in [vec![("TRUSTED_PROXIES", "10.0.0.0/33")], vec![("TRUSTED_PROXIES", "proxy.local")], vec![
+            ("TRUSTED_PROXIES", "10.0.0.1"),
+            ("FORWARDED_HEADER", "x-real-ip"),
+        ]] {
+            let vars: Vec<(String, String)> = bad.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            assert!(TrustedProxies::from_vars(|name| vars.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone())).is_err(), "{:?}", bad);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// CSRF protection for the admin panel (signed double-submit). Every browser gets a random `ping0_csrf`
+// cookie; admin forms carry `csrf_token`, an HMAC of that cookie under the cookie secret, and every POST
+// under /admin must send it back. A cross-site page can neither read the cookie nor compute the MAC.
+// Scripts on admin pages (passkeys) send the token in an `X-CSRF-Token` header instead, and so must browser
+// sessions that change things through the JSON API (see `header_valid`), which is why the cookie covers the
+// whole site rather than /admin.
+
+use axum::body::{to_bytes, Body};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum_extra::headers::{Cookie, HeaderMapExt};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::Sha256;
+
+use crate::handlers::AppState;
+
+const COOKIE_NAME: &str = "ping0_csrf";
+const COOKIE_LEN: usize = 32;
+pub const FIELD_NAME: &str = "csrf_token";
+const HEADER_NAME: &str = "x-csrf-token";
+// Admin forms are a few short fields; anything bigger is not one of ours
+const MAX_FORM_BYTES: usize = 64 * 1024;
+
+// The token for the current request's forms, put in the request extensions by `protect`
+#[derive(Clone)]
+pub struct CsrfToken(pub String);
+
+fn mac(secret: &[u8], cookie: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(b"csrf:");
+    mac.update(cookie.as_bytes());
+    mac
+}
+
+fn valid(secret: &[u8], cookie: &str, token: &str) -> bool {
+    hex::decode(token).is_ok_and(|sig| mac(secret, cookie).verify_slice(&sig).is_ok())
+}
+
+// Whether the request's X-CSRF-Token header matches its cookie, for session requests outside /admin
+pub fn header_valid(secret: &[u8], headers: &HeaderMap) -> bool {
+    let Some(cookie) = headers.typed_get::<Cookie>().and_then(|c| c.get(COOKIE_NAME).map(str::to_string)) else {
+        return false;
+    };
+    headers.get(HEADER_NAME).and_then(|v| v.to_str().ok()).is_some_and(|token| valid(secret, &cookie, token))
+}
+
+fn rejected() -> Response {
+    (StatusCode::FORBIDDEN, "Invalid or missing CSRF token. Reload the page and try again.").into_response()
+}
+
+// Middleware for /admin: issues the cookie when missing, exposes the form token to handlers and checks it on POST
+pub async fn protect(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    if !req.uri().path().starts_with("/admin") {
+        return next.run(req).await;
+    }
+    let existing = req.headers().typed_get::<Cookie>().and_then(|c| c.get(COOKIE_NAME).map(str::to_string));
+    let (issued, is_new) = match existing {
+        Some(cookie) => (cookie, false),
+        None if req.method() == Method::POST => return rejected(),
+        None => (rand::thread_rng().sample_iter(&Alphanumeric).take(COOKIE_LEN).map(char::from).collect(), true),
+    };
+
+    if req.method() == Method::POST && let Some(token) = req.headers().get(HEADER_NAME) {
+        if !token.to_str().is_ok_and(|t| valid(&state.cookie_secret, &issued, t)) {
+            tracing::warn!("CSRF check failed for POST {}", req.uri().path());
+            return rejected();
+        }
+    } else if req.method() == Method::POST {
+        let is_form = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with("application/x-www-form-urlencoded"));
+        if !is_form {
+            return rejected();
+        }
+        let (parts, body) = req.into_parts();
+        let Ok(bytes) = to_bytes(body, MAX_FORM_BYTES).await else {
+            return (StatusCode::PAYLOAD_TOO_LARGE, "Form too large").into_response();
+        };
+        let token = url::form_urlencoded::parse(&bytes).find(|(k, _)| k == FIELD_NAME).map(|(_, v)| v.into_owned());
+        if !token.is_some_and(|t| valid(&state.cookie_secret, &issued, &t)) {
+            tracing::warn!("CSRF check failed for POST {}", parts.uri.path());
+            return rejected();
+        }
+        req = Request::from_parts(parts, Body::from(bytes));
+    }
+
+    let token = hex::encode(mac(&state.cookie_secret, &issued).finalize().into_bytes());
+    req.extensions_mut().insert(CsrfToken(token));
+    let page_load = req.method() == Method::GET;
+    let mut resp = next.run(req).await;
+    // Also sent again with every page, so a cookie issued back when it was limited to /admin is
+    // replaced by one the JSON API gets to see
+    if is_new || page_load {
+        let secure = if state.base_url.starts_with("https://") { "; Secure" } else { "" };
+        let cookie = format!("{}={}; HttpOnly; SameSite=Lax; Path=/{}", COOKIE_NAME, issued, secure);
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            resp.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+    resp
+}
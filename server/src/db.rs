@@ -0,0 +1,145 @@
+// Shared SQLite access. Connections come from a pool and are set up for concurrent use: WAL journal (readers
+// don't block the writer), a busy timeout instead of failing at once when another connection holds the
+// write lock, and enforced foreign keys. rusqlite blocks, so async code runs its queries through
+// `Db::call`, which takes a connection and runs the closure on tokio's blocking threads.
+
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, ErrorCode};
+use std::time::Duration;
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+// How long a request waits for a free connection before it gets a 503
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_POOL_SIZE: u32 = 8;
+
+#[derive(Clone)]
+pub struct Db(Pool<SqliteConnectionManager>);
+
+#[derive(Debug)]
+pub enum DbError {
+    // No connection became free in time
+    Unavailable(r2d2::Error),
+    Query(rusqlite::Error),
+    // The closure panicked
+    Panicked,
+}
+
+impl From<rusqlite::Error> for DbError {
+    fn from(e: rusqlite::Error) -> Self {
+        DbError::Query(e)
+    }
+}
+
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbError::Unavailable(e) => write!(f, "no database connection: {}", e),
+            DbError::Query(e) => e.fmt(f),
+            DbError::Panicked => f.write_str("database task panicked"),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl DbError {
+    // 503 when waiting a moment may help (no free connection, or the write lock outlasted the busy
+    // timeout), 500 otherwise
+    pub fn status(&self) -> StatusCode {
+        match self {
+            DbError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            DbError::Query(e) if matches!(e.sqlite_error_code(), Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn public_message(&self) -> &'static str {
+        match self.status() {
+            StatusCode::SERVICE_UNAVAILABLE => "Database busy, try again shortly",
+            _ => "Server error",
+        }
+    }
+
+    fn response(&self) -> Response {
+        let status = self.status();
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            return (status, [(header::RETRY_AFTER, "1")], self.public_message()).into_response();
+        }
+        (status, self.public_message()).into_response()
+    }
+}
+
+impl IntoResponse for DbError {
+    fn into_response(self) -> Response {
+        tracing::error!("database: {}", self);
+        self.response()
+    }
+}
+
+// Logs a failed query with some context and answers 503 or 500 (see DbError::status)
+pub fn error_response(context: &str, e: impl Into<DbError>) -> Response {
+    let e = e.into();
+    tracing::error!("{}: {}", context, e);
+    e.response()
+}
+
+impl Db {
+    pub fn open(path: &str) -> anyhow::Result<Db> {
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA synchronous = NORMAL;")
+        });
+        let size = std::env::var("DATABASE_POOL_SIZE").ok().and_then(|v| v.trim().parse().ok()).filter(|n| *n > 0).unwrap_or(DEFAULT_POOL_SIZE);
+        let pool = Pool::builder().max_size(size).connection_timeout(CHECKOUT_TIMEOUT).build(manager)?;
+        // The journal mode is stored in the database file, so setting it once covers every connection
+        let mode: String = pool.get()?.pragma_update_and_check(None, "journal_mode", "WAL", |r| r.get(0))?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            tracing::warn!("SQLite journal mode is {} (WAL unavailable)", mode);
+        }
+        Ok(Db(pool))
+    }
+
+    // A connection for code that may block (startup, the CLI, other blocking closures)
+    pub fn get(&self) -> Result<PooledConnection<SqliteConnectionManager>, DbError> {
+        self.0.get().map_err(DbError::Unavailable)
+    }
+
+    // Runs `f` with a pooled connection on the blocking thread pool
+    pub async fn call<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&mut Connection) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.0.clone();
+        match tokio::task::spawn_blocking(move || pool.get().map(|mut conn| f(&mut conn))).await {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(e)) => Err(DbError::Unavailable(e)),
+            Err(e) => {
+                tracing::error!("database task: {}", e);
+                Err(DbError::Panicked)
+            }
+        }
+    }
+
+    // `call` for queries, with their errors folded into DbError
+    pub async fn query<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        Ok(self.call(f).await??)
+    }
+
+    // `call` for handlers whose database work produces the whole response
+    pub async fn respond<F>(&self, f: F) -> Response
+    where
+        F: FnOnce(&mut Connection) -> Response + Send + 'static,
+    {
+        self.call(f).await.unwrap_or_else(IntoResponse::into_response)
+    }
+}
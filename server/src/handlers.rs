@@ -1,27 +1,44 @@
-// The final, corrected handlers.rs file
-
-use axum::extract::{Form, Multipart, Path, Query, State};
+use axum::body::Body;
+use axum::extract::multipart::Field;
+use axum::extract::{Extension, Form, Multipart, Path, Query, State};
 use axum_extra::typed_header::TypedHeader;
-use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
 use axum::response::{Html, IntoResponse, Redirect, Response};
 use axum::Json;
 use axum::debug_handler;
-use axum_extra::headers::Cookie;
+use axum_extra::headers::{Cookie, HeaderMapExt};
+use futures::TryStreamExt;
 use mime_guess::from_path as mime_from_path;
 use nanoid::nanoid;
 use qrcode::render::svg::Color;
 use qrcode::QrCode;
-use rusqlite::{params, Connection};
-use serde::Deserialize;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::IpAddr;
 use std::path::{Path as StdPath}; // Use StdPath to avoid conflict with axum::extract::Path
-use tokio::fs;
-use tokio::io::AsyncWriteExt;
+use std::sync::Arc;
+use utoipa::ToSchema;
 use uuid::Uuid;
 use askama::Template;
-use ping0::templates::{IndexTemplate, ResultTemplate, ImageOgTemplate, FileInfoTemplate, AdminLoginTemplate, AdminHomeTemplate, AdminItemsTemplate, AdminItem};
+use crate::templates::{IndexTemplate, ResultTemplate, ImageOgTemplate, FileInfoTemplate, PasswordPromptTemplate, AdminLoginTemplate, AdminSetupTemplate, AdminHomeTemplate, AdminItemsTemplate, AdminItem, AdminStatsTemplate, StatsDay, AdminKeysTemplate, AdminKey, AdminUsersTemplate, AdminUserRow, AdminInviteRow, AdminInviteTemplate, AdminSessionsTemplate, AdminSession, AdminLoginCodeTemplate, AdminTwoFactorTemplate, AdminTotpEnrollment, AdminPasskeysTemplate, AdminPasskey};
 use image::{imageops::FilterType, DynamicImage, ImageOutputFormat, GenericImageView};
+use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256};
-use rand::{distributions::Alphanumeric, Rng};
+use subtle::ConstantTimeEq;
+use crate::admins::{self, AccountError, AdminUser, Role};
+use crate::analytics::GeoIp;
+use crate::apikeys::{self, AuthError, Caller, Scope};
+use crate::clientip::{ClientIp, TrustedProxies};
+use crate::csrf::CsrfToken;
+use crate::db::{self, Db, DbError};
+use crate::throttle;
+use crate::twofactor;
+use crate::passkeys;
+use crate::oidc::{self, OidcConfig};
+use crate::ratelimit::RateLimiter;
+use webauthn_rs::prelude::{PublicKeyCredential, RegisterPublicKeyCredential, Webauthn};
+use crate::storage::{limit_stream, Storage};
 pub async fn cors_preflight() -> impl IntoResponse {
     // Let CorsLayer attach the appropriate headers; return 204 No Content
     (StatusCode::NO_CONTENT, ())
@@ -30,7 +47,7 @@ pub async fn cors_preflight() -> impl IntoResponse {
 // Removed CodeParams; using Path<String> directly for routes with one :code param
 
 // Utility to extract the admin cookie token
-fn extract_admin_token(cookie: Option<TypedHeader<Cookie>>) -> Option<String> {
+pub(crate) fn extract_admin_token(cookie: Option<TypedHeader<Cookie>>) -> Option<String> {
     cookie
         .as_ref()
         .and_then(|TypedHeader(c)| c.get("ping0_admin"))
@@ -38,7 +55,7 @@ fn extract_admin_token(cookie: Option<TypedHeader<Cookie>>) -> Option<String> {
 }
 
 // Maximum file size: 1 GiB
-const MAX_FILE_SIZE: usize = 1024 * 1024 * 1024;
+pub(crate) const MAX_FILE_SIZE: usize = 1024 * 1024 * 1024;
 
 // Allowed file extensions for uploads
 const ALLOWED_EXTENSIONS: &[&str] = &[
@@ -58,7 +75,7 @@ const ALLOWED_EXTENSIONS: &[&str] = &[
 const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp", "tif", "tiff", "avif"];
 
 // Preview target size: strictly under 1 MiB
-const PREVIEW_MAX_BYTES: usize = 1 * 1024 * 1024;
+const PREVIEW_MAX_BYTES: usize = 1024 * 1024;
 
 // ensure_dir was unused; removed to avoid dead_code warning
 
@@ -70,6 +87,11 @@ fn make_preview_filename(original_filename: &str) -> String {
     format!("{}.jpg", stem)
 }
 
+// Storage key of the OG preview generated for an uploaded image
+fn preview_key(original_key: &str) -> String {
+    format!("previews/{}", make_preview_filename(original_key))
+}
+
 fn is_image_ext(ext: &str) -> bool {
     IMAGE_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext))
 }
@@ -94,11 +116,9 @@ fn encode_jpeg_under_limit(img: &DynamicImage, max_bytes: usize) -> Option<Vec<u
         for q in &qualities {
             let mut buf = Vec::with_capacity(512 * 1024);
             let mut cursor = std::io::Cursor::new(&mut buf);
-            if resized.write_to(&mut cursor, ImageOutputFormat::Jpeg(*q)).is_ok() {
-                if buf.len() <= max_bytes {
-                    tracing::info!("Preview compressed: scale={}, quality={}, size={}KB", scale, q, buf.len() / 1024);
-                    return Some(buf);
-                }
+            if resized.write_to(&mut cursor, ImageOutputFormat::Jpeg(*q)).is_ok() && buf.len() <= max_bytes {
+                tracing::info!("Preview compressed: scale={}, quality={}, size={}KB", scale, q, buf.len() / 1024);
+                return Some(buf);
             }
         }
     }
@@ -107,12 +127,10 @@ fn encode_jpeg_under_limit(img: &DynamicImage, max_bytes: usize) -> Option<Vec<u
     None
 }
 
-fn try_generate_preview(original_path: &StdPath, preview_path: &StdPath) -> Result<(), String> {
-    let img = image::open(original_path).map_err(|e| format!("open image: {}", e))?;
+fn try_generate_preview(original: &[u8]) -> Result<Vec<u8>, String> {
+    let img = image::load_from_memory(original).map_err(|e| format!("open image: {}", e))?;
     match encode_jpeg_under_limit(&img, PREVIEW_MAX_BYTES) {
-        Some(bytes) => {
-            std::fs::write(preview_path, &bytes).map_err(|e| format!("write preview: {}", e))
-        }
+        Some(bytes) => Ok(bytes),
         None => {
             // Fallback: For very large images, aggressively scale down first
             let (w, h) = img.dimensions();
@@ -131,35 +149,32 @@ fn try_generate_preview(original_path: &StdPath, preview_path: &StdPath) -> Resu
             
             // Try to write at very low quality
             let mut buf = Vec::new();
-            let mut cur = std::io::Cursor::new(&mut buf);
             for quality in [45u8, 35, 25, 15] {
                 buf.clear();
-                cur = std::io::Cursor::new(&mut buf);
-                if fallback_img.write_to(&mut cur, ImageOutputFormat::Jpeg(quality)).is_ok() {
-                    if buf.len() <= PREVIEW_MAX_BYTES {
-                        tracing::warn!("Fallback succeeded at quality={}, size={}KB", quality, buf.len() / 1024);
-                        return std::fs::write(preview_path, &buf).map_err(|e| format!("write preview: {}", e));
-                    }
+                let mut cur = std::io::Cursor::new(&mut buf);
+                if fallback_img.write_to(&mut cur, ImageOutputFormat::Jpeg(quality)).is_ok() && buf.len() <= PREVIEW_MAX_BYTES {
+                    tracing::warn!("Fallback succeeded at quality={}, size={}KB", quality, buf.len() / 1024);
+                    return Ok(buf);
                 }
             }
             
             // Last resort: use absolute minimum
             buf.clear();
-            cur = std::io::Cursor::new(&mut buf);
+            let mut cur = std::io::Cursor::new(&mut buf);
             fallback_img.write_to(&mut cur, ImageOutputFormat::Jpeg(10)).map_err(|e| format!("encode jpeg: {}", e))?;
             tracing::warn!("Last resort fallback: quality=10, size={}KB", buf.len() / 1024);
-            std::fs::write(preview_path, &buf).map_err(|e| format!("write preview: {}", e))
+            Ok(buf)
         }
     }
 }
 
 #[derive(Clone)]
-pub struct AppState { pub db_path: String, pub base_url: String }
+pub struct AppState { pub db: Db, pub base_url: String, pub storage: Arc<dyn Storage>, pub cookie_secret: Arc<[u8]>, pub geoip: Option<Arc<GeoIp>>, pub webauthn: Option<Arc<Webauthn>>, pub oidc: Option<Arc<OidcConfig>>, pub rate_limiter: Arc<RateLimiter>, pub trusted_proxies: Arc<TrustedProxies> }
 
-#[derive(Deserialize)]
-pub struct LinkRequest { pub link: String, pub qr: Option<String> }
+#[derive(Deserialize, ToSchema)]
+pub struct LinkRequest { pub link: String, pub qr: Option<String>, pub alias: Option<String> }
 
-fn is_allowed_extension(ext: &str) -> bool {
+pub(crate) fn is_allowed_extension(ext: &str) -> bool {
     ALLOWED_EXTENSIONS.iter().any(|&allowed| allowed.eq_ignore_ascii_case(ext))
 }
 
@@ -171,8 +186,246 @@ fn ensure_absolute(base: &str, url: &str) -> String {
     }
 }
 
-pub async fn upload_handler(State(state): State<AppState>, mut multipart: Multipart) -> impl IntoResponse {
-    while let Ok(Some(mut field)) = multipart.next_field().await {
+pub(crate) fn now_secs() -> i64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+// Parse an expiry such as "1h", "1d", "7d", "30m" or "never" into seconds (None = never expires)
+pub(crate) fn parse_expires_in(value: &str) -> Result<Option<i64>, String> {
+    let v = value.trim().to_ascii_lowercase();
+    if v.is_empty() || v == "never" { return Ok(None); }
+    let (num, unit) = v.split_at(v.len() - 1);
+    let mult = match unit { "m" => 60, "h" => 3600, "d" => 86400, "w" => 7 * 86400, _ => return Err(format!("Invalid expires_in '{}'", value)) };
+    match num.parse::<i64>() {
+        Ok(n) if n > 0 && n <= 3650 * 86400 / mult => Ok(Some(n * mult)),
+        _ => Err(format!("Invalid expires_in '{}'", value)),
+    }
+}
+
+fn is_truthy(value: &str) -> bool {
+    matches!(value.trim().to_ascii_lowercase().as_str(), "true" | "on" | "1" | "yes")
+}
+
+// Vanity aliases share the namespace of generated codes
+const ALIAS_MIN_LEN: usize = 3;
+const ALIAS_MAX_LEN: usize = 64;
+const RESERVED_ALIASES: &[&str] = &["admin", "api", "files", "health", "static", "submit", "upload", "link", "login", "logout"];
+
+pub(crate) fn validate_alias(alias: &str) -> Result<(), String> {
+    if alias.len() < ALIAS_MIN_LEN || alias.len() > ALIAS_MAX_LEN {
+        return Err(format!("Alias must be {}-{} characters long", ALIAS_MIN_LEN, ALIAS_MAX_LEN));
+    }
+    if !alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err("Alias may only contain letters, digits, '-' and '_'".to_string());
+    }
+    if RESERVED_ALIASES.iter().any(|r| r.eq_ignore_ascii_case(alias)) {
+        return Err(format!("Alias '{}' is reserved", alias));
+    }
+    Ok(())
+}
+
+// Optional per-item settings accepted by the form, /api/upload and tus metadata
+#[derive(Default, Clone)]
+pub(crate) struct ItemOptions {
+    pub alias: Option<String>,
+    pub expires_at: Option<i64>,
+    pub max_views: Option<i64>,
+    pub burn_after_reading: bool,
+    pub password_hash: Option<String>,
+    // Set from the authenticated caller, never from request fields
+    pub api_key_id: Option<i64>,
+}
+
+impl ItemOptions {
+    // Apply one text field by name; unknown names are ignored. Async because a password is hashed
+    // on the blocking pool.
+    pub(crate) async fn apply_field(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "alias" => {
+                let v = value.trim();
+                self.alias = if v.is_empty() { None } else { validate_alias(v)?; Some(v.to_string()) };
+            }
+            "expires_in" => { self.expires_at = parse_expires_in(value)?.map(|secs| now_secs() + secs); }
+            "max_views" => {
+                let v = value.trim();
+                self.max_views = if v.is_empty() { None } else {
+                    match v.parse::<i64>() { Ok(n) if n > 0 => Some(n), _ => return Err(format!("Invalid max_views '{}'", value)) }
+                };
+            }
+            "burn_after_reading" => { self.burn_after_reading = is_truthy(value); }
+            "password" => {
+                self.password_hash = if value.is_empty() { None } else { Some(crate::password::hash_password_async(value.to_string()).await?) };
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_option_field(name: &str) -> bool {
+        matches!(name, "alias" | "expires_in" | "max_views" | "burn_after_reading" | "password")
+    }
+}
+
+// Generated codes are retried this many times before giving up on collisions
+const CODE_ATTEMPTS: usize = 5;
+
+#[derive(Debug)]
+pub(crate) enum InsertError {
+    AliasTaken(String),
+    Db(DbError),
+}
+
+impl std::fmt::Display for InsertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InsertError::AliasTaken(alias) => write!(f, "Alias '{}' is already taken", alias),
+            InsertError::Db(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+impl InsertError {
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            InsertError::AliasTaken(_) => StatusCode::CONFLICT,
+            InsertError::Db(e) => e.status(),
+        }
+    }
+
+    // Message safe to show to the client
+    pub(crate) fn public_message(&self) -> String {
+        match self {
+            InsertError::AliasTaken(_) => self.to_string(),
+            InsertError::Db(e) => e.public_message().to_string(),
+        }
+    }
+}
+
+fn is_code_collision(e: &rusqlite::Error) -> bool {
+    matches!(e, rusqlite::Error::SqliteFailure(f, _) if f.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY)
+}
+
+pub(crate) fn alias_taken(conn: &Connection, alias: &str) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT 1 FROM items WHERE code = ?1", params![alias], |_| Ok(())).optional().map(|r| r.is_some())
+}
+
+// Insert a new item under its alias, or under a generated code (retried on collision); returns the code
+pub(crate) fn insert_item(conn: &Connection, kind: &str, value: &str, opts: &ItemOptions) -> Result<String, InsertError> {
+    // Burn-after-reading without an explicit limit means "first view only"
+    let max_views = opts.max_views.or(if opts.burn_after_reading { Some(1) } else { None });
+    let insert = |code: &str| conn.execute(
+        "INSERT INTO items(code, kind, value, created_at, expires_at, max_views, burn_after_reading, password_hash, api_key_id) VALUES (?1, ?2, ?3, strftime('%s','now'), ?4, ?5, ?6, ?7, ?8)",
+        params![code, kind, value, opts.expires_at, max_views, opts.burn_after_reading, opts.password_hash, opts.api_key_id],
+    );
+    if let Some(alias) = &opts.alias {
+        return match insert(alias) {
+            Ok(_) => Ok(alias.clone()),
+            Err(e) if is_code_collision(&e) => Err(InsertError::AliasTaken(alias.clone())),
+            Err(e) => Err(InsertError::Db(e.into())),
+        };
+    }
+    let mut attempt = 1;
+    loop {
+        let code = nanoid!(8);
+        match insert(&code) {
+            Ok(_) => return Ok(code),
+            Err(e) if is_code_collision(&e) && attempt < CODE_ATTEMPTS => {
+                tracing::warn!("Generated code {} already exists, retrying", code);
+                attempt += 1;
+            }
+            Err(e) => return Err(InsertError::Db(e.into())),
+        }
+    }
+}
+
+// insert_item on the blocking pool, for handlers that have no connection of their own
+pub(crate) async fn save_item(state: &AppState, kind: &'static str, value: String, opts: ItemOptions) -> Result<String, InsertError> {
+    state.db.call(move |conn| insert_item(conn, kind, &value, &opts)).await.unwrap_or_else(|e| Err(InsertError::Db(e)))
+}
+
+// Remove an item row together with its click history
+pub(crate) fn delete_item_row(conn: &Connection, code: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM clicks WHERE code = ?1", params![code])?;
+    conn.execute("DELETE FROM items WHERE code = ?1", params![code])?;
+    Ok(())
+}
+
+// Remove the stored file (and image preview) behind a 'file' item
+pub(crate) async fn delete_item_files(storage: &dyn Storage, kind: &str, value: &str) {
+    if kind == "file"
+        && let Some(fname) = value.strip_prefix("file:") {
+            if let Err(e) = storage.delete(fname).await { tracing::warn!("delete {}: {}", fname, e); }
+            let _ = storage.delete(&preview_key(fname)).await;
+        }
+}
+
+// QR code as an inline SVG, at least `size` pixels square; empty if `data` doesn't fit in one
+fn qr_svg(data: &str, size: u32) -> String {
+    QrCode::new(data.as_bytes())
+        .map(|c| c
+            .render::<Color>()
+            .min_dimensions(size, size)
+            .quiet_zone(true)
+            .dark_color(Color("#000000"))
+            .light_color(Color("#ffffff"))
+            .build())
+        .unwrap_or_default()
+}
+
+// QR code for a short URL as an SVG data URL (the `qr_code_data` field of API responses)
+pub(crate) fn qr_data_url(base_url: &str, short_url: &str) -> Option<String> {
+    let image = qr_svg(&ensure_absolute(base_url, short_url), 320);
+    (!image.is_empty()).then(|| format!("data:image/svg+xml;utf8,{}", urlencoding::encode(&image)))
+}
+
+// Stream one multipart file field into storage, enforcing MAX_FILE_SIZE
+pub(crate) async fn store_field(storage: &dyn Storage, key: &str, field: Field<'_>) -> io::Result<u64> {
+    let body = Box::pin(field.map_err(io::Error::other));
+    storage.put_stream(key, limit_stream(body, MAX_FILE_SIZE as u64)).await
+}
+
+// Who may create items through the form and legacy endpoints (see ALLOW_ANONYMOUS_UPLOADS)
+pub(crate) async fn creating_caller(state: &AppState, headers: &HeaderMap) -> Result<Caller, (StatusCode, &'static str)> {
+    match apikeys::identify(state, headers).await {
+        Ok(caller) if apikeys::may_create(&caller) => Ok(caller),
+        Ok(Caller::Key(_)) => Err((StatusCode::FORBIDDEN, "API key lacks the 'create' scope")),
+        Ok(_) => Err((StatusCode::UNAUTHORIZED, "Authentication required")),
+        Err(AuthError::InvalidKey) => Err((StatusCode::UNAUTHORIZED, "Unknown or revoked API key")),
+        Err(AuthError::Db(e)) => { tracing::error!("authenticate: {}", e); Err((StatusCode::INTERNAL_SERVER_ERROR, "Server error")) }
+    }
+}
+
+// JSON returned by /upload and /link
+#[derive(Serialize, ToSchema)]
+pub struct ShortLinkResponse {
+    pub code: String,
+    pub short: String,
+    // Stored file name (uploads only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    // QR code for the short link as inline SVG; empty for links without `qr=on`
+    pub qr_svg: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/upload",
+    tag = "upload",
+    request_body(content = crate::openapi::UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "File stored", body = ShortLinkResponse),
+        (status = 400, description = "No file or file type not allowed", body = String),
+        (status = 401, description = "Anonymous uploads are disabled", body = String),
+        (status = 413, description = "File too large, or larger than the hourly upload quota", body = String),
+        (status = 429, description = "Rate limit exceeded; see `Retry-After`", body = String),
+    ),
+    security((), ("bearer" = []), ("session" = []))
+)]
+pub async fn upload_handler(State(state): State<AppState>, headers: HeaderMap, mut multipart: Multipart) -> Response {
+    let caller = match creating_caller(&state, &headers).await { Ok(c) => c, Err(e) => return e.into_response() };
+    while let Ok(Some(field)) = multipart.next_field().await {
         let name = field.name().unwrap_or("");
         if name == "file" {
             let filename = field.file_name().unwrap_or("file").to_string();
@@ -184,131 +437,107 @@ pub async fn upload_handler(State(state): State<AppState>, mut multipart: Multip
 
             if !is_allowed_extension(ext) {
                 tracing::warn!("Rejected file with extension: {}", ext);
-                return (StatusCode::BAD_REQUEST, format!("File type '.{}' not allowed", ext));
+                return (StatusCode::BAD_REQUEST, format!("File type '.{}' not allowed", ext)).into_response();
             }
 
             let id = Uuid::new_v4();
             let filename_saved = format!("{}.{}", id, ext);
-            let path = format!("uploads/{}", filename_saved);
-            if let Err(e) = fs::create_dir_all("uploads").await {
-                tracing::error!("Failed to create uploads dir: {}", e);
-                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create uploads directory".to_string());
-            }
-            let mut out = match tokio::fs::File::create(&path).await {
-                Ok(f) => f,
-                Err(e) => {
-                    tracing::error!("Failed to create file: {}", e);
-                    return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save file".to_string());
+            match store_field(state.storage.as_ref(), &filename_saved, field).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::FileTooLarge => {
+                    return (StatusCode::PAYLOAD_TOO_LARGE, format!("File too large. Max size: {}MB", MAX_FILE_SIZE / 1024 / 1024)).into_response();
                 }
-            };
-            let mut written: usize = 0;
-            while let Ok(Some(chunk)) = field.chunk().await {
-                written = written.saturating_add(chunk.len());
-                if written > MAX_FILE_SIZE {
-                    let _ = tokio::fs::remove_file(&path).await;
-                    return (StatusCode::PAYLOAD_TOO_LARGE, format!("File too large. Max size: {}MB", MAX_FILE_SIZE / 1024 / 1024));
-                }
-                if let Err(e) = out.write_all(&chunk).await {
-                    tracing::error!("Write error: {}", e);
-                    let _ = tokio::fs::remove_file(&path).await;
-                    return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save file".to_string());
+                Err(e) => {
+                    tracing::error!("Failed to save file: {}", e);
+                    return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save file".to_string()).into_response();
                 }
             }
 
-            let short_code = nanoid!(8);
             let original = format!("file:{}", filename_saved);
-            let conn = Connection::open(&state.db_path).unwrap();
-            conn.execute(
-                "INSERT INTO items(code, kind, value, created_at) VALUES (?1, ?2, ?3, strftime('%s','now'))",
-                params![short_code, "file", original],
-            ).ok();
+            let opts = ItemOptions { api_key_id: caller.api_key_id(), ..Default::default() };
+            let short_code = match save_item(&state, "file", original, opts).await {
+                Ok(code) => code,
+                Err(e) => {
+                    tracing::error!("insert item: {}", e);
+                    let _ = state.storage.delete(&filename_saved).await;
+                    return (e.status(), e.public_message()).into_response();
+                }
+            };
 
             let short_link = format!("{}/s/{}", state.base_url, short_code);
-            let qr_target = ensure_absolute(&state.base_url, &short_link);
-            let qr_svg = QrCode::new(qr_target.as_bytes())
-                .map(|c| c
-                    .render::<Color>()
-                    .min_dimensions(320, 320)
-                    .quiet_zone(true)
-                    .dark_color(Color("#000000"))
-                    .light_color(Color("#ffffff"))
-                    .build())
-                .unwrap_or_default();
-
-            let body = format!(
-                "{{\"code\":\"{}\", \"short\":\"{}\", \"file\":\"{}\", \"qr_svg\": \"{}\"}}",
-                short_code,
-                short_link,
-                filename_saved,
-                qr_svg.replace('\"', "\\\""),
-            );
+            let qr_svg = qr_svg(&ensure_absolute(&state.base_url, &short_link), 320);
 
             tracing::info!("File uploaded successfully: {}", filename_saved);
-            return (StatusCode::OK, body);
+            return Json(ShortLinkResponse { code: short_code, short: short_link, file: Some(filename_saved), qr_svg }).into_response();
         }
     }
-    (StatusCode::BAD_REQUEST, "No file provided".to_string())
+    (StatusCode::BAD_REQUEST, "No file provided".to_string()).into_response()
 }
 
-pub async fn link_handler(State(state): State<AppState>, Form(req): Form<LinkRequest>) -> impl IntoResponse {
+#[utoipa::path(
+    post,
+    path = "/link",
+    tag = "upload",
+    request_body(content = LinkRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Short link created", body = ShortLinkResponse),
+        (status = 400, description = "Missing or invalid URL or alias", body = String),
+        (status = 401, description = "Anonymous uploads are disabled", body = String),
+        (status = 409, description = "Alias already taken", body = String),
+        (status = 429, description = "Rate limit exceeded; see `Retry-After`", body = String),
+    ),
+    security((), ("bearer" = []), ("session" = []))
+)]
+pub async fn link_handler(State(state): State<AppState>, headers: HeaderMap, Form(req): Form<LinkRequest>) -> Response {
+    let caller = match creating_caller(&state, &headers).await { Ok(c) => c, Err(e) => return e.into_response() };
     if req.link.is_empty() {
-        return (StatusCode::BAD_REQUEST, "No link provided".to_string());
+        return (StatusCode::BAD_REQUEST, "No link provided".to_string()).into_response();
     }
 
     if !req.link.starts_with("http://") && !req.link.starts_with("https://") {
-        return (StatusCode::BAD_REQUEST, "Invalid URL format. Must start with http:// or https://".to_string());
+        return (StatusCode::BAD_REQUEST, "Invalid URL format. Must start with http:// or https://".to_string()).into_response();
     }
 
-    let short_code = nanoid!(8);
-    let conn = Connection::open(&state.db_path).unwrap();
-    conn.execute(
-        "INSERT INTO items(code, kind, value, created_at) VALUES (?1, ?2, ?3, strftime('%s','now'))",
-        params![short_code, "url", req.link],
-    ).ok();
+    let mut opts = ItemOptions { api_key_id: caller.api_key_id(), ..Default::default() };
+    if let Some(alias) = &req.alias
+        && let Err(e) = opts.apply_field("alias", alias).await { return (StatusCode::BAD_REQUEST, e).into_response(); }
+    let short_code = match save_item(&state, "url", req.link.clone(), opts).await {
+        Ok(code) => code,
+        Err(e) => { tracing::error!("insert item: {}", e); return (e.status(), e.public_message()).into_response(); }
+    };
 
     let short_link = format!("{}/s/{}", state.base_url, short_code);
     let qr_svg = if matches!(req.qr.as_deref(), Some("on")) {
-        let qr_target = ensure_absolute(&state.base_url, &short_link);
-        QrCode::new(qr_target.as_bytes())
-            .map(|c| c
-                .render::<Color>()
-                .min_dimensions(320, 320)
-                .quiet_zone(true)
-                .dark_color(Color("#000000"))
-                .light_color(Color("#ffffff"))
-                .build())
-            .unwrap_or_default()
+        qr_svg(&ensure_absolute(&state.base_url, &short_link), 320)
     } else {
         String::new()
     };
-    let body = format!(
-        "{{\"code\":\"{}\", \"short\":\"{}\", \"qr_svg\": \"{}\"}}",
-        short_code,
-        short_link,
-        qr_svg.replace('\"', "\\\""),
-    );
-
     tracing::info!("Short link created for URL: {} -> {}", req.link, short_link);
-    (StatusCode::OK, body)
+    Json(ShortLinkResponse { code: short_code, short: short_link, file: None, qr_svg }).into_response()
 }
 
 pub async fn index_handler() -> Html<String> { Html(IndexTemplate.render().unwrap_or_else(|_| "Template error".to_string())) }
 
-pub async fn submit_handler(State(state): State<AppState>, mut multipart: Multipart) -> axum::response::Response {
+pub async fn submit_handler(State(state): State<AppState>, headers: HeaderMap, mut multipart: Multipart) -> axum::response::Response {
+    let caller = match creating_caller(&state, &headers).await { Ok(c) => c, Err(e) => return e.into_response() };
     let mut link_value: Option<String> = None;
     let mut file_bytes: Option<(String, Vec<u8>)> = None;
     let mut want_qr: bool = false;
+    let mut opts = ItemOptions { api_key_id: caller.api_key_id(), ..Default::default() };
 
     while let Ok(Some(field)) = multipart.next_field().await {
         let name = field.name().unwrap_or("").to_string();
         match name.as_str() {
+            n if ItemOptions::is_option_field(n) => {
+                let text = field.text().await.unwrap_or_default();
+                if let Err(e) = opts.apply_field(n, &text).await { return (StatusCode::BAD_REQUEST, e).into_response(); }
+            }
             "link" => {
-                if let Ok(text) = field.text().await { if !text.trim().is_empty() { link_value = Some(text.trim().to_string()); } }
+                if let Ok(text) = field.text().await && !text.trim().is_empty() { link_value = Some(text.trim().to_string()); }
             }
             "file" => {
-                if let Some(fname) = field.file_name().map(|s| s.to_string()) {
-                    if let Ok(bytes) = field.bytes().await { file_bytes = Some((fname, bytes.to_vec())); }
-                }
+                if let Some(fname) = field.file_name().map(|s| s.to_string())
+                    && let Ok(bytes) = field.bytes().await { file_bytes = Some((fname, bytes.to_vec())); }
             }
             "qr" => { want_qr = true; }
             _ => {}
@@ -322,23 +551,27 @@ pub async fn submit_handler(State(state): State<AppState>, mut multipart: Multip
 
         let id = Uuid::new_v4();
         let filename_saved = format!("{}.{}", id, ext);
-        let path = format!("uploads/{}", filename_saved);
-        if let Err(e) = fs::create_dir_all("uploads").await { tracing::error!("create uploads dir: {}", e); return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create uploads directory".to_string()).into_response(); }
-        if let Err(e) = fs::write(&path, &data).await { tracing::error!("save file: {}", e); return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save file".to_string()).into_response(); }
+        if let Err(e) = state.storage.put(&filename_saved, data.into()).await { tracing::error!("save file: {}", e); return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save file".to_string()).into_response(); }
 
-        let short_code = nanoid!(8);
         let original = format!("file:{}", filename_saved);
-        let conn = Connection::open(&state.db_path).unwrap();
-        conn.execute("INSERT INTO items(code, kind, value, created_at) VALUES (?1, ?2, ?3, strftime('%s','now'))", params![short_code, "file", original]).ok();
+        let short_code = match save_item(&state, "file", original, opts).await {
+            Ok(code) => code,
+            Err(e) => {
+                tracing::error!("insert item: {}", e);
+                let _ = state.storage.delete(&filename_saved).await;
+                return (e.status(), e.public_message()).into_response();
+            }
+        };
         let redirect_to = format!("/r/{}?qr={}", short_code, if want_qr {"1"} else {"0"});
         return Redirect::to(&redirect_to).into_response();
     }
 
     if let Some(link) = link_value {
         if !link.starts_with("http://") && !link.starts_with("https://") { return (StatusCode::BAD_REQUEST, "Invalid URL format".to_string()).into_response(); }
-        let short_code = nanoid!(8);
-        let conn = Connection::open(&state.db_path).unwrap();
-        conn.execute("INSERT INTO items(code, kind, value, created_at) VALUES (?1, ?2, ?3, strftime('%s','now'))", params![short_code, "url", link]).ok();
+        let short_code = match save_item(&state, "url", link, opts).await {
+            Ok(code) => code,
+            Err(e) => { tracing::error!("insert item: {}", e); return (e.status(), e.public_message()).into_response(); }
+        };
         let redirect_to = format!("/r/{}?qr={}", short_code, if want_qr {"1"} else {"0"});
         return Redirect::to(&redirect_to).into_response();
     }
@@ -346,71 +579,365 @@ pub async fn submit_handler(State(state): State<AppState>, mut multipart: Multip
     (StatusCode::BAD_REQUEST, "Provide a URL or a file".to_string()).into_response()
 }
 
-pub async fn result_handler(State(state): State<AppState>, Path(code): Path<String>, Query(q): Query<std::collections::HashMap<String,String>>) -> Html<String> {
-    let conn = Connection::open(&state.db_path).unwrap();
-    let mut stmt = conn.prepare("SELECT kind, value FROM items WHERE code = ?1").unwrap();
-    let row = stmt.query_row(params![code.clone()], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)));
-    let (_kind, _value) = match row { Ok(v) => v, Err(_) => return Html("<h1>Not found</h1>".to_string()) };
+pub async fn result_handler(State(state): State<AppState>, Path(code): Path<String>, Query(q): Query<std::collections::HashMap<String,String>>) -> Response {
+    let lookup = code.clone();
+    let row = state.db.query(move |conn| {
+        conn.query_row("SELECT kind, value FROM items WHERE code = ?1", params![lookup], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?))).optional()
+    }).await;
+    let (_kind, _value) = match row {
+        Ok(Some(v)) => v,
+        Ok(None) => return Html("<h1>Not found</h1>".to_string()).into_response(),
+        Err(e) => return db::error_response("load item", e),
+    };
     let short_link = format!("{}/s/{}", state.base_url, code);
     let qr_svg = if q.get("qr").map(|v| v=="1").unwrap_or(false) {
-        let qr_target = ensure_absolute(&state.base_url, &short_link);
-        QrCode::new(qr_target.as_bytes())
-            .map(|c| c
-                .render::<Color>()
-                .min_dimensions(320,320)
-                .quiet_zone(true)
-                .dark_color(Color("#000000"))
-                .light_color(Color("#ffffff"))
-                .build())
-            .unwrap_or_default()
+        qr_svg(&ensure_absolute(&state.base_url, &short_link), 320)
     } else { String::new() };
     let tpl = ResultTemplate { code, short_link, qr_svg: if qr_svg.is_empty() { None } else { Some(qr_svg) } };
-    Html(tpl.render().unwrap_or_else(|_| "Template error".to_string()))
+    Html(tpl.render().unwrap_or_else(|_| "Template error".to_string())).into_response()
 }
 
-pub async fn short_handler(State(state): State<AppState>, Path(code): Path<String>, headers: HeaderMap) -> axum::response::Response {
-    let (kind, value) = {
-        let conn = Connection::open(&state.db_path).unwrap();
-        let mut stmt = conn.prepare("SELECT kind, value FROM items WHERE code = ?1").unwrap();
-        let row = stmt.query_row(params![code.clone()], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)));
-        match row { Ok(v) => v, Err(_) => return (StatusCode::NOT_FOUND, "Not found").into_response() }
+// Return the storage key of the OG preview for an image, generating it on first use
+async fn ensure_preview(storage: &dyn Storage, original_key: &str) -> Option<String> {
+    let key = preview_key(original_key);
+    if let Ok(Some(_)) = storage.stat(&key).await {
+        return Some(key);
+    }
+    let original = storage.get(original_key).await.ok()?;
+    let encoded = tokio::task::spawn_blocking(move || try_generate_preview(&original)).await.ok()?;
+    match encoded {
+        Ok(bytes) => match storage.put(&key, bytes.into()).await {
+            Ok(()) => Some(key),
+            Err(e) => { tracing::warn!("store preview for {}: {}", original_key, e); None }
+        },
+        Err(e) => { tracing::warn!("generate preview for {}: {}", original_key, e); None }
+    }
+}
+
+// Parse a single `Range: bytes=a-b` header against an object of `size` bytes
+fn parse_range(headers: &HeaderMap, size: u64) -> Option<std::ops::Range<u64>> {
+    let spec = headers.get(header::RANGE)?.to_str().ok()?.strip_prefix("bytes=")?;
+    if spec.contains(',') { return None; }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => { let n: u64 = suffix.parse().ok()?; (size.saturating_sub(n), size) }
+        (s, "") => (s.parse().ok()?, size),
+        (s, e) => (s.parse().ok()?, e.parse::<u64>().ok()?.saturating_add(1).min(size)),
     };
+    if start < end { Some(start..end) } else { None }
+}
 
-    match kind.as_str() {
-        "url" => Redirect::permanent(&value).into_response(),
+#[derive(Clone, Copy, PartialEq)]
+enum Delivery {
+    Normal,
+    // Every fetch is accounted for: no presigned redirects and no partial content
+    Strict,
+}
+
+// Stream a stored object as an HTTP response (with Range support), or redirect to a presigned URL
+async fn serve_object(storage: &dyn Storage, key: &str, headers: &HeaderMap, delivery: Delivery) -> io::Result<Response> {
+    if delivery == Delivery::Normal
+        && let Some(url) = storage.presigned_url(key).await? {
+            return Ok(Redirect::temporary(&url).into_response());
+        }
+    let meta = storage.stat(key).await?.ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    let range = if delivery == Delivery::Normal { parse_range(headers, meta.size) } else { None };
+    let body = storage.stream(key, range.clone()).await?;
+    let mime = mime_from_path(key).first_or_octet_stream();
+    let mut resp = Response::new(Body::from_stream(body));
+    let h = resp.headers_mut();
+    h.insert(header::CONTENT_TYPE, HeaderValue::from_str(mime.as_ref()).unwrap_or(HeaderValue::from_static("application/octet-stream")));
+    h.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    match range {
+        Some(r) => {
+            h.insert(header::CONTENT_LENGTH, HeaderValue::from(r.end - r.start));
+            h.insert(header::CONTENT_RANGE, HeaderValue::from_str(&format!("bytes {}-{}/{}", r.start, r.end - 1, meta.size)).unwrap());
+            *resp.status_mut() = StatusCode::PARTIAL_CONTENT;
+        }
+        None => { h.insert(header::CONTENT_LENGTH, HeaderValue::from(meta.size)); }
+    }
+    Ok(resp)
+}
+
+fn is_expired(expires_at: Option<i64>) -> bool {
+    expires_at.is_some_and(|t| t <= now_secs())
+}
+
+fn gone_response() -> Response {
+    (StatusCode::GONE, "This link has expired").into_response()
+}
+
+pub(crate) fn is_preview_bot(headers: &HeaderMap) -> bool {
+    headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok()).is_some_and(crate::analytics::is_link_preview)
+}
+
+// The parts of an `items` row needed to decide whether (and how) it may be served
+struct ItemRow { code: String, kind: String, value: String, expires_at: Option<i64>, max_views: Option<i64>, view_count: i64, password_hash: Option<String> }
+
+impl ItemRow {
+    fn is_view_limited(&self) -> bool { self.max_views.is_some() }
+
+    // Private items are never exposed to link previews, presigned URLs or cached redirects
+    fn is_private(&self) -> bool { self.is_view_limited() || self.password_hash.is_some() }
+
+    // 410 response if the item can no longer be accessed
+    fn unavailable(&self) -> Option<Response> {
+        if is_expired(self.expires_at) {
+            return Some(gone_response());
+        }
+        if self.max_views.is_some_and(|max| self.view_count >= max) {
+            return Some((StatusCode::GONE, "This link has reached its view limit").into_response());
+        }
+        None
+    }
+}
+
+const ITEM_ROW_COLUMNS: &str = "code, kind, value, expires_at, max_views, view_count, password_hash";
+
+fn item_row(r: &rusqlite::Row) -> rusqlite::Result<ItemRow> {
+    Ok(ItemRow {
+        code: r.get(0)?,
+        kind: r.get(1)?,
+        value: r.get(2)?,
+        expires_at: r.get(3)?,
+        max_views: r.get(4)?,
+        view_count: r.get(5)?,
+        password_hash: r.get(6)?,
+    })
+}
+
+fn load_item(conn: &Connection, column: &str, key: &str) -> rusqlite::Result<Option<ItemRow>> {
+    let sql = format!("SELECT {} FROM items WHERE {} = ?1", ITEM_ROW_COLUMNS, column);
+    conn.query_row(&sql, params![key], item_row).optional()
+}
+
+async fn find_item(state: &AppState, column: &'static str, key: String) -> Result<Option<ItemRow>, DbError> {
+    state.db.query(move |conn| load_item(conn, column, &key)).await
+}
+
+// The image item whose OG preview is stored under `key`. Previews are named after the file's stem, so
+// candidates are the files "<stem>.*" (a range, since stems may contain LIKE wildcards).
+async fn find_preview_owner(state: &AppState, key: String) -> Result<Option<ItemRow>, DbError> {
+    let Some(stem) = key.strip_prefix("previews/").and_then(|name| name.strip_suffix(".jpg")) else { return Ok(None) };
+    let (from, to) = (format!("file:{}.", stem), format!("file:{}/", stem));
+    state.db.query(move |conn| {
+        let sql = format!("SELECT {} FROM items WHERE kind = 'file' AND value >= ?1 AND value < ?2", ITEM_ROW_COLUMNS);
+        let mut stmt = conn.prepare(&sql)?;
+        let items = stmt.query_map(params![from, to], item_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(items.into_iter().find(|item| preview_key(item.value.strip_prefix("file:").unwrap_or(&item.value)) == key))
+    }).await
+}
+
+// Unlock cookies are per item and valid for a day: "<expiry>.<hmac>". The MAC covers the password
+// hash too, so changing an item's password invalidates cookies issued for the old one.
+const UNLOCK_TTL_SECS: i64 = 24 * 60 * 60;
+
+fn unlock_cookie_name(code: &str) -> String {
+    format!("ping0_unlock_{}", code)
+}
+
+fn unlock_mac(secret: &[u8], item: &ItemRow, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(format!("unlock:{}:{}:", item.code, expires).as_bytes());
+    mac.update(item.password_hash.as_deref().unwrap_or("").as_bytes());
+    mac
+}
+
+fn unlock_cookie(state: &AppState, item: &ItemRow) -> String {
+    let expires = now_secs() + UNLOCK_TTL_SECS;
+    let sig = hex::encode(unlock_mac(&state.cookie_secret, item, expires).finalize().into_bytes());
+    let secure = if state.base_url.starts_with("https://") { "; Secure" } else { "" };
+    format!("{}={}.{}; HttpOnly; SameSite=Lax; Path=/; Max-Age={}{}", unlock_cookie_name(&item.code), expires, sig, UNLOCK_TTL_SECS, secure)
+}
+
+fn is_unlocked(state: &AppState, item: &ItemRow, headers: &HeaderMap) -> bool {
+    if item.password_hash.is_none() {
+        return true;
+    }
+    let Some(cookie) = headers.typed_get::<Cookie>() else { return false };
+    let Some((expires, sig)) = cookie.get(&unlock_cookie_name(&item.code)).and_then(|v| v.split_once('.')) else { return false };
+    let (Ok(expires), Ok(sig)) = (expires.parse::<i64>(), hex::decode(sig)) else { return false };
+    expires > now_secs() && unlock_mac(&state.cookie_secret, item, expires).verify_slice(&sig).is_ok()
+}
+
+fn password_prompt(code: &str, error: Option<&str>) -> Response {
+    let tpl = PasswordPromptTemplate { code: code.to_string(), error: error.map(str::to_string) };
+    let status = if error.is_some() { StatusCode::UNAUTHORIZED } else { StatusCode::OK };
+    (status, Html(tpl.render().unwrap_or_else(|_| "Template error".to_string()))).into_response()
+}
+
+enum ViewOutcome { Counted, Burn, Exhausted }
+
+// How long a burned item's row outlives its content, so revisits get 410 rather than 404
+const BURNED_ROW_TTL_SECS: i64 = 24 * 60 * 60;
+
+// Atomically use up one view; the limit check and increment happen in a single write transaction
+fn record_view(conn: &mut Connection, code: &str) -> rusqlite::Result<ViewOutcome> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let updated = tx.execute(
+        "UPDATE items SET view_count = view_count + 1 WHERE code = ?1 AND (max_views IS NULL OR view_count < max_views)",
+        params![code],
+    )?;
+    if updated == 0 {
+        return Ok(ViewOutcome::Exhausted);
+    }
+    let (count, max, burn): (i64, Option<i64>, bool) = tx.query_row(
+        "SELECT view_count, max_views, burn_after_reading FROM items WHERE code = ?1",
+        params![code],
+        |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+    )?;
+    let burned = burn && max.is_some_and(|m| count >= m);
+    if burned {
+        // The reaper deletes the row once it expires
+        tx.execute(
+            "UPDATE items SET expires_at = MIN(COALESCE(expires_at, ?2), ?2) WHERE code = ?1",
+            params![code, now_secs() + BURNED_ROW_TTL_SECS],
+        )?;
+    }
+    tx.commit()?;
+    Ok(if burned { ViewOutcome::Burn } else { ViewOutcome::Counted })
+}
+
+async fn count_view(state: &AppState, code: &str) -> Result<ViewOutcome, DbError> {
+    let code = code.to_string();
+    state.db.query(move |conn| record_view(conn, &code)).await
+}
+
+// Delete an item's files right away (burn after reading); an already opened stream keeps working.
+// The spent row stays behind until it expires (see record_view).
+async fn burn_item(state: &AppState, item: &ItemRow) {
+    delete_item_files(state.storage.as_ref(), &item.kind, &item.value).await;
+    tracing::info!("Burned {} after its last view", item.code);
+}
+
+fn serve_error(key: &str, e: io::Error) -> Response {
+    match e.kind() {
+        io::ErrorKind::NotFound | io::ErrorKind::InvalidInput => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        _ => { tracing::error!("serve {}: {}", key, e); (StatusCode::INTERNAL_SERVER_ERROR, "IO Error").into_response() }
+    }
+}
+
+// Serve the file behind an item, counting the view (and burning the item after its last one).
+// Preview bots never use up views, and never get the content of view-limited items.
+async fn deliver_file(state: &AppState, item: &ItemRow, key: &str, headers: &HeaderMap) -> Response {
+    let limited = item.is_view_limited();
+    if is_preview_bot(headers) {
+        if item.is_private() { return (StatusCode::FORBIDDEN, "Not available to link previews").into_response(); }
+        return serve_object(state.storage.as_ref(), key, headers, Delivery::Normal).await.unwrap_or_else(|e| serve_error(key, e));
+    }
+    // Follow-up range requests of a player or download manager are part of the same view
+    let continuation = !limited && parse_range(headers, u64::MAX).is_some_and(|r| r.start > 0);
+    let outcome = if continuation { Ok(ViewOutcome::Counted) } else { count_view(state, &item.code).await };
+    let delivery = if item.is_private() { Delivery::Strict } else { Delivery::Normal };
+    match outcome {
+        Ok(ViewOutcome::Counted) => serve_object(state.storage.as_ref(), key, headers, delivery).await.unwrap_or_else(|e| serve_error(key, e)),
+        Ok(ViewOutcome::Burn) => {
+            // Open the stream before deleting so this last reader still gets the whole file
+            let resp = serve_object(state.storage.as_ref(), key, headers, Delivery::Strict).await;
+            burn_item(state, item).await;
+            resp.unwrap_or_else(|e| serve_error(key, e))
+        }
+        Ok(ViewOutcome::Exhausted) => (StatusCode::GONE, "This link has reached its view limit").into_response(),
+        Err(e) => db::error_response(&format!("record view {}", item.code), e),
+    }
+}
+
+pub async fn files_handler(State(state): State<AppState>, Path(key): Path<String>, headers: HeaderMap) -> Response {
+    // Files belonging to an item go through the same expiry and view-limit checks as /s/:code;
+    // the only other keys served are image previews of public items. Anything else in storage
+    // (tus parts, unfinished uploads) is not reachable.
+    match find_item(&state, "value", format!("file:{}", key)).await {
+        Ok(Some(item)) => {
+            if let Some(resp) = item.unavailable() { return resp; }
+            if !is_unlocked(&state, &item, &headers) { return Redirect::to(&format!("/s/{}", item.code)).into_response(); }
+            deliver_file(&state, &item, &key, &headers).await
+        }
+        Ok(None) => match find_preview_owner(&state, key.clone()).await {
+            Ok(Some(item)) if !item.is_private() => {
+                if let Some(resp) = item.unavailable() { return resp; }
+                serve_object(state.storage.as_ref(), &key, &headers, Delivery::Normal).await.unwrap_or_else(|e| serve_error(&key, e))
+            }
+            Ok(_) => (StatusCode::NOT_FOUND, "File not found").into_response(),
+            Err(e) => db::error_response(&format!("preview lookup {}", key), e),
+        },
+        Err(e) => db::error_response(&format!("files lookup {}", key), e),
+    }
+}
+
+pub async fn short_handler(State(state): State<AppState>, Path(code): Path<String>, ClientIp(ip): ClientIp, headers: HeaderMap) -> axum::response::Response {
+    let item = match find_item(&state, "code", code.clone()).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => return db::error_response(&format!("load item {}", code), e),
+    };
+    if let Some(resp) = item.unavailable() {
+        return resp;
+    }
+    if !is_unlocked(&state, &item, &headers) {
+        return password_prompt(&item.code, None);
+    }
+    let resp = serve_short(&state, &item, &code, &headers).await;
+    // A click is a visit that got the link or the content: not a spent view limit or a missing file
+    if resp.status().is_success() || resp.status().is_redirection() {
+        let (geoip, click_code) = (state.geoip.clone(), item.code.clone());
+        let clicked = state.db.query(move |conn| crate::analytics::record_click(conn, geoip.as_deref(), &click_code, &headers, Some(ip))).await;
+        if let Err(e) = clicked {
+            tracing::warn!("record click {}: {}", item.code, e);
+        }
+    }
+    resp
+}
+
+// What /s/:code answers for an item that is available and unlocked
+async fn serve_short(state: &AppState, item: &ItemRow, code: &str, headers: &HeaderMap) -> Response {
+    let limited = item.is_view_limited();
+
+    match item.kind.as_str() {
+        "url" if limited => {
+            if is_preview_bot(headers) {
+                return Html("<!DOCTYPE html><title>ping0</title>".to_string()).into_response();
+            }
+            // Temporary redirects so browsers come back (and count) every time
+            match count_view(state, &item.code).await {
+                Ok(ViewOutcome::Counted) => Redirect::temporary(&item.value).into_response(),
+                Ok(ViewOutcome::Burn) => { burn_item(state, item).await; Redirect::temporary(&item.value).into_response() }
+                Ok(ViewOutcome::Exhausted) => (StatusCode::GONE, "This link has reached its view limit").into_response(),
+                Err(e) => db::error_response(&format!("record view {}", item.code), e),
+            }
+        }
+        "url" => {
+            if !is_preview_bot(headers)
+                && let Err(e) = count_view(state, &item.code).await { tracing::warn!("record view {}: {}", item.code, e); }
+            // Never permanent: a cached redirect would skip click analytics and outlive expiry or deletion
+            Redirect::temporary(&item.value).into_response()
+        }
         "file" => {
-            let filename = value.strip_prefix("file:").unwrap_or(&value);
+            let filename = item.value.strip_prefix("file:").unwrap_or(&item.value);
             if let Some(ext) = StdPath::new(filename).extension().and_then(|e| e.to_str()) {
                 let mime = mime_from_path(filename).first_or_octet_stream();
                 if is_image_ext(ext) || ext.eq_ignore_ascii_case("svg") {
                     let page_url = format!("{}/s/{}", state.base_url, code);
                     let image_url_full = format!("{}/files/{}", state.base_url, filename);
-                    // For raster images: if original <= 1MB, use original; else generate a preview
-                    let og_image_url = if !ext.eq_ignore_ascii_case("svg") {
-                        let original_fs_path = StdPath::new("uploads").join(filename);
-                        let original_is_small = std::fs::metadata(&original_fs_path)
-                            .map(|m| m.len() as usize <= PREVIEW_MAX_BYTES)
-                            .unwrap_or(false);
+                    // For raster images: if original <= 1MB, use original; else generate a preview.
+                    // Private images get no OG image at all, so previews never reveal them.
+                    let og_image_url = if item.is_private() {
+                        None
+                    } else if !ext.eq_ignore_ascii_case("svg") {
+                        let original_is_small = matches!(
+                            state.storage.stat(filename).await,
+                            Ok(Some(meta)) if meta.size as usize <= PREVIEW_MAX_BYTES
+                        );
                         if original_is_small {
-                            image_url_full.clone()
+                            Some(image_url_full.clone())
                         } else {
-                            let preview_dir = StdPath::new("uploads").join("previews");
-                            let preview_name = make_preview_filename(filename);
-                            let preview_fs_path = preview_dir.join(&preview_name);
-                            let preview_web_path = format!("previews/{}", preview_name);
-                            if !preview_fs_path.exists() {
-                                let _ = std::fs::create_dir_all(&preview_dir);
-                                let _ = try_generate_preview(&original_fs_path, &preview_fs_path);
-                            }
-                            if preview_fs_path.exists() {
-                                format!("{}/files/{}", state.base_url, preview_web_path)
-                            } else {
-                                image_url_full.clone()
+                            match ensure_preview(state.storage.as_ref(), filename).await {
+                                Some(key) => Some(format!("{}/files/{}", state.base_url, key)),
+                                None => Some(image_url_full.clone()),
                             }
                         }
                     } else {
                         // For SVG use the original (usually tiny)
-                        image_url_full.clone()
+                        Some(image_url_full.clone())
                     };
                     // Content negotiation: if the client wants HTML, return the OG preview page;
                     // otherwise (e.g., Markdown image fetch), redirect to the raw image.
@@ -422,16 +949,10 @@ pub async fn short_handler(State(state): State<AppState>, Path(code): Path<Strin
                     let wants_html = accept.contains("text/html");
                     if !wants_html {
                         // For non-HTML (e.g., direct image fetch), stream the file instead of redirecting to avoid user-agent caching/transform issues
-                        let fs_path = StdPath::new("uploads").join(filename);
-                        if let Ok(bytes) = std::fs::read(&fs_path) {
-                            let mut resp = axum::response::Response::new(bytes.into());
-                            resp.headers_mut().insert(axum::http::header::CONTENT_TYPE, axum::http::HeaderValue::from_str(mime.as_ref()).unwrap_or(axum::http::HeaderValue::from_static("image/jpeg")));
-                            return resp;
-                        }
-                        return Redirect::permanent(&image_url_full).into_response();
+                        return deliver_file(state, item, filename, headers).await;
                     }
                     let tpl = ImageOgTemplate {
-                        og_image_url: og_image_url,
+                        og_image_url,
                         full_image_url: image_url_full,
                         page_url,
                         title: "Shared Image".to_string(),
@@ -451,235 +972,1047 @@ pub async fn short_handler(State(state): State<AppState>, Path(code): Path<Strin
     }
 }
 
-fn hash_with_salt(password: &str, salt: &str) -> String {
+#[derive(Deserialize)]
+pub struct UnlockForm { pub password: String }
+
+// Wrong passwords count against the client IP and the item, like failed admin logins
+pub async fn short_unlock(State(state): State<AppState>, ClientIp(ip): ClientIp, Path(code): Path<String>, Form(form): Form<UnlockForm>) -> Response {
+    let item = match find_item(&state, "code", code.clone()).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => return db::error_response(&format!("load item {}", code), e),
+    };
+    if let Some(resp) = item.unavailable() {
+        return resp;
+    }
+    let Some(hash) = item.password_hash.clone() else {
+        return Redirect::to(&format!("/s/{}", code)).into_response();
+    };
+    let throttle_keys = [throttle::ip_key(ip), throttle::item_key(&item.code)];
+    let keys = throttle_keys.clone();
+    match state.db.call(move |conn| begin_login_attempt(conn, &keys)).await {
+        Ok(None) => {}
+        Ok(Some(denied)) => return denied,
+        Err(e) => return e.into_response(),
+    }
+    if !crate::password::verify_password_async(form.password, hash).await {
+        tracing::warn!("Wrong password for item {} from {}", item.code, ip);
+        return password_prompt(&item.code, Some("Wrong password"));
+    }
+    if let Err(e) = state.db.call(move |conn| login_succeeded(conn, &throttle_keys)).await {
+        tracing::error!("unlock throttle: {}", e);
+    }
+    let mut headers = HeaderMap::new();
+    headers.insert(header::SET_COOKIE, HeaderValue::from_str(&unlock_cookie(&state, &item)).unwrap());
+    (headers, Redirect::to(&format!("/s/{}", code))).into_response()
+}
+
+// Admin passwords used to be a single SHA-256 over salt+password; such rows are only ever verified,
+// then replaced with an Argon2id hash on the next successful login
+fn legacy_sha256_matches(password: &str, salt: &str, hash: &str) -> bool {
+    let Ok(expected) = hex::decode(hash) else { return false };
     let mut hasher = Sha256::new();
     hasher.update(salt.as_bytes());
     hasher.update(password.as_bytes());
-    let result = hasher.finalize();
-    hex::encode(result)
+    // Constant time, so response timing doesn't reveal how much of the hash matched
+    hasher.finalize().as_slice().ct_eq(&expected).into()
 }
 
-fn generate_token(len: usize) -> String {
-    rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(len)
-        .map(char::from)
-        .collect()
+// (password matches, hash should be upgraded)
+async fn verify_admin_password(password: String, hash: String, salt: String) -> (bool, bool) {
+    if hash.starts_with("$argon2") {
+        (crate::password::verify_password_async(password, hash).await, false)
+    } else {
+        let ok = !salt.is_empty() && legacy_sha256_matches(&password, &salt, &hash);
+        // As slow as the other paths, so legacy accounts don't stand out either
+        crate::password::verify_dummy(password).await;
+        (ok, ok)
+    }
 }
 
-async fn require_admin_token(db_path: &str, token_opt: Option<&str>) -> bool {
-    if let Some(token) = token_opt {
-        if let Ok(conn) = Connection::open(db_path) {
-            if let Ok(mut stmt) = conn.prepare("SELECT 1 FROM sessions WHERE token = ?1") {
-                let exists: Result<i32, _> = stmt.query_row(params![token], |r| r.get(0));
-                return exists.is_ok();
-            }
+// User agents are only kept for the sessions page, so very long ones are cut
+const MAX_SESSION_UA_LEN: usize = 256;
+
+// Why an admin page was refused
+enum Denied {
+    NotLoggedIn,
+    RoleTooLow,
+}
+
+impl IntoResponse for Denied {
+    fn into_response(self) -> Response {
+        match self {
+            Denied::NotLoggedIn => Redirect::to("/admin/login").into_response(),
+            Denied::RoleTooLow => (StatusCode::FORBIDDEN, "Your role doesn't allow this").into_response(),
         }
     }
-    false
 }
 
-pub async fn admin_login_get() -> Html<String> {
-    Html(AdminLoginTemplate.render().unwrap_or_else(|_| "Template error".to_string()))
+// The logged-in admin, if their role is at least `min`
+fn require_role(conn: &Connection, cookie: Option<TypedHeader<Cookie>>, min: Role) -> Result<AdminUser, Denied> {
+    match admins::session_user(conn, extract_admin_token(cookie).as_deref()) {
+        Some(user) if user.role.at_least(min) => Ok(user),
+        Some(_) => Err(Denied::RoleTooLow),
+        None => Err(Denied::NotLoggedIn),
+    }
+}
+
+// require_role for handlers that have async work to do after the check
+async fn authorize(state: &AppState, cookie: Option<TypedHeader<Cookie>>, min: Role) -> Result<AdminUser, Response> {
+    match state.db.call(move |conn| require_role(conn, cookie, min)).await {
+        Ok(Ok(user)) => Ok(user),
+        Ok(Err(denied)) => Err(denied.into_response()),
+        Err(e) => Err(e.into_response()),
+    }
+}
+
+fn render_login(state: &AppState, conn: &Connection, csrf: String, error: Option<String>) -> Response {
+    let needs_setup = admins::admin_exists(conn).is_ok_and(|exists| !exists);
+    let passkeys = state.webauthn.is_some() && passkeys::any_registered(conn).unwrap_or(false);
+    let sso = state.oidc.as_ref().map(|cfg| cfg.provider_name.clone());
+    let status = if error.is_some() { StatusCode::FORBIDDEN } else { StatusCode::OK };
+    let tpl = AdminLoginTemplate { csrf, needs_setup, passkeys, sso, error };
+    (status, Html(tpl.render().unwrap_or_else(|_| "Template error".to_string()))).into_response()
+}
+
+pub async fn admin_login_get(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>) -> Response {
+    state.db.clone().respond(move |conn| render_login(&state, conn, csrf, None)).await
+}
+
+// Single sign-on: off to the identity provider
+pub async fn admin_oidc_login(State(state): State<AppState>) -> Response {
+    let Some(cfg) = state.oidc.clone() else { return (StatusCode::NOT_FOUND, "Not found").into_response() };
+    match oidc::start(&state, &cfg).await {
+        Ok((url, cookie)) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::SET_COOKIE, HeaderValue::from_str(&cookie).unwrap());
+            (headers, Redirect::to(&url)).into_response()
+        }
+        Err(e) => { tracing::error!("OIDC discovery: {}", e); (StatusCode::BAD_GATEWAY, "The identity provider is not reachable").into_response() }
+    }
 }
 
 #[derive(Deserialize)]
+pub struct OidcCallbackQuery { pub code: Option<String>, pub state: Option<String>, pub error: Option<String>, pub error_description: Option<String> }
+
+// The IdP sends the browser back here; SSO logins skip the TOTP step, the IdP handles MFA
+pub async fn admin_oidc_callback(
+    State(state): State<AppState>,
+    Extension(CsrfToken(csrf)): Extension<CsrfToken>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Query(q): Query<OidcCallbackQuery>,
+) -> Response {
+    let Some(cfg) = state.oidc.clone() else { return (StatusCode::NOT_FOUND, "Not found").into_response() };
+    let (Some(code), Some(returned_state)) = (q.code, q.state) else {
+        let reason = q.error_description.or(q.error).unwrap_or_else(|| "no code".to_string());
+        tracing::warn!("OIDC callback without a code: {}", reason);
+        return state.db.clone().respond(move |conn| render_login(&state, conn, csrf, Some(format!("Sign-in was cancelled or failed: {}", reason)))).await;
+    };
+    let flow = headers.typed_get::<Cookie>().and_then(|c| c.get("ping0_oidc").map(str::to_string));
+    let identity = oidc::finish(&state, &cfg, flow.as_deref(), &code, &returned_state).await;
+    state.db.clone().respond(move |conn| {
+        let mut resp_headers = HeaderMap::new();
+        resp_headers.insert(header::SET_COOKIE, HeaderValue::from_str(&oidc::clear_flow_cookie()).unwrap());
+        match identity.and_then(|identity| oidc::upsert_user(conn, &identity)) {
+            Ok(admin_id) => start_session(conn, admin_id, ip, &headers, resp_headers),
+            Err(AccountError::Rejected(e)) => (resp_headers, render_login(&state, conn, csrf, Some(e))).into_response(),
+            Err(AccountError::Internal(e)) => { tracing::error!("OIDC login: {}", e); (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response() }
+        }
+    }).await
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct AdminLoginForm { pub username: String, pub password: String }
 
-pub async fn admin_login_post(State(state): State<AppState>, Form(f): Form<AdminLoginForm>) -> impl IntoResponse {
-    let conn = Connection::open(&state.db_path).unwrap();
-    let count: i64 = conn.query_row("SELECT COUNT(*) FROM admin", [], |r| r.get(0)).unwrap_or(0);
-    if count == 0 {
-        let salt = generate_token(16);
-        let hash = hash_with_salt(&f.password, &salt);
-        let _ = conn.execute("INSERT INTO admin (id, username, password_hash, salt) VALUES (1, ?1, ?2, ?3)", params![f.username, hash, salt]);
-    }
-    let row = conn
-        .prepare("SELECT password_hash, salt FROM admin WHERE username = ?1")
-        .and_then(|mut s| s.query_row(params![f.username], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?))));
-    let (hash, salt) = match row { Ok(v) => v, Err(_) => return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response() };
-    if hash != hash_with_salt(&f.password, &salt) { return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response(); }
-
-    let token = generate_token(48);
-    let _ = conn.execute("INSERT INTO sessions (token, created_at) VALUES (?1, strftime('%s','now'))", params![token.clone()]);
-    let mut headers = HeaderMap::new();
-    let cookie = format!("ping0_admin={}; HttpOnly; SameSite=Lax; Path=/; Max-Age=2592000", token);
-    headers.insert(axum::http::header::SET_COOKIE, HeaderValue::from_str(&cookie).unwrap());
-    (headers, Redirect::to("/admin")).into_response()
+#[utoipa::path(
+    post,
+    path = "/admin/login",
+    tag = "admin",
+    request_body(content = AdminLoginForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Logged in; sets the `ping0_admin` session cookie and redirects to /admin"),
+        (status = 200, description = "Password accepted, two-factor login on: the page asks for a code (POST /admin/login/2fa)", content_type = "text/html"),
+        (status = 401, description = "Invalid credentials", body = String),
+        (status = 429, description = "Too many failed attempts from this IP or for this username; see `Retry-After`", body = String),
+    )
+)]
+pub async fn admin_login_post(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, ClientIp(ip): ClientIp, headers: HeaderMap, Form(f): Form<AdminLoginForm>) -> Response {
+    let throttle_keys = [throttle::ip_key(ip), throttle::user_key(&f.username)];
+    let keys = throttle_keys.clone();
+    match state.db.call(move |conn| begin_login_attempt(conn, &keys)).await {
+        Ok(None) => {}
+        Ok(Some(denied)) => return denied,
+        Err(e) => return e.into_response(),
+    }
+    let username = f.username.clone();
+    let row = state.db.query(move |conn| {
+        conn.query_row(
+            "SELECT id, password_hash, salt FROM admin WHERE username = ?1 AND disabled_at IS NULL",
+            params![username],
+            |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?)),
+        )
+        .optional()
+    }).await;
+    let row = match row {
+        Ok(row) => row,
+        Err(e) => return db::error_response("admin login", e),
+    };
+    // The attempt is already counted, so failures just return
+    let Some((admin_id, hash, salt)) = row else {
+        crate::password::verify_dummy(f.password).await;
+        tracing::warn!("Failed admin login for '{}' from {}", f.username, ip);
+        return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+    };
+    let (valid, upgrade) = verify_admin_password(f.password.clone(), hash, salt).await;
+    if !valid {
+        tracing::warn!("Failed admin login for '{}' from {}", f.username, ip);
+        return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+    }
+    let new_hash = match upgrade {
+        true => crate::password::hash_password_async(f.password.clone()).await.map_err(|e| tracing::error!("{}", e)).ok(),
+        false => None,
+    };
+    state.db.clone().respond(move |conn| {
+        login_succeeded(conn, &throttle_keys);
+        if let Some(new_hash) = new_hash {
+            let _ = conn.execute("UPDATE admin SET password_hash = ?1, salt = '' WHERE id = ?2", params![new_hash, admin_id]);
+            tracing::info!("Upgraded password hash of admin '{}' to Argon2id", f.username);
+        }
+        match twofactor::is_enabled(conn, admin_id) {
+            Ok(false) => {}
+            Ok(true) => match twofactor::challenge_cookie(&state, conn, admin_id) {
+                Ok(cookie) => {
+                    let mut headers = HeaderMap::new();
+                    headers.insert(header::SET_COOKIE, HeaderValue::from_str(&cookie).unwrap());
+                    return (headers, render_login_code(csrf, None)).into_response();
+                }
+                Err(e) => { tracing::error!("2fa challenge: {}", e); return (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response(); }
+            },
+            Err(e) => { tracing::error!("2fa status: {}", e); return (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response(); }
+        }
+        start_session(conn, admin_id, ip, &headers, HeaderMap::new())
+    }).await
 }
 
-pub async fn admin_logout(State(state): State<AppState>, cookie: Option<TypedHeader<Cookie>>) -> impl IntoResponse {
-    if let Some(tok) = extract_admin_token(cookie) {
-        if let Ok(conn) = Connection::open(&state.db_path) {
-            let _ = conn.execute("DELETE FROM sessions WHERE token = ?1", params![tok]);
+// Logs the user in: a fresh session token, whatever session cookie the browser sent before is ended
+fn start_session(conn: &Connection, admin_id: i64, ip: IpAddr, headers: &HeaderMap, mut resp_headers: HeaderMap) -> Response {
+    match session_cookie(conn, admin_id, ip, headers) {
+        Ok(cookie) => {
+            resp_headers.append(axum::http::header::SET_COOKIE, cookie);
+            (resp_headers, Redirect::to("/admin")).into_response()
+        }
+        Err(e) => { tracing::error!("create session: {}", e); (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response() }
+    }
+}
+
+// Set-Cookie value for a new session
+fn session_cookie(conn: &Connection, admin_id: i64, ip: IpAddr, headers: &HeaderMap) -> rusqlite::Result<HeaderValue> {
+    let previous = headers.typed_get::<Cookie>().and_then(|c| c.get("ping0_admin").map(str::to_string));
+    let user_agent: Option<String> = headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok()).map(|ua| ua.chars().take(MAX_SESSION_UA_LEN).collect());
+    let token = admins::create_session(conn, admin_id, previous.as_deref(), Some(&ip.to_string()), user_agent.as_deref())?;
+    let cookie = format!("ping0_admin={}; HttpOnly; SameSite=Lax; Path=/; Max-Age={}", token, admins::session_max_age());
+    Ok(HeaderValue::from_str(&cookie).unwrap())
+}
+
+// Counts a login attempt against `keys` (the client IP first, then the account), or a 429 with
+// Retry-After when the IP or account is locked out after too many failed logins
+fn begin_login_attempt(conn: &mut Connection, keys: &[String]) -> Option<Response> {
+    match throttle::begin_attempt(conn, keys) {
+        Ok(None) => None,
+        Ok(Some(wait)) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(wait));
+            let message = format!("Too many failed attempts. Try again in {} seconds.", wait);
+            Some((StatusCode::TOO_MANY_REQUESTS, headers, message).into_response())
+        }
+        Err(e) => {
+            tracing::error!("login throttle: {}", e);
+            Some((StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response())
         }
     }
+}
+
+// The attempt was good after all: it no longer counts against the IP, and the account starts over
+fn login_succeeded(conn: &Connection, keys: &[String]) {
+    let result = throttle::forgive(conn, &keys[0]).and_then(|()| keys[1..].iter().try_for_each(|key| throttle::clear(conn, key)));
+    if let Err(e) = result {
+        tracing::error!("login throttle: {}", e);
+    }
+}
+
+fn render_login_code(csrf: String, error: Option<String>) -> Response {
+    let status = if error.is_some() { StatusCode::UNAUTHORIZED } else { StatusCode::OK };
+    (status, Html(AdminLoginCodeTemplate { csrf, error }.render().unwrap_or_else(|_| "Template error".to_string()))).into_response()
+}
+
+pub async fn admin_login_code_get(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, headers: HeaderMap) -> Response {
+    state.db.clone().respond(move |conn| {
+        if twofactor::challenge_user(&state, conn, &headers).is_none() {
+            return Redirect::to("/admin/login").into_response();
+        }
+        render_login_code(csrf, None)
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct AdminCodeForm { pub code: String }
+
+// Second login step for users with two-factor login
+pub async fn admin_login_code_post(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, ClientIp(ip): ClientIp, headers: HeaderMap, Form(f): Form<AdminCodeForm>) -> Response {
+    state.db.clone().respond(move |conn| {
+        let Some(admin_id) = twofactor::challenge_user(&state, conn, &headers) else {
+            return Redirect::to("/admin/login").into_response();
+        };
+        let throttle_keys = [throttle::ip_key(ip), throttle::second_factor_key(admin_id)];
+        if let Some(denied) = begin_login_attempt(conn, &throttle_keys) {
+            return denied;
+        }
+        match twofactor::verify_code(conn, admin_id, &f.code) {
+            Ok(true) => {
+                login_succeeded(conn, &throttle_keys);
+                let mut resp_headers = HeaderMap::new();
+                resp_headers.insert(header::SET_COOKIE, HeaderValue::from_str(&twofactor::clear_challenge_cookie()).unwrap());
+                start_session(conn, admin_id, ip, &headers, resp_headers)
+            }
+            Ok(false) => {
+                tracing::warn!("Wrong two-factor code for admin {} from {}", admin_id, ip);
+                render_login_code(csrf, Some("Wrong or already used code".to_string()))
+            }
+            Err(e) => { tracing::error!("2fa verify: {}", e); (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response() }
+        }
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct SetupQuery { pub token: Option<String> }
+
+fn render_setup(csrf: String, token: String, error: Option<String>) -> Response {
+    let status = if error.is_some() { StatusCode::BAD_REQUEST } else { StatusCode::OK };
+    (status, Html(AdminSetupTemplate { csrf, token, error }.render().unwrap_or_else(|_| "Template error".to_string()))).into_response()
+}
+
+// First-admin setup, only reachable with the token from the startup log
+pub async fn admin_setup_get(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, Query(q): Query<SetupQuery>) -> Response {
+    if state.db.query(|conn| admins::admin_exists(conn)).await.unwrap_or(true) {
+        return Redirect::to("/admin/login").into_response();
+    }
+    render_setup(csrf, q.token.unwrap_or_default(), None)
+}
+
+#[derive(Deserialize)]
+pub struct AdminSetupForm { pub token: String, pub username: String, pub password: String }
+
+pub async fn admin_setup_post(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, Form(f): Form<AdminSetupForm>) -> Response {
+    let hash = admins::hash_new_password(&f.username, f.password.clone()).await;
+    state.db.respond(move |conn| match hash.and_then(|hash| crate::setup::complete_setup(conn, &f.token, &f.username, &hash)) {
+        Ok(()) => Redirect::to("/admin/login").into_response(),
+        Err(AccountError::Rejected(e)) => render_setup(csrf, f.token, Some(e)),
+        Err(AccountError::Internal(e)) => { tracing::error!("admin setup: {}", e); (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response() }
+    }).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/logout",
+    tag = "admin",
+    responses((status = 303, description = "Session ended; redirects to /admin/login")),
+    security(("session" = []))
+)]
+pub async fn admin_logout(State(state): State<AppState>, cookie: Option<TypedHeader<Cookie>>) -> impl IntoResponse {
+    if let Some(tok) = extract_admin_token(cookie)
+        && let Err(e) = state.db.query(move |conn| admins::end_session(conn, &tok)).await {
+            tracing::error!("logout: {}", e);
+        }
     let mut headers = HeaderMap::new();
     headers.insert(axum::http::header::SET_COOKIE, HeaderValue::from_static("ping0_admin=; Max-Age=0; Path=/"));
     (headers, Redirect::to("/admin/login")).into_response()
 }
 
 #[debug_handler]
-pub async fn admin_home(State(state): State<AppState>, cookie: Option<TypedHeader<Cookie>>) -> Response {
-    if !require_admin_token(&state.db_path, extract_admin_token(cookie).as_deref()).await {
-        return Redirect::to("/admin/login").into_response();
-    }
-    Html(AdminHomeTemplate.render().unwrap_or_else(|_| "Template error".to_string())).into_response()
+pub async fn admin_home(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    let user = match authorize(&state, cookie, Role::Viewer).await { Ok(user) => user, Err(resp) => return resp };
+    let tpl = AdminHomeTemplate { csrf, username: user.username, role: user.role.as_str(), is_owner: user.role == Role::Owner };
+    Html(tpl.render().unwrap_or_else(|_| "Template error".to_string())).into_response()
 }
 
 #[debug_handler]
-pub async fn admin_items(State(state): State<AppState>, cookie: Option<TypedHeader<Cookie>>) -> Response {
-    if !require_admin_token(&state.db_path, extract_admin_token(cookie).as_deref()).await {
-        return Redirect::to("/admin/login").into_response();
-    }
-    let conn = Connection::open(&state.db_path).unwrap();
-    let mut stmt = conn.prepare("SELECT code, kind, value, created_at FROM items ORDER BY created_at DESC LIMIT 500").unwrap();
-    let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?, r.get::<_, i64>(3)?))).unwrap();
+pub async fn admin_items(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    let user = match authorize(&state, cookie, Role::Viewer).await { Ok(user) => user, Err(resp) => return resp };
+    let rows = state.db.query(|conn| {
+        let mut stmt = conn.prepare("SELECT code, kind, value, created_at, expires_at, view_count, max_views, password_hash IS NOT NULL FROM items ORDER BY created_at DESC LIMIT 500")?;
+        let rows = stmt.query_map([], |r| Ok(AdminItem {
+            code: r.get(0)?,
+            kind: r.get(1)?,
+            value: r.get(2)?,
+            created_at: r.get(3)?,
+            expires_at: r.get(4)?,
+            view_count: r.get(5)?,
+            max_views: r.get(6)?,
+            protected: r.get(7)?,
+            mime: None,
+        }))?;
+        Ok(rows.flatten().collect::<Vec<_>>())
+    }).await;
+    let rows = match rows { Ok(rows) => rows, Err(e) => return db::error_response("list items", e) };
     let mut items: Vec<AdminItem> = Vec::new();
-    for row in rows {
-        if let Ok((code, kind, value, created_at)) = row {
-            let mime = if kind == "file" {
-                value.strip_prefix("file:")
-                    .map(|fname| mime_from_path(fname).first_or_octet_stream().to_string())
-            } else { None };
-            items.push(AdminItem { code, kind, value, created_at, mime });
+    for mut item in rows {
+        if item.kind == "file" {
+            item.mime = item.value.strip_prefix("file:")
+                .map(|fname| mime_from_path(fname).first_or_octet_stream().to_string());
+        }
+        items.push(item);
+    }
+    Html(AdminItemsTemplate { csrf, items, can_moderate: user.role.at_least(Role::Moderator) }.render().unwrap_or_else(|_| "Template error".to_string())).into_response()
+}
+
+// Days shown in the per-item click histogram
+const STATS_DAYS: i64 = 30;
+
+pub async fn admin_item_stats(State(state): State<AppState>, Path(code): Path<String>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    if let Err(resp) = authorize(&state, cookie, Role::Viewer).await { return resp; }
+    let lookup = code.clone();
+    let found = state.db.query(move |conn| {
+        let item = conn.query_row("SELECT kind, value FROM items WHERE code = ?1", params![lookup], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?))).optional()?;
+        match item {
+            Some(item) => Ok(Some((item, crate::analytics::item_stats(conn, &lookup, STATS_DAYS)?))),
+            None => Ok(None),
+        }
+    }).await;
+    let ((kind, value), stats) = match found {
+        Ok(Some(v)) => v,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => return db::error_response(&format!("stats {}", code), e),
+    };
+    let peak = stats.daily.iter().map(|(_, n)| *n).max().unwrap_or(0).max(1);
+    let days = stats.daily.iter().map(|&(day, count)| StatsDay {
+        label: crate::analytics::day_label(day),
+        count,
+        width_pct: count * 100 / peak,
+    }).collect();
+    let tpl = AdminStatsTemplate {
+        code,
+        kind,
+        value,
+        total: stats.total,
+        humans: stats.total - stats.bots,
+        bots: stats.bots,
+        unique_visitors: stats.unique_visitors,
+        referrers: stats.referrers,
+        families: stats.families,
+        countries: stats.countries,
+        regions: stats.regions,
+        days,
+    };
+    Html(tpl.render().unwrap_or_else(|_| "Template error".to_string())).into_response()
+}
+
+fn render_admin_keys(conn: &Connection, csrf: String, new_token: Option<String>, error: Option<String>) -> Response {
+    let keys = match apikeys::list_keys(conn) {
+        Ok(keys) => keys,
+        Err(e) => return db::error_response("list keys", e),
+    };
+    let keys = keys.into_iter().map(|k| AdminKey {
+        id: k.id,
+        name: k.name,
+        prefix: k.prefix,
+        scopes: k.scopes,
+        created_at: k.created_at,
+        last_used_at: k.last_used_at,
+        revoked: k.revoked_at.is_some(),
+        item_count: k.item_count,
+    }).collect();
+    Html(AdminKeysTemplate { csrf, keys, new_token, error }.render().unwrap_or_else(|_| "Template error".to_string())).into_response()
+}
+
+pub async fn admin_keys(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    state.db.respond(move |conn| {
+        if let Err(denied) = require_role(conn, cookie, Role::Owner) { return denied.into_response(); }
+        render_admin_keys(conn, csrf, None, None)
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct AdminKeyForm {
+    pub name: String,
+    pub scope_create: Option<String>,
+    pub scope_read: Option<String>,
+    pub scope_delete: Option<String>,
+    pub scope_admin: Option<String>,
+}
+
+pub async fn admin_create_key(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>, Form(form): Form<AdminKeyForm>) -> Response {
+    state.db.respond(move |conn| {
+        if let Err(denied) = require_role(conn, cookie, Role::Owner) { return denied.into_response(); }
+        let scopes: Vec<Scope> = [
+            (Scope::Create, &form.scope_create),
+            (Scope::Read, &form.scope_read),
+            (Scope::Delete, &form.scope_delete),
+            (Scope::Admin, &form.scope_admin),
+        ].into_iter().filter(|(_, checked)| checked.is_some()).map(|(scope, _)| scope).collect();
+        let name = form.name.trim();
+        if name.is_empty() || scopes.is_empty() {
+            return render_admin_keys(conn, csrf, None, Some("A key needs a name and at least one scope".to_string()));
         }
+        match apikeys::create_key(conn, name, &scopes) {
+            Ok((id, token)) => {
+                tracing::info!("API key {} ({}) created with scopes {}", id, name, apikeys::format_scopes(&scopes));
+                render_admin_keys(conn, csrf, Some(token), None)
+            }
+            Err(e) => db::error_response("create key", e),
+        }
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct AdminRevokeForm { pub delete_items: Option<String> }
+
+pub async fn admin_revoke_key(State(state): State<AppState>, Path(id): Path<i64>, cookie: Option<TypedHeader<Cookie>>, Form(form): Form<AdminRevokeForm>) -> Response {
+    if let Err(resp) = authorize(&state, cookie, Role::Owner).await { return resp; }
+    if let Err(e) = apikeys::revoke_key(&state, id, form.delete_items.is_some()).await {
+        return db::error_response(&format!("revoke key {}", id), e);
     }
-    Html(AdminItemsTemplate { items }.render().unwrap_or_else(|_| "Template error".to_string())).into_response()
+    Redirect::to("/admin/keys").into_response()
 }
 
-// THIS IS THE RESTORED AND CORRECTED FUNCTION
+fn render_admin_users(conn: &Connection, csrf: String, current: &AdminUser, invite_url: Option<String>, error: Option<String>) -> Response {
+    let (users, invites) = match admins::list_users(conn).and_then(|users| Ok((users, admins::list_invites(conn)?))) {
+        Ok(v) => v,
+        Err(e) => return db::error_response("list users", e),
+    };
+    let users = users.into_iter().map(|u| AdminUserRow {
+        is_self: u.id == current.id,
+        id: u.id,
+        username: u.username,
+        role: u.role,
+        created_at: u.created_at,
+        disabled: u.disabled,
+    }).collect();
+    let invites = invites.into_iter().map(|i| AdminInviteRow {
+        id: i.id,
+        role: i.role,
+        created_by: i.created_by.unwrap_or_else(|| "-".to_string()),
+        expires_at: i.expires_at,
+    }).collect();
+    let status = if error.is_some() { StatusCode::BAD_REQUEST } else { StatusCode::OK };
+    (status, Html(AdminUsersTemplate { csrf, users, invites, invite_url, error }.render().unwrap_or_else(|_| "Template error".to_string()))).into_response()
+}
+
+fn account_error_response(conn: &Connection, csrf: String, current: &AdminUser, e: AccountError) -> Response {
+    match e {
+        AccountError::Rejected(msg) => render_admin_users(conn, csrf, current, None, Some(msg)),
+        AccountError::Internal(e) => { tracing::error!("admin users: {}", e); (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response() }
+    }
+}
+
+pub async fn admin_users(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Owner) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        render_admin_users(conn, csrf, &user, None, None)
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct AdminRoleForm { pub role: String }
+
+pub async fn admin_invite_user(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>, Form(form): Form<AdminRoleForm>) -> Response {
+    state.db.clone().respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Owner) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        let Some(role) = Role::parse(&form.role) else {
+            return render_admin_users(conn, csrf, &user, None, Some(format!("Unknown role '{}'", form.role)));
+        };
+        match admins::create_invite(conn, &user, role) {
+            Ok(token) => render_admin_users(conn, csrf, &user, Some(format!("{}/admin/invite?token={}", state.base_url, token)), None),
+            Err(e) => db::error_response("create invite", e),
+        }
+    }).await
+}
+
+pub async fn admin_revoke_invite(State(state): State<AppState>, Path(id): Path<i64>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    state.db.respond(move |conn| {
+        if let Err(denied) = require_role(conn, cookie, Role::Owner) { return denied.into_response(); }
+        if let Err(e) = admins::revoke_invite(conn, id) {
+            return db::error_response(&format!("revoke invite {}", id), e);
+        }
+        Redirect::to("/admin/users").into_response()
+    }).await
+}
+
+pub async fn admin_set_role(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, Path(id): Path<i64>, cookie: Option<TypedHeader<Cookie>>, Form(form): Form<AdminRoleForm>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Owner) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        let Some(role) = Role::parse(&form.role) else {
+            return render_admin_users(conn, csrf, &user, None, Some(format!("Unknown role '{}'", form.role)));
+        };
+        match admins::set_role(conn, &user, id, role) {
+            Ok(()) => Redirect::to("/admin/users").into_response(),
+            Err(e) => account_error_response(conn, csrf, &user, e),
+        }
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct AdminDisableForm { pub disabled: String }
+
+pub async fn admin_set_disabled(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, Path(id): Path<i64>, cookie: Option<TypedHeader<Cookie>>, Form(form): Form<AdminDisableForm>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Owner) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        match admins::set_disabled(conn, &user, id, is_truthy(&form.disabled)) {
+            Ok(()) => Redirect::to("/admin/users").into_response(),
+            Err(e) => account_error_response(conn, csrf, &user, e),
+        }
+    }).await
+}
+
+pub async fn admin_sessions(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    let user = match authorize(&state, cookie, Role::Viewer).await { Ok(user) => user, Err(resp) => return resp };
+    let viewer = user.clone();
+    let sessions = match state.db.query(move |conn| admins::list_sessions(conn, &viewer)).await {
+        Ok(sessions) => sessions,
+        Err(e) => return db::error_response("list sessions", e),
+    };
+    let sessions = sessions.into_iter().map(|s| AdminSession {
+        current: s.id == user.session_id,
+        id: s.id,
+        username: s.username,
+        created_at: s.created_at,
+        last_seen_at: s.last_seen_at,
+        ip: s.ip.unwrap_or_else(|| "-".to_string()),
+        user_agent: s.user_agent.unwrap_or_else(|| "-".to_string()),
+    }).collect();
+    let tpl = AdminSessionsTemplate {
+        csrf,
+        sessions,
+        all_users: user.role == Role::Owner,
+        idle_minutes: admins::session_idle_timeout() / 60,
+        max_age_hours: admins::session_max_age() / 3600,
+    };
+    Html(tpl.render().unwrap_or_else(|_| "Template error".to_string())).into_response()
+}
+
+pub async fn admin_revoke_session(State(state): State<AppState>, Path(id): Path<i64>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        match admins::revoke_session(conn, &user, id) {
+            Ok(true) if id == user.session_id => Redirect::to("/admin/login").into_response(),
+            Ok(_) => Redirect::to("/admin/sessions").into_response(),
+            Err(e) => db::error_response(&format!("revoke session {}", id), e),
+        }
+    }).await
+}
+
+pub async fn admin_revoke_other_sessions(State(state): State<AppState>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        match admins::revoke_other_sessions(conn, &user) {
+            Ok(n) => {
+                tracing::info!("Admin '{}' revoked {} sessions", user.username, n);
+                Redirect::to("/admin/sessions").into_response()
+            }
+            Err(e) => db::error_response("revoke sessions", e),
+        }
+    }).await
+}
+
+fn render_two_factor(conn: &Connection, csrf: String, user: &AdminUser, recovery_codes: Vec<String>, error: Option<String>) -> Response {
+    let status = twofactor::is_enabled(conn, user.id).and_then(|enabled| Ok((
+        enabled,
+        twofactor::remaining_recovery_codes(conn, user.id)?,
+        twofactor::pending_enrollment(conn, user)?,
+    )));
+    let (enabled, remaining_codes, pending) = match status {
+        Ok(v) => v,
+        Err(e) => return db::error_response("2fa status", e),
+    };
+    let enrollment = pending.filter(|_| !enabled).map(|e| AdminTotpEnrollment { qr_svg: qr_svg(&e.otpauth_url, 240), secret: e.secret });
+    let status = if error.is_some() { StatusCode::BAD_REQUEST } else { StatusCode::OK };
+    let tpl = AdminTwoFactorTemplate { csrf, enabled, remaining_codes, enrollment, recovery_codes, error };
+    (status, Html(tpl.render().unwrap_or_else(|_| "Template error".to_string()))).into_response()
+}
+
+fn two_factor_result(conn: &Connection, csrf: String, user: &AdminUser, result: Result<Vec<String>, AccountError>) -> Response {
+    match result {
+        Ok(codes) => render_two_factor(conn, csrf, user, codes, None),
+        Err(AccountError::Rejected(e)) => render_two_factor(conn, csrf, user, Vec::new(), Some(e)),
+        Err(AccountError::Internal(e)) => { tracing::error!("2fa: {}", e); (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response() }
+    }
+}
+
+pub async fn admin_two_factor(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        render_two_factor(conn, csrf, &user, Vec::new(), None)
+    }).await
+}
+
+pub async fn admin_two_factor_enroll(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        match twofactor::is_enabled(conn, user.id).and_then(|enabled| if enabled { Ok(None) } else { twofactor::begin_enrollment(conn, &user) }) {
+            Ok(_) => render_two_factor(conn, csrf, &user, Vec::new(), None),
+            Err(e) => db::error_response("2fa enroll", e),
+        }
+    }).await
+}
+
+pub async fn admin_two_factor_confirm(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>, Form(f): Form<AdminCodeForm>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        let result = twofactor::confirm_enrollment(conn, &user, &f.code);
+        two_factor_result(conn, csrf, &user, result)
+    }).await
+}
+
+pub async fn admin_two_factor_recovery(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>, Form(f): Form<AdminCodeForm>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        let result = twofactor::regenerate_recovery_codes(conn, &user, &f.code);
+        two_factor_result(conn, csrf, &user, result)
+    }).await
+}
+
+pub async fn admin_two_factor_disable(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>, Form(f): Form<AdminCodeForm>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        let result = twofactor::disable(conn, &user, &f.code).map(|()| Vec::new());
+        two_factor_result(conn, csrf, &user, result)
+    }).await
+}
+
+// ---------- Passkeys ----------
+
+pub async fn admin_passkey_js() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/javascript; charset=utf-8"), (header::CACHE_CONTROL, "no-cache")], include_str!("passkey.js"))
+}
+
+fn passkey_error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+fn passkey_account_error(e: AccountError) -> Response {
+    match e {
+        AccountError::Rejected(msg) => passkey_error(StatusCode::BAD_REQUEST, &msg),
+        AccountError::Internal(e) => { tracing::error!("passkey: {}", e); passkey_error(StatusCode::INTERNAL_SERVER_ERROR, "Server error") }
+    }
+}
+
+#[derive(Serialize)]
+struct PasskeyChallenge<T: Serialize> { ceremony: String, options: T }
+
+pub async fn admin_passkeys(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    let available = state.webauthn.is_some();
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        let passkeys = match passkeys::list(conn, user.id) {
+            Ok(rows) => rows.into_iter().map(|p| AdminPasskey { id: p.id, name: p.name, created_at: p.created_at, last_used_at: p.last_used_at }).collect(),
+            Err(e) => return db::error_response("list passkeys", e),
+        };
+        let tpl = AdminPasskeysTemplate { csrf, available, passkeys };
+        Html(tpl.render().unwrap_or_else(|_| "Template error".to_string())).into_response()
+    }).await
+}
+
+pub async fn admin_passkey_register_start(State(state): State<AppState>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    let Some(webauthn) = state.webauthn.clone() else { return passkey_error(StatusCode::NOT_FOUND, "Passkeys are not available") };
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        match passkeys::start_registration(&webauthn, conn, &user) {
+            Ok((ceremony, options)) => Json(PasskeyChallenge { ceremony, options }).into_response(),
+            Err(e) => passkey_account_error(e),
+        }
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyRegisterRequest { pub ceremony: String, #[serde(default)] pub name: String, pub credential: RegisterPublicKeyCredential }
+
+pub async fn admin_passkey_register_finish(State(state): State<AppState>, cookie: Option<TypedHeader<Cookie>>, Json(req): Json<PasskeyRegisterRequest>) -> Response {
+    let Some(webauthn) = state.webauthn.clone() else { return passkey_error(StatusCode::NOT_FOUND, "Passkeys are not available") };
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        match passkeys::finish_registration(&webauthn, conn, &user, &req.ceremony, &req.name, &req.credential) {
+            Ok(()) => StatusCode::NO_CONTENT.into_response(),
+            Err(e) => passkey_account_error(e),
+        }
+    }).await
+}
+
+pub async fn admin_delete_passkey(State(state): State<AppState>, Path(id): Path<i64>, cookie: Option<TypedHeader<Cookie>>) -> Response {
+    state.db.respond(move |conn| {
+        let user = match require_role(conn, cookie, Role::Viewer) { Ok(user) => user, Err(denied) => return denied.into_response() };
+        match passkeys::delete(conn, &user, id) {
+            Ok(_) => Redirect::to("/admin/passkeys").into_response(),
+            Err(e) => db::error_response(&format!("delete passkey {}", id), e),
+        }
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyLoginStart { pub username: String }
+
+pub async fn admin_passkey_login_start(State(state): State<AppState>, Json(req): Json<PasskeyLoginStart>) -> Response {
+    let Some(webauthn) = state.webauthn.clone() else { return passkey_error(StatusCode::NOT_FOUND, "Passkeys are not available") };
+    state.db.clone().respond(move |conn| match passkeys::start_login(&webauthn, conn, &state.cookie_secret, &req.username) {
+        Ok((ceremony, options)) => Json(PasskeyChallenge { ceremony, options }).into_response(),
+        Err(e) => passkey_account_error(e),
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyLoginFinish { pub ceremony: String, pub credential: PublicKeyCredential }
+
+// A verified passkey is a complete login (it already requires user verification), so no TOTP step follows
+pub async fn admin_passkey_login_finish(State(state): State<AppState>, ClientIp(ip): ClientIp, headers: HeaderMap, Json(req): Json<PasskeyLoginFinish>) -> Response {
+    let Some(webauthn) = state.webauthn.clone() else { return passkey_error(StatusCode::NOT_FOUND, "Passkeys are not available") };
+    state.db.respond(move |conn| {
+        // Same keys as the password form, so switching between the two doesn't reset the count
+        let mut throttle_keys = vec![throttle::ip_key(ip)];
+        match passkeys::login_username(conn, &req.ceremony) {
+            Ok(Some(username)) => throttle_keys.push(throttle::user_key(&username)),
+            Ok(None) => {}
+            Err(e) => return db::error_response("passkey login", e),
+        }
+        if let Some(denied) = begin_login_attempt(conn, &throttle_keys) {
+            return denied;
+        }
+        let admin_id = match passkeys::finish_login(&webauthn, conn, &req.ceremony, &req.credential) {
+            Ok(id) => id,
+            Err(AccountError::Rejected(msg)) => {
+                tracing::warn!("Failed passkey login from {}", ip);
+                return passkey_error(StatusCode::UNAUTHORIZED, &msg);
+            }
+            Err(e) => return passkey_account_error(e),
+        };
+        login_succeeded(conn, &throttle_keys);
+        match session_cookie(conn, admin_id, ip, &headers) {
+            Ok(cookie) => ([(header::SET_COOKIE, cookie)], Json(serde_json::json!({ "redirect": "/admin" }))).into_response(),
+            Err(e) => passkey_account_error(e.into()),
+        }
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct InviteQuery { pub token: Option<String> }
+
+fn render_invite(csrf: String, token: String, role: Role, error: Option<String>) -> Response {
+    let status = if error.is_some() { StatusCode::BAD_REQUEST } else { StatusCode::OK };
+    (status, Html(AdminInviteTemplate { csrf, token, role: role.as_str(), error }.render().unwrap_or_else(|_| "Template error".to_string()))).into_response()
+}
+
+// Accepting an invite: pick a username and password
+pub async fn admin_invite_get(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, Query(q): Query<InviteQuery>) -> Response {
+    let token = q.token.unwrap_or_default();
+    state.db.respond(move |conn| match admins::invite_role(conn, &token) {
+        Ok(Some(role)) => render_invite(csrf, token, role, None),
+        Ok(None) => (StatusCode::NOT_FOUND, "This invite is invalid, expired or already used").into_response(),
+        Err(e) => db::error_response("invite", e),
+    }).await
+}
+
+#[derive(Deserialize)]
+pub struct AdminInviteForm { pub token: String, pub username: String, pub password: String }
+
+pub async fn admin_invite_post(State(state): State<AppState>, Extension(CsrfToken(csrf)): Extension<CsrfToken>, Form(f): Form<AdminInviteForm>) -> Response {
+    let hash = admins::hash_new_password(&f.username, f.password.clone()).await;
+    state.db.respond(move |conn| match hash.and_then(|hash| admins::accept_invite(conn, &f.token, &f.username, &hash)) {
+        Ok(()) => Redirect::to("/admin/login").into_response(),
+        Err(AccountError::Rejected(e)) => {
+            match admins::invite_role(conn, &f.token).ok().flatten() {
+                Some(role) => render_invite(csrf, f.token, role, Some(e)),
+                None => (StatusCode::BAD_REQUEST, e).into_response(),
+            }
+        }
+        Err(AccountError::Internal(e)) => { tracing::error!("accept invite: {}", e); (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response() }
+    }).await
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AdminExpiryForm { pub expires_in: String }
+
+#[utoipa::path(
+    post,
+    path = "/admin/items/{code}/expiry",
+    tag = "admin",
+    params(("code" = String, Path, description = "Short code or alias")),
+    request_body(content = AdminExpiryForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Expiry changed; redirects to /admin/items"),
+        (status = 400, description = "Invalid expires_in", body = String),
+    ),
+    security(("session" = []))
+)]
+pub async fn admin_set_expiry(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Form(f): Form<AdminExpiryForm>,
+) -> Response {
+    state.db.respond(move |conn| {
+        if let Err(denied) = require_role(conn, cookie, Role::Moderator) { return denied.into_response(); }
+        let expires_at = match parse_expires_in(&f.expires_in) {
+            Ok(secs) => secs.map(|secs| now_secs() + secs),
+            Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        };
+        if let Err(e) = conn.execute("UPDATE items SET expires_at = ?1 WHERE code = ?2", params![expires_at, code]) {
+            return db::error_response(&format!("set expiry {}", code), e);
+        }
+        Redirect::to("/admin/items").into_response()
+    }).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/items/{code}/delete",
+    tag = "admin",
+    params(("code" = String, Path, description = "Short code or alias")),
+    responses((status = 303, description = "Item and its file deleted; redirects to /admin/items")),
+    security(("session" = []))
+)]
 #[debug_handler]
 pub async fn admin_delete_item(
     State(state): State<AppState>,
     Path(code): Path<String>,
     cookie: Option<TypedHeader<Cookie>>,
 ) -> impl IntoResponse {
-    if !require_admin_token(&state.db_path, extract_admin_token(cookie).as_deref()).await {
-        return Redirect::to("/admin/login").into_response();
-    }
-    // Query needed info inside a short-lived DB connection (avoid holding Connection across awaits)
-    let kind_value: Option<(String, String)> = {
-        let conn = Connection::open(&state.db_path).unwrap();
-        conn.query_row(
-            "SELECT kind, value FROM items WHERE code = ?1",
-            params![code.clone()],
-            |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)),
-        ).ok()
-    };
+    if let Err(resp) = authorize(&state, cookie, Role::Moderator).await { return resp; }
+    // Look the item up, delete its files, then the row (no connection is held across the storage awaits)
+    let lookup = code.clone();
+    let kind_value = state.db.query(move |conn| conn.query_row(
+        "SELECT kind, value FROM items WHERE code = ?1",
+        params![lookup],
+        |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)),
+    ).optional()).await;
 
-    if let Some((kind, value)) = kind_value {
-        if kind == "file" {
-            if let Some(fname) = value.strip_prefix("file:") {
-                let path_to_delete = std::path::PathBuf::from("uploads").join(fname);
-                let _ = tokio::fs::remove_file(&path_to_delete).await;
-                let preview_name = make_preview_filename(fname);
-                let preview_path = std::path::PathBuf::from("uploads").join("previews").join(preview_name);
-                let _ = tokio::fs::remove_file(preview_path).await;
-            }
-        }
+    match kind_value {
+        Ok(Some((kind, value))) => delete_item_files(state.storage.as_ref(), &kind, &value).await,
+        Ok(None) => {}
+        Err(e) => return db::error_response(&format!("delete {}", code), e),
     }
 
-    // Now delete the DB row in a fresh connection
-    {
-        let conn = Connection::open(&state.db_path).unwrap();
-        let _ = conn.execute("DELETE FROM items WHERE code = ?1", params![code]);
+    let row = code.clone();
+    if let Err(e) = state.db.query(move |conn| delete_item_row(conn, &row)).await {
+        return db::error_response(&format!("delete {}", code), e);
     }
     Redirect::to("/admin/items").into_response()
 }
 
 
+// JSON returned by /api/upload (and by GET /api/tus/{id} once an upload is finished)
+#[derive(Serialize, ToSchema)]
+pub struct ApiUploadResponse {
+    // Always true
+    pub success: bool,
+    pub short_url: String,
+    // PNG data URL of the QR code, if `qr_required=true` was sent
+    pub qr_code_data: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiUploadError {
+    // Always false
+    pub success: bool,
+    pub error: String,
+}
+
+pub(crate) fn api_upload_ok(short_url: String, qr_code_data: Option<String>) -> Response {
+    Json(ApiUploadResponse { success: true, short_url, qr_code_data }).into_response()
+}
+
+pub(crate) fn api_upload_error(status: StatusCode, error: impl Into<String>) -> Response {
+    (status, Json(ApiUploadError { success: false, error: error.into() })).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/upload",
+    tag = "upload",
+    request_body(content = crate::openapi::ApiUploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Item created", body = ApiUploadResponse),
+        (status = 400, description = "Invalid URL, option or file type", body = ApiUploadError),
+        (status = 401, description = "Anonymous uploads are disabled", body = ApiUploadError),
+        (status = 409, description = "Alias already taken", body = ApiUploadError),
+        (status = 413, description = "File too large, or larger than the hourly upload quota", body = ApiUploadError),
+        (status = 429, description = "Rate limit exceeded; see `Retry-After`", body = ApiUploadError),
+    ),
+    security((), ("bearer" = []), ("session" = []))
+)]
 #[debug_handler]
-pub async fn api_upload(State(state): State<AppState>, mut multipart: Multipart) -> axum::response::Response {
+pub async fn api_upload(State(state): State<AppState>, headers: HeaderMap, mut multipart: Multipart) -> axum::response::Response {
+    let caller = match creating_caller(&state, &headers).await {
+        Ok(c) => c,
+        Err((status, error)) => return api_upload_error(status, error),
+    };
     let mut link_value: Option<String> = None;
     let mut saved_filename: Option<String> = None;
     let mut qr_required: bool = false;
+    let mut opts = ItemOptions { api_key_id: caller.api_key_id(), ..Default::default() };
+    let mut option_error: Option<String> = None;
 
-    while let Ok(Some(mut field)) = multipart.next_field().await {
+    while let Ok(Some(field)) = multipart.next_field().await {
         let name = field.name().unwrap_or("");
         match name {
             "content" => {
                 if let Some(fname) = field.file_name().map(|s| s.to_string()) {
                     let ext = StdPath::new(&fname).extension().and_then(|e| e.to_str()).unwrap_or("bin");
-                    if !is_allowed_extension(ext) { return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"success": false, "error": "File type not allowed"}))).into_response(); }
-                    if let Err(e) = fs::create_dir_all("uploads").await { tracing::error!("create uploads dir: {}", e); return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"success": false, "error": "Server error"}))).into_response(); }
+                    if !is_allowed_extension(ext) { return api_upload_error(StatusCode::BAD_REQUEST, "File type not allowed"); }
                     let id = Uuid::new_v4();
                     let filename_saved = format!("{}.{}", id, ext);
-                    let path = format!("uploads/{}", filename_saved);
-                    let mut out = match tokio::fs::File::create(&path).await { Ok(f) => f, Err(e) => { tracing::error!("create file: {}", e); return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"success": false, "error": "Server error"}))).into_response(); } };
-                    let mut written: usize = 0;
-                    while let Ok(Some(chunk)) = field.chunk().await {
-                        written = written.saturating_add(chunk.len());
-                        if written > MAX_FILE_SIZE { let _ = tokio::fs::remove_file(&path).await; return (StatusCode::PAYLOAD_TOO_LARGE, Json(serde_json::json!({"success": false, "error": "File too large"}))).into_response(); }
-                        if let Err(e) = out.write_all(&chunk).await { tracing::error!("write: {}", e); let _ = tokio::fs::remove_file(&path).await; return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"success": false, "error": "Server error"}))).into_response(); }
+                    match store_field(state.storage.as_ref(), &filename_saved, field).await {
+                        Ok(_) => {}
+                        Err(e) if e.kind() == io::ErrorKind::FileTooLarge => { return api_upload_error(StatusCode::PAYLOAD_TOO_LARGE, "File too large"); }
+                        Err(e) => { tracing::error!("store upload: {}", e); return api_upload_error(StatusCode::INTERNAL_SERVER_ERROR, "Server error"); }
                     }
                     saved_filename = Some(filename_saved);
-                } else if let Ok(text) = field.text().await {
-                    if !text.trim().is_empty() { link_value = Some(text.trim().to_string()); }
-                }
+                } else if let Ok(text) = field.text().await
+                    && !text.trim().is_empty() { link_value = Some(text.trim().to_string()); }
             }
             "qr_required" => {
                 if let Ok(v) = field.text().await { qr_required = v.trim().eq_ignore_ascii_case("true"); }
             }
+            n if ItemOptions::is_option_field(n) => {
+                let n = n.to_string();
+                let text = field.text().await.unwrap_or_default();
+                if let Err(e) = opts.apply_field(&n, &text).await { option_error = Some(e); }
+            }
             _ => {}
         }
     }
 
+    if let Some(e) = option_error {
+        if let Some(key) = &saved_filename { let _ = state.storage.delete(key).await; }
+        return api_upload_error(StatusCode::BAD_REQUEST, e);
+    }
+
     if let Some(filename_saved) = saved_filename {
-        let short_code = nanoid!(8);
-        {
-            let conn = Connection::open(&state.db_path).unwrap();
-            let original = format!("file:{}", filename_saved);
-            conn.execute("INSERT INTO items(code, kind, value, created_at) VALUES (?1, ?2, ?3, strftime('%s','now'))", params![short_code, "file", original]).ok();
-        }
-        let short_url = format!("{}/s/{}", state.base_url, short_code);
-        let qr_code_data = if qr_required {
-            let qr_target = ensure_absolute(&state.base_url, &short_url);
-            match QrCode::new(qr_target.as_bytes()) {
-                Ok(c) => {
-                    let image = c
-                        .render::<qrcode::render::svg::Color>()
-                        .min_dimensions(320,320)
-                        .quiet_zone(true)
-                        .dark_color(Color("#000000"))
-                        .light_color(Color("#ffffff"))
-                        .build();
-                    let data_url = format!("data:image/svg+xml;utf8,{}", urlencoding::encode(&image));
-                    Some(data_url)
-                }
-                Err(_) => None,
+        let inserted = save_item(&state, "file", format!("file:{}", filename_saved), opts).await;
+        let short_code = match inserted {
+            Ok(code) => code,
+            Err(e) => {
+                tracing::error!("insert item: {}", e);
+                let _ = state.storage.delete(&filename_saved).await;
+                return api_upload_error(e.status(), e.public_message());
             }
-        } else { None };
-        return Json(serde_json::json!({"success": true, "short_url": short_url, "qr_code_data": qr_code_data})).into_response();
+        };
+        let short_url = format!("{}/s/{}", state.base_url, short_code);
+        let qr_code_data = if qr_required { qr_data_url(&state.base_url, &short_url) } else { None };
+        return api_upload_ok(short_url, qr_code_data);
     }
 
     if let Some(link) = link_value {
-        if !link.starts_with("http://") && !link.starts_with("https://") { return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"success": false, "error": "Invalid URL"}))).into_response(); }
-        let short_code = nanoid!(8);
-        {
-            let conn = Connection::open(&state.db_path).unwrap();
-            conn.execute("INSERT INTO items(code, kind, value, created_at) VALUES (?1, ?2, ?3, strftime('%s','now'))", params![short_code, "url", link]).ok();
-        }
-        let short_url = format!("{}/s/{}", state.base_url, short_code);
-        let qr_code_data = if qr_required {
-            let qr_target = ensure_absolute(&state.base_url, &short_url);
-            match QrCode::new(qr_target.as_bytes()) {
-                Ok(c) => {
-                    let image = c
-                        .render::<qrcode::render::svg::Color>()
-                        .min_dimensions(320,320)
-                        .quiet_zone(true)
-                        .dark_color(Color("#000000"))
-                        .light_color(Color("#ffffff"))
-                        .build();
-                    let data_url = format!("data:image/svg+xml;utf8,{}", urlencoding::encode(&image));
-                    Some(data_url)
-                }
-                Err(_) => None,
+        if !link.starts_with("http://") && !link.starts_with("https://") { return api_upload_error(StatusCode::BAD_REQUEST, "Invalid URL"); }
+        let inserted = save_item(&state, "url", link, opts).await;
+        let short_code = match inserted {
+            Ok(code) => code,
+            Err(e) => {
+                tracing::error!("insert item: {}", e);
+                return api_upload_error(e.status(), e.public_message());
             }
-        } else { None };
-        return Json(serde_json::json!({"success": true, "short_url": short_url, "qr_code_data": qr_code_data})).into_response();
+        };
+        let short_url = format!("{}/s/{}", state.base_url, short_code);
+        let qr_code_data = if qr_required { qr_data_url(&state.base_url, &short_url) } else { None };
+        return api_upload_ok(short_url, qr_code_data);
     }
 
-    (StatusCode::BAD_REQUEST, Json(serde_json::json!({"success": false, "error": "Provide content or file"}))).into_response()
+    api_upload_error(StatusCode::BAD_REQUEST, "Provide content or file")
 }
\ No newline at end of file
@@ -1,2 +1,22 @@
+pub mod admins;
+pub mod analytics;
+pub mod api;
+pub mod apikeys;
+pub mod clientip;
+pub mod csrf;
+pub mod db;
+pub mod handlers;
+pub mod migrations;
+pub mod oidc;
+pub mod openapi;
+pub mod passkeys;
+pub mod password;
+pub mod ratelimit;
+pub mod reaper;
+pub mod routes;
+pub mod setup;
+pub mod storage;
 pub mod templates;
-
+pub mod throttle;
+pub mod tus;
+pub mod twofactor;
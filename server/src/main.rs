@@ -1,24 +1,25 @@
-use axum::routing::{get, post};
-use axum::{Router, Json};
-use axum::extract::DefaultBodyLimit;
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
-use tower_http::services::ServeDir;
-use tower_http::cors::{CorsLayer, Any};
-use tower_http::limit::RequestBodyLimitLayer;
-use axum::http::Method;
-use axum::routing::get_service;
-use axum::http::StatusCode;
-use serde_json::json;
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
 
-mod handlers;
+use ping0::{analytics, clientip, db, handlers, migrations, oidc, passkeys, ratelimit, reaper, routes, setup, storage};
 
-async fn health_check() -> Json<serde_json::Value> {
-    Json(json!({
-        "status": "healthy",
-        "service": "ping0"
-    }))
+// Key for signing cookies: COOKIE_SECRET, or a random one kept in the database so restarts
+// don't invalidate cookies that were already handed out
+fn cookie_secret(conn: &Connection) -> anyhow::Result<Vec<u8>> {
+    if let Ok(secret) = std::env::var("COOKIE_SECRET")
+        && !secret.trim().is_empty() {
+            return Ok(secret.into_bytes());
+        }
+    let stored: Option<String> = conn
+        .query_row("SELECT value FROM settings WHERE key = 'cookie_secret'", [], |r| r.get(0))
+        .optional()?;
+    if let Some(hex_secret) = stored {
+        return Ok(hex::decode(hex_secret)?);
+    }
+    let secret: [u8; 32] = rand::random();
+    conn.execute("INSERT INTO settings (key, value) VALUES ('cookie_secret', ?1)", [hex::encode(secret)])?;
+    Ok(secret.to_vec())
 }
 
 #[tokio::main]
@@ -41,81 +42,64 @@ async fn main() -> anyhow::Result<()> {
     let base_url = std::env::var("BASE_URL")
         .ok()
         .filter(|v| !v.trim().is_empty())
-        .unwrap_or_else(|| format!("https://w9.se"));
+        .unwrap_or_else(|| "https://w9.se".to_string());
     
     tracing::info!("Base URL: {}", base_url);
 
-    // Initialize SQLite (file-based)
+    // Initialize SQLite (file-based, shared through a connection pool)
     let db_path = std::env::var("DATABASE_PATH").unwrap_or_else(|_| "data/ping0.db".to_string());
     std::fs::create_dir_all("data").ok();
-    let conn = Connection::open(&db_path)?;
-    conn.execute_batch(
-        r#"
-        CREATE TABLE IF NOT EXISTS items (
-            code TEXT PRIMARY KEY,
-            kind TEXT NOT NULL,        -- 'url' | 'file'
-            value TEXT NOT NULL,       -- url or 'file:filename'
-            created_at INTEGER NOT NULL
-        );
-        CREATE TABLE IF NOT EXISTS admin (
-            id INTEGER PRIMARY KEY,
-            username TEXT NOT NULL UNIQUE,
-            password_hash TEXT NOT NULL,
-            salt TEXT NOT NULL
-        );
-        CREATE TABLE IF NOT EXISTS sessions (
-            token TEXT PRIMARY KEY,
-            created_at INTEGER NOT NULL
-        );
-        "#,
-    )?;
+    let db = db::Db::open(&db_path)?;
+    let mut conn = db.get()?;
 
-    let base_url = std::env::var("BASE_URL")
-        .ok()
-        .filter(|v| !v.trim().is_empty())
-        .unwrap_or_else(|| format!("https://w9.se"));
+    // `ping0 migrate` only brings the schema up to date
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if matches!(args.as_slice(), [cmd] if cmd == "migrate") {
+        let version = migrations::run(&mut conn)?;
+        println!("Database schema is at version {}", version);
+        return Ok(());
+    }
+    migrations::startup(&mut conn, migrations::auto_migrate())?;
+
+    // `ping0 admin create <username>` runs against the database and exits
+    if setup::run_cli(&conn, &args)? {
+        return Ok(());
+    }
+    setup::bootstrap(&conn, &base_url)?;
+
+    // Uploaded files and generated previews (local directory or S3 bucket)
+    let storage = storage::from_env()?;
 
-    let app_state = handlers::AppState { db_path: db_path.clone(), base_url: base_url.clone() };
+    let cookie_secret = cookie_secret(&conn)?.into();
+    // Return the startup connection to the pool
+    drop(conn);
+
+    // Optional offline geolocation of clicks
+    let geoip = analytics::GeoIp::from_env()?.map(std::sync::Arc::new);
+
+    let webauthn = passkeys::build(&base_url).map(std::sync::Arc::new);
+    let oidc = oidc::OidcConfig::from_env(&base_url)?.map(std::sync::Arc::new);
+    let rate_limiter = std::sync::Arc::new(ratelimit::RateLimiter::from_env());
+    // Reverse proxies whose forwarding headers name the real client
+    let trusted_proxies = std::sync::Arc::new(clientip::TrustedProxies::from_env()?);
+    let app_state = handlers::AppState { db, base_url: base_url.clone(), storage, cookie_secret, geoip, webauthn, oidc, rate_limiter, trusted_proxies };
+
+    // Periodically delete expired items and abandoned resumable uploads
+    let reaper_interval: u64 = std::env::var("REAPER_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(300);
+    reaper::spawn(app_state.clone(), std::time::Duration::from_secs(reaper_interval));
 
-    let app = Router::new()
-        .route("/health", get(health_check))
-        // CORS preflight: explicitly handle OPTIONS on API endpoints
-        .route("/api/upload", axum::routing::options(handlers::cors_preflight))
-        .route("/", get(handlers::index_handler))
-        .route("/submit", post(handlers::submit_handler))
-        .route("/upload", post(handlers::upload_handler))
-        .route("/link", post(handlers::link_handler))
-        .route("/api/upload", post(handlers::api_upload))
-        .route("/r/:code", get(handlers::result_handler))
-        .route("/s/:code", get(handlers::short_handler))
-        // Admin routes
-        .route("/admin/login", get(handlers::admin_login_get))
-        .route("/admin/login", post(handlers::admin_login_post))
-        .route("/admin/logout", post(handlers::admin_logout))
-        .route("/admin", get(handlers::admin_home))
-        .route("/admin/items", get(handlers::admin_items))
-        .route("/admin/items/:code/delete", post(handlers::admin_delete_item))
-        .with_state(app_state)
-        // Set individual field limit to 1 GiB for multipart uploads
-        .layer(DefaultBodyLimit::max(1024 * 1024 * 1024))
-        // Raise max request body size to 1 GiB (inner layer)
-        .layer(RequestBodyLimitLayer::new(1024 * 1024 * 1024))
-        // CORS (outer layer) so even inner rejections (like 413) get CORS headers
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-                .allow_headers(Any)
-                .expose_headers(Any)
-        )
-        .nest_service("/files", get_service(ServeDir::new("uploads")).handle_error(|_| async { (StatusCode::INTERNAL_SERVER_ERROR, "IO Error") }));
+    let app = routes::router(app_state);
 
     let addr: SocketAddr = format!("{}:{}", host, port).parse()?;
     tracing::info!("🚀 Server listening on {}", addr);
     
     // Axum 0.7 API: use TcpListener + axum::serve
     let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
@@ -0,0 +1,375 @@
+// Database schema changes, applied in order at startup (or by `ping0 migrate` when DATABASE_AUTO_MIGRATE=false).
+// The schema version is kept in `PRAGMA user_version`: a database at version N has had the first N
+// migrations applied. Migrations are never edited once released; a schema change is a new entry at the end
+// of MIGRATIONS. A database with a version newer than this binary knows is refused rather than guessed at.
+
+use anyhow::bail;
+use rusqlite::{Connection, TransactionBehavior};
+
+struct Migration {
+    description: &'static str,
+    apply: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { description: "baseline schema", apply: baseline },
+    Migration { description: "object key per tus part", apply: tus_part_keys },
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.len() as i64
+}
+
+pub fn current_version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.pragma_query_value(None, "user_version", |r| r.get(0))
+}
+
+// DATABASE_AUTO_MIGRATE=false leaves migrating to `ping0 migrate`
+pub fn auto_migrate() -> bool {
+    std::env::var("DATABASE_AUTO_MIGRATE").map(|v| !matches!(v.trim().to_ascii_lowercase().as_str(), "false" | "0" | "no" | "off")).unwrap_or(true)
+}
+
+fn refuse_newer(version: i64) -> anyhow::Result<()> {
+    if version > latest_version() {
+        bail!(
+            "The database schema is at version {} but this ping0 only knows versions up to {}. Run a newer ping0 or restore a backup.",
+            version,
+            latest_version()
+        );
+    }
+    Ok(())
+}
+
+// Fails unless the database is exactly at the version this binary expects
+pub fn require_current(conn: &Connection) -> anyhow::Result<()> {
+    let version = current_version(conn)?;
+    refuse_newer(version)?;
+    if version < latest_version() {
+        bail!(
+            "The database schema is at version {} and needs migrating to {}. Run `ping0 migrate` or set DATABASE_AUTO_MIGRATE=true.",
+            version,
+            latest_version()
+        );
+    }
+    Ok(())
+}
+
+// Applies pending migrations, each in its own transaction together with the version bump. Another instance
+// migrating at the same time waits for the write lock and then finds the work done.
+pub fn run(conn: &mut Connection) -> anyhow::Result<i64> {
+    refuse_newer(current_version(conn)?)?;
+    loop {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let version = current_version(&tx)?;
+        refuse_newer(version)?;
+        let Some(migration) = MIGRATIONS.get(version as usize) else { return Ok(version) };
+        (migration.apply)(&tx)?;
+        tx.pragma_update(None, "user_version", version + 1)?;
+        tx.commit()?;
+        tracing::info!("Applied database migration {} ({})", version + 1, migration.description);
+    }
+}
+
+// What the server does with the schema before it starts: migrate, or with DATABASE_AUTO_MIGRATE=false refuse
+// to start on a database that isn't current
+pub fn startup(conn: &mut Connection, auto_migrate: bool) -> anyhow::Result<()> {
+    if auto_migrate {
+        run(conn)?;
+    } else {
+        require_current(conn)?;
+    }
+    Ok(())
+}
+
+// Add a column to an existing table unless it is already there (tables predating the column)
+fn ensure_column(conn: &Connection, table: &str, column: &str, decl: &str) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |r| r.get::<_, String>(1))?
+        .flatten()
+        .any(|name| name == column);
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl))?;
+    }
+    Ok(())
+}
+
+// Version 1: everything up to the introduction of migrations. Databases from before then have no version
+// but may lack any of the later tables and columns, so this is written to bring any of them up to date.
+fn baseline(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS items (
+            code TEXT PRIMARY KEY,
+            kind TEXT NOT NULL,        -- 'url' | 'file'
+            value TEXT NOT NULL,       -- url or 'file:filename'
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER,        -- unix seconds; NULL = never
+            max_views INTEGER,         -- NULL = unlimited
+            view_count INTEGER NOT NULL DEFAULT 0,
+            burn_after_reading INTEGER NOT NULL DEFAULT 0,
+            password_hash TEXT,        -- Argon2 PHC string; NULL = public
+            api_key_id INTEGER         -- API key that created the item; NULL = web form / admin
+        );
+        CREATE TABLE IF NOT EXISTS api_keys (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            prefix TEXT NOT NULL,      -- first characters of the token, to tell keys apart
+            key_hash TEXT NOT NULL UNIQUE,
+            scopes TEXT NOT NULL,      -- comma-separated: create,read,delete,admin
+            created_at INTEGER NOT NULL,
+            last_used_at INTEGER,
+            revoked_at INTEGER
+        );
+        CREATE TABLE IF NOT EXISTS clicks (
+            id INTEGER PRIMARY KEY,
+            ts INTEGER NOT NULL,
+            code TEXT NOT NULL,
+            referrer_host TEXT,
+            ua_family TEXT NOT NULL,
+            is_bot INTEGER NOT NULL,
+            ip_hash TEXT,              -- salted per UTC day, never the raw address
+            country TEXT,              -- ISO code from the optional GeoIP database
+            region TEXT
+        );
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS admin (
+            id INTEGER PRIMARY KEY,
+            username TEXT NOT NULL UNIQUE,
+            password_hash TEXT NOT NULL,
+            salt TEXT NOT NULL,
+            role TEXT NOT NULL DEFAULT 'owner', -- owner | moderator | viewer
+            created_at INTEGER,
+            disabled_at INTEGER,       -- NULL = can log in
+            totp_secret TEXT,          -- base32; NULL = no two-factor login
+            totp_pending TEXT,         -- secret waiting for its first code during enrollment
+            totp_last_step INTEGER,    -- time step of the last accepted code, against replays
+            webauthn_id TEXT,          -- WebAuthn user handle (UUID), set with the first passkey
+            oidc_subject TEXT          -- "<issuer>|<sub>" for users who sign in with OIDC
+        );
+        CREATE TABLE IF NOT EXISTS admin_passkeys (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            credential_id TEXT NOT NULL UNIQUE, -- hex
+            passkey TEXT NOT NULL,              -- webauthn-rs Passkey as JSON
+            created_at INTEGER NOT NULL,
+            last_used_at INTEGER
+        );
+        CREATE TABLE IF NOT EXISTS webauthn_ceremonies (
+            id TEXT PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,        -- register | login
+            state TEXT NOT NULL,
+            expires_at INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS admin_recovery_codes (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            code_hash TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            used_at INTEGER
+        );
+        CREATE TABLE IF NOT EXISTS admin_invites (
+            id INTEGER PRIMARY KEY,
+            token_hash TEXT NOT NULL UNIQUE,
+            role TEXT NOT NULL,
+            created_by INTEGER,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            accepted_by INTEGER        -- user created from the invite; NULL = pending
+        );
+        CREATE TABLE IF NOT EXISTS sessions (
+            token TEXT PRIMARY KEY,
+            created_at INTEGER NOT NULL,
+            user_id INTEGER,
+            last_seen_at INTEGER,
+            ip TEXT,
+            user_agent TEXT
+        );
+        CREATE TABLE IF NOT EXISTS login_attempts (
+            key TEXT PRIMARY KEY,      -- "ip:<addr>", "user:<name>" or "2fa:<user id>"
+            failures INTEGER NOT NULL,
+            last_failure_at INTEGER NOT NULL,
+            locked_until INTEGER
+        );
+        CREATE TABLE IF NOT EXISTS tus_uploads (
+            id TEXT PRIMARY KEY,
+            ext TEXT NOT NULL,
+            upload_length INTEGER NOT NULL,
+            upload_offset INTEGER NOT NULL,
+            qr_required INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            code TEXT,                 -- set once the upload is finished
+            metadata TEXT              -- JSON item options applied on finish
+        );
+        CREATE TABLE IF NOT EXISTS tus_parts (
+            upload_id TEXT NOT NULL,
+            part_offset INTEGER NOT NULL,
+            size INTEGER NOT NULL,
+            PRIMARY KEY (upload_id, part_offset)
+        );
+        "#,
+    )?;
+    ensure_column(conn, "items", "expires_at", "INTEGER")?;
+    ensure_column(conn, "items", "max_views", "INTEGER")?;
+    ensure_column(conn, "items", "view_count", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "items", "burn_after_reading", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "items", "password_hash", "TEXT")?;
+    ensure_column(conn, "items", "api_key_id", "INTEGER")?;
+    ensure_column(conn, "clicks", "country", "TEXT")?;
+    ensure_column(conn, "clicks", "region", "TEXT")?;
+    ensure_column(conn, "tus_uploads", "metadata", "TEXT")?;
+    ensure_column(conn, "admin", "role", "TEXT NOT NULL DEFAULT 'owner'")?;
+    ensure_column(conn, "admin", "created_at", "INTEGER")?;
+    ensure_column(conn, "admin", "disabled_at", "INTEGER")?;
+    ensure_column(conn, "admin", "totp_secret", "TEXT")?;
+    ensure_column(conn, "admin", "totp_pending", "TEXT")?;
+    ensure_column(conn, "admin", "totp_last_step", "INTEGER")?;
+    ensure_column(conn, "admin", "webauthn_id", "TEXT")?;
+    ensure_column(conn, "admin", "oidc_subject", "TEXT")?;
+    ensure_column(conn, "sessions", "user_id", "INTEGER")?;
+    ensure_column(conn, "sessions", "last_seen_at", "INTEGER")?;
+    ensure_column(conn, "sessions", "ip", "TEXT")?;
+    ensure_column(conn, "sessions", "user_agent", "TEXT")?;
+    // Sessions from before there were several users all belong to the single admin
+    conn.execute("UPDATE sessions SET user_id = (SELECT MIN(id) FROM admin) WHERE user_id IS NULL", [])?;
+    conn.execute_batch(
+        r#"
+        CREATE INDEX IF NOT EXISTS items_value ON items(value);
+        CREATE INDEX IF NOT EXISTS clicks_code_ts ON clicks(code, ts);
+        CREATE INDEX IF NOT EXISTS items_api_key_id ON items(api_key_id) WHERE api_key_id IS NOT NULL;
+        CREATE UNIQUE INDEX IF NOT EXISTS admin_oidc_subject ON admin(oidc_subject) WHERE oidc_subject IS NOT NULL;
+        CREATE INDEX IF NOT EXISTS admin_passkeys_user ON admin_passkeys(user_id);
+        CREATE INDEX IF NOT EXISTS admin_recovery_codes_user ON admin_recovery_codes(user_id);
+        CREATE INDEX IF NOT EXISTS items_expires_at ON items(expires_at) WHERE expires_at IS NOT NULL;
+        "#,
+    )?;
+    Ok(())
+}
+
+// Version 2: each tus part records its own object key. Parts written before then used
+// `tus/{id}/{offset}`.
+fn tus_part_keys(conn: &Connection) -> rusqlite::Result<()> {
+    ensure_column(conn, "tus_parts", "part_key", "TEXT")?;
+    conn.execute("UPDATE tus_parts SET part_key = printf('tus/%s/%020d', upload_id, part_offset) WHERE part_key IS NULL", [])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        stmt.query_map([], |r| r.get(1)).unwrap().collect::<rusqlite::Result<_>>().unwrap()
+    }
+
+    fn schema(conn: &Connection) -> Vec<(String, String)> {
+        let mut stmt = conn.prepare("SELECT name, sql FROM sqlite_master WHERE sql IS NOT NULL ORDER BY name").unwrap();
+        stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?))).unwrap().collect::<rusqlite::Result<_>>().unwrap()
+    }
+
+    // The tables as the oldest released ping0 created them, before any of the later columns
+    fn baseline_era() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE items (code TEXT PRIMARY KEY, kind TEXT NOT NULL, value TEXT NOT NULL, created_at INTEGER NOT NULL);
+            CREATE TABLE clicks (id INTEGER PRIMARY KEY, ts INTEGER NOT NULL, code TEXT NOT NULL, referrer_host TEXT,
+                ua_family TEXT NOT NULL, is_bot INTEGER NOT NULL, ip_hash TEXT);
+            CREATE TABLE admin (id INTEGER PRIMARY KEY, username TEXT NOT NULL UNIQUE, password_hash TEXT NOT NULL, salt TEXT NOT NULL);
+            CREATE TABLE sessions (token TEXT PRIMARY KEY, created_at INTEGER NOT NULL);
+            CREATE TABLE tus_uploads (id TEXT PRIMARY KEY, ext TEXT NOT NULL, upload_length INTEGER NOT NULL,
+                upload_offset INTEGER NOT NULL, qr_required INTEGER NOT NULL DEFAULT 0, created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL, code TEXT);
+            CREATE TABLE tus_parts (upload_id TEXT NOT NULL, part_offset INTEGER NOT NULL, size INTEGER NOT NULL,
+                PRIMARY KEY (upload_id, part_offset));
+            INSERT INTO items VALUES ('abc', 'url', 'https://example.com/', 1700000000);
+            INSERT INTO admin VALUES (7, 'admin', 'hash', 'salt');
+            INSERT INTO sessions VALUES ('token', 1700000000);
+            INSERT INTO tus_parts VALUES ('upload', 5, 5);
+            "#,
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn fresh_database_reaches_the_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(current_version(&conn).unwrap(), 0);
+        assert_eq!(run(&mut conn).unwrap(), latest_version());
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert!(columns(&conn, "tus_parts").contains(&"part_key".to_string()));
+        require_current(&conn).unwrap();
+    }
+
+    #[test]
+    fn baseline_era_database_gets_the_new_columns() {
+        let mut conn = baseline_era();
+        assert_eq!(run(&mut conn).unwrap(), latest_version());
+        for (table, column) in [
+            ("items", "password_hash"),
+            ("items", "api_key_id"),
+            ("clicks", "country"),
+            ("admin", "role"),
+            ("admin", "totp_last_step"),
+            ("admin", "oidc_subject"),
+            ("sessions", "user_id"),
+            ("tus_uploads", "metadata"),
+            ("tus_parts", "part_key"),
+        ] {
+            assert!(columns(&conn, table).contains(&column.to_string()), "{}.{}", table, column);
+        }
+        let (views, role): (i64, String) =
+            conn.query_row("SELECT view_count, (SELECT role FROM admin) FROM items WHERE code = 'abc'", [], |r| Ok((r.get(0)?, r.get(1)?))).unwrap();
+        assert_eq!((views, role.as_str()), (0, "owner"));
+        let user: i64 = conn.query_row("SELECT user_id FROM sessions", [], |r| r.get(0)).unwrap();
+        assert_eq!(user, 7);
+        let key: String = conn.query_row("SELECT part_key FROM tus_parts", [], |r| r.get(0)).unwrap();
+        assert_eq!(key, "tus/upload/00000000000000000005");
+    }
+
+    #[test]
+    fn running_twice_changes_nothing() {
+        let mut conn = baseline_era();
+        run(&mut conn).unwrap();
+        let before = schema(&conn);
+        assert_eq!(run(&mut conn).unwrap(), latest_version());
+        assert_eq!(schema(&conn), before);
+        // Even replaying every migration over an up-to-date schema is harmless
+        for migration in MIGRATIONS {
+            (migration.apply)(&conn).unwrap();
+        }
+        assert_eq!(schema(&conn), before);
+    }
+
+    #[test]
+    fn newer_database_is_refused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+        let err = run(&mut conn).unwrap_err().to_string();
+        assert!(err.contains("only knows versions up to"), "{}", err);
+        assert!(require_current(&conn).is_err());
+        assert!(startup(&mut conn, true).is_err());
+        assert_eq!(current_version(&conn).unwrap(), latest_version() + 1);
+        assert!(schema(&conn).is_empty());
+    }
+
+    #[test]
+    fn stale_database_without_auto_migrate_fails_startup() {
+        let mut conn = baseline_era();
+        let err = startup(&mut conn, false).unwrap_err().to_string();
+        assert!(err.contains("ping0 migrate"), "{}", err);
+        assert_eq!(current_version(&conn).unwrap(), 0);
+        assert!(!columns(&conn, "items").contains(&"password_hash".to_string()));
+
+        startup(&mut conn, true).unwrap();
+        startup(&mut conn, false).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+// OpenID Connect single sign-on for the admin panel: authorization code flow with PKCE against the
+// issuer in OIDC_ISSUER. The browser carries the flow's state, nonce and PKCE verifier in a signed,
+// short-lived `ping0_oidc` cookie. Roles come from the IdP's groups on every login; a user whose
+// groups map to no role is refused. SSO users are matched by issuer and subject, never by username.
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use openidconnect::core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata};
+use openidconnect::{
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet, EndpointNotSet, EndpointSet, IssuerUrl, Nonce,
+    PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, TokenResponse,
+};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::admins::{self, AccountError, Role};
+use crate::handlers::{now_secs, AppState};
+
+const FLOW_COOKIE: &str = "ping0_oidc";
+const FLOW_TTL_SECS: i64 = 10 * 60;
+const CALLBACK_PATH: &str = "/admin/oidc/callback";
+
+type DiscoveredClient = CoreClient<EndpointSet, EndpointNotSet, EndpointNotSet, EndpointNotSet, EndpointMaybeSet, EndpointMaybeSet>;
+
+pub struct OidcConfig {
+    issuer: IssuerUrl,
+    client_id: ClientId,
+    client_secret: Option<ClientSecret>,
+    redirect_url: RedirectUrl,
+    scopes: Vec<String>,
+    groups_claim: String,
+    // IdP group -> role; the highest role among the user's groups wins
+    role_map: Vec<(String, Role)>,
+    default_role: Option<Role>,
+    // Shown on the login button
+    pub provider_name: String,
+    http: openidconnect::reqwest::Client,
+}
+
+// "admins=owner,editors=moderator,staff=viewer"
+fn parse_role_map(spec: &str) -> anyhow::Result<Vec<(String, Role)>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (group, role) = entry.rsplit_once('=').ok_or_else(|| anyhow::anyhow!("OIDC_ROLE_MAP: expected group=role, got '{}'", entry))?;
+            let role = Role::parse(role.trim()).ok_or_else(|| anyhow::anyhow!("OIDC_ROLE_MAP: unknown role '{}'", role.trim()))?;
+            Ok((group.trim().to_string(), role))
+        })
+        .collect()
+}
+
+impl OidcConfig {
+    // None unless OIDC_ISSUER is set; then OIDC_CLIENT_ID is required too
+    pub fn from_env(base_url: &str) -> anyhow::Result<Option<OidcConfig>> {
+        OidcConfig::from_vars(base_url, |name| std::env::var(name).ok())
+    }
+
+    // from_env with the variables looked up through `var`
+    pub fn from_vars(base_url: &str, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<Option<OidcConfig>> {
+        let env_nonempty = |name: &str| var(name).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let Some(issuer) = env_nonempty("OIDC_ISSUER") else { return Ok(None) };
+        let client_id = env_nonempty("OIDC_CLIENT_ID").ok_or_else(|| anyhow::anyhow!("OIDC_ISSUER is set but OIDC_CLIENT_ID is not"))?;
+        let role_map = parse_role_map(&env_nonempty("OIDC_ROLE_MAP").unwrap_or_default())?;
+        let default_role = match env_nonempty("OIDC_DEFAULT_ROLE") {
+            Some(role) => Some(Role::parse(&role).ok_or_else(|| anyhow::anyhow!("OIDC_DEFAULT_ROLE: unknown role '{}'", role))?),
+            None => None,
+        };
+        if role_map.is_empty() && default_role.is_none() {
+            anyhow::bail!("OIDC needs OIDC_ROLE_MAP or OIDC_DEFAULT_ROLE, otherwise nobody gets a role");
+        }
+        let http = openidconnect::reqwest::ClientBuilder::new()
+            // Following redirects here would open the door to SSRF
+            .redirect(openidconnect::reqwest::redirect::Policy::none())
+            .timeout(std::time::Duration::from_secs(15))
+            .build()?;
+        Ok(Some(OidcConfig {
+            issuer: IssuerUrl::new(issuer)?,
+            client_id: ClientId::new(client_id),
+            client_secret: env_nonempty("OIDC_CLIENT_SECRET").map(ClientSecret::new),
+            redirect_url: RedirectUrl::new(format!("{}{}", base_url.trim_end_matches('/'), CALLBACK_PATH))?,
+            scopes: env_nonempty("OIDC_SCOPES")
+                .unwrap_or_else(|| "openid profile email".to_string())
+                .split_whitespace()
+                .filter(|s| *s != "openid")
+                .map(str::to_string)
+                .collect(),
+            groups_claim: env_nonempty("OIDC_GROUPS_CLAIM").unwrap_or_else(|| "groups".to_string()),
+            role_map,
+            default_role,
+            provider_name: env_nonempty("OIDC_PROVIDER_NAME").unwrap_or_else(|| "SSO".to_string()),
+            http,
+        }))
+    }
+
+    // Discovery runs per login so rotated signing keys are picked up without a restart
+    async fn client(&self) -> anyhow::Result<DiscoveredClient> {
+        let metadata = CoreProviderMetadata::discover_async(self.issuer.clone(), &self.http).await?;
+        Ok(CoreClient::from_provider_metadata(metadata, self.client_id.clone(), self.client_secret.clone())
+            .set_redirect_uri(self.redirect_url.clone()))
+    }
+
+    fn role_for(&self, groups: &[String]) -> Option<Role> {
+        self.role_map
+            .iter()
+            .filter(|(group, _)| groups.contains(group))
+            .map(|(_, role)| *role)
+            .max_by_key(|role| role.rank())
+            .or(self.default_role)
+    }
+}
+
+// ---------- Flow cookie ----------
+
+#[derive(Serialize, Deserialize)]
+struct Flow {
+    state: String,
+    nonce: String,
+    verifier: String,
+    expires: i64,
+}
+
+fn flow_mac(secret: &[u8], payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(b"oidc:");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+fn flow_cookie(state: &AppState, flow: &Flow) -> String {
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(flow).unwrap_or_default());
+    let sig = hex::encode(flow_mac(&state.cookie_secret, &payload).finalize().into_bytes());
+    let secure = if state.base_url.starts_with("https://") { "; Secure" } else { "" };
+    format!("{}={}.{}; HttpOnly; SameSite=Lax; Path=/admin/oidc; Max-Age={}{}", FLOW_COOKIE, payload, sig, FLOW_TTL_SECS, secure)
+}
+
+pub fn clear_flow_cookie() -> String {
+    format!("{}=; Max-Age=0; Path=/admin/oidc", FLOW_COOKIE)
+}
+
+fn read_flow(state: &AppState, cookie: Option<&str>) -> Option<Flow> {
+    let (payload, sig) = cookie?.split_once('.')?;
+    flow_mac(&state.cookie_secret, payload).verify_slice(&hex::decode(sig).ok()?).ok()?;
+    let flow: Flow = serde_json::from_slice(&base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    (flow.expires > now_secs()).then_some(flow)
+}
+
+// ---------- Login ----------
+
+// The IdP URL to send the browser to, and the Set-Cookie value that remembers the flow
+pub async fn start(state: &AppState, cfg: &OidcConfig) -> anyhow::Result<(String, String)> {
+    let client = cfg.client().await?;
+    let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+    let mut request = client
+        .authorize_url(CoreAuthenticationFlow::AuthorizationCode, CsrfToken::new_random, Nonce::new_random)
+        .set_pkce_challenge(challenge);
+    for scope in &cfg.scopes {
+        request = request.add_scope(Scope::new(scope.clone()));
+    }
+    let (url, csrf, nonce) = request.url();
+    let flow = Flow {
+        state: csrf.secret().clone(),
+        nonce: nonce.secret().clone(),
+        verifier: verifier.secret().clone(),
+        expires: now_secs() + FLOW_TTL_SECS,
+    };
+    Ok((url.to_string(), flow_cookie(state, &flow)))
+}
+
+// The verified identity from the IdP's callback
+pub struct Identity {
+    subject: String,
+    username: String,
+    pub role: Role,
+}
+
+// Exchanges the code (with the PKCE verifier) and verifies the ID token; Rejected errors are shown to the user
+pub async fn finish(state: &AppState, cfg: &OidcConfig, flow_cookie: Option<&str>, code: &str, returned_state: &str) -> Result<Identity, AccountError> {
+    let rejected = |msg: &str| AccountError::Rejected(msg.to_string());
+    let internal = |e: anyhow::Error| AccountError::Internal(e);
+    let flow = read_flow(state, flow_cookie).ok_or_else(|| rejected("The sign-in expired or was started in another browser. Try again."))?;
+    if flow.state != returned_state {
+        return Err(rejected("The sign-in expired or was started in another browser. Try again."));
+    }
+    let client = cfg.client().await.map_err(internal)?;
+    let response = client
+        .exchange_code(AuthorizationCode::new(code.to_string()))
+        .map_err(|e| internal(e.into()))?
+        .set_pkce_verifier(PkceCodeVerifier::new(flow.verifier))
+        .request_async(&cfg.http)
+        .await
+        .map_err(|e| {
+            tracing::warn!("OIDC token exchange failed: {}", e);
+            rejected("The identity provider did not accept the sign-in")
+        })?;
+    let id_token = response.id_token().ok_or_else(|| rejected("The identity provider sent no ID token"))?;
+    let claims = id_token.claims(&client.id_token_verifier(), &Nonce::new(flow.nonce)).map_err(|e| {
+        tracing::warn!("OIDC ID token rejected: {}", e);
+        rejected("The identity provider's ID token could not be verified")
+    })?;
+
+    // The token is verified above; its payload is read again for the configurable groups claim
+    let payload: serde_json::Value = id_token
+        .to_string()
+        .split('.')
+        .nth(1)
+        .and_then(|p| base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(p).ok())
+        .and_then(|p| serde_json::from_slice(&p).ok())
+        .unwrap_or_default();
+    let groups: Vec<String> = match payload.get(&cfg.groups_claim) {
+        Some(serde_json::Value::Array(items)) => items.iter().filter_map(|g| g.as_str().map(str::to_string)).collect(),
+        Some(serde_json::Value::String(g)) => vec![g.clone()],
+        _ => Vec::new(),
+    };
+    let username = claims
+        .preferred_username()
+        .map(|u| u.as_str().to_string())
+        .or_else(|| claims.email().map(|e| e.as_str().to_string()))
+        .unwrap_or_else(|| claims.subject().as_str().to_string());
+    let Some(role) = cfg.role_for(&groups) else {
+        tracing::warn!("OIDC login of '{}' refused: no role for groups {:?}", username, groups);
+        return Err(rejected("Your account has no ping0 role. Ask an administrator to add you to a ping0 group."));
+    };
+    Ok(Identity { subject: format!("{}|{}", claims.issuer().as_str(), claims.subject().as_str()), username, role })
+}
+
+// The admin row for an SSO identity, created on first login; the role follows the IdP each time,
+// except that the last active owner keeps the owner role
+pub fn upsert_user(conn: &mut Connection, identity: &Identity) -> Result<i64, AccountError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let existing: Option<(i64, Option<i64>, String)> = tx
+        .query_row(
+            "SELECT id, disabled_at, role FROM admin WHERE oidc_subject = ?1",
+            params![identity.subject],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )
+        .optional()?;
+    let id = match existing {
+        Some((_, Some(_), _)) => return Err(AccountError::Rejected("This account is disabled".to_string())),
+        Some((id, None, role)) => {
+            if role == Role::Owner.as_str() && identity.role != Role::Owner && admins::other_active_owners(&tx, id)? == 0 {
+                tracing::warn!("Kept the owner role of SSO admin {}: there must be at least one active owner", id);
+            } else {
+                tx.execute("UPDATE admin SET role = ?1 WHERE id = ?2", params![identity.role.as_str(), id])?;
+            }
+            id
+        }
+        None => {
+            // Local accounts keep their names; an SSO user with the same name gets a suffix
+            let mut username = identity.username.clone();
+            let mut n = 1;
+            while tx.query_row("SELECT EXISTS (SELECT 1 FROM admin WHERE username = ?1)", params![username], |r| r.get::<_, bool>(0))? {
+                n += 1;
+                username = format!("{}-{}", identity.username, n);
+            }
+            // No password: SSO users can't use the password form
+            tx.execute(
+                "INSERT INTO admin (username, password_hash, salt, role, created_at, oidc_subject) VALUES (?1, '!', '', ?2, ?3, ?4)",
+                params![username, identity.role.as_str(), now_secs(), identity.subject],
+            )?;
+            tracing::info!("Created SSO admin '{}' as {}", username, identity.role.as_str());
+            tx.last_insert_rowid()
+        }
+    };
+    tx.commit()?;
+    Ok(id)
+}
@@ -0,0 +1,135 @@
+// OpenAPI 3 document for the HTTP API, generated from the handler annotations and the request/response
+// types. Served as /api/openapi.json, with a bundled Swagger UI at /api/docs.
+
+use utoipa::openapi::path::PathItemType;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{Content, Ref, Server};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+use crate::{api, handlers};
+
+// Multipart bodies are read field by field, so these types only describe them for the document
+
+// Body of POST /api/upload: `content` is either a file or a URL as plain text
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ApiUploadForm {
+    #[schema(format = Binary)]
+    content: String,
+    // "true" to get a QR code
+    qr_required: Option<bool>,
+    alias: Option<String>,
+    // "1h", "1d", "7d", ... or "never"
+    expires_in: Option<String>,
+    max_views: Option<i64>,
+    burn_after_reading: Option<bool>,
+    password: Option<String>,
+}
+
+// Body of POST /upload
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    #[schema(format = Binary)]
+    file: String,
+}
+
+// Multipart body of POST /api/v1/items for file items
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct CreateFileItemForm {
+    #[schema(format = Binary)]
+    file: String,
+    qr: Option<bool>,
+    alias: Option<String>,
+    expires_in: Option<String>,
+    max_views: Option<i64>,
+    burn_after_reading: Option<bool>,
+    password: Option<String>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(description = "Short links and file sharing. Errors of /api/v1 use the `ErrorEnvelope` shape."),
+    paths(
+        handlers::api_upload,
+        handlers::upload_handler,
+        handlers::link_handler,
+        api::create_item,
+        api::list_items,
+        api::get_item,
+        api::update_item,
+        api::delete_item,
+        api::item_stats,
+        api::list_keys,
+        api::create_key,
+        api::revoke_key,
+        handlers::admin_login_post,
+        handlers::admin_logout,
+        handlers::admin_set_expiry,
+        handlers::admin_delete_item,
+    ),
+    components(schemas(
+        ApiUploadForm,
+        UploadForm,
+        CreateFileItemForm,
+        handlers::ApiUploadResponse,
+        handlers::ApiUploadError,
+        handlers::ShortLinkResponse,
+        handlers::LinkRequest,
+        handlers::AdminLoginForm,
+        handlers::AdminExpiryForm,
+        api::ItemResponse,
+        api::FileResponse,
+        api::ItemListResponse,
+        api::CreateItemRequest,
+        api::UpdateItemRequest,
+        api::StatsResponse,
+        api::CountResponse,
+        api::DayResponse,
+        api::KeyResponse,
+        api::CreatedKeyResponse,
+        api::CreateKeyRequest,
+        api::ErrorEnvelope,
+        api::ErrorBody,
+    )),
+    modifiers(&Extras),
+    tags(
+        (name = "upload", description = "Creating links and files from forms and scripts"),
+        (name = "items", description = "Versioned JSON API for items"),
+        (name = "keys", description = "API key management (admin scope)"),
+        (name = "admin", description = "Admin panel actions (session cookie; form posts also need the `csrf_token` field of the page they come from)"),
+    )
+)]
+pub struct ApiDoc;
+
+struct Extras;
+
+impl Modify for Extras {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("p0_...").build()),
+        );
+        components.add_security_scheme("session", SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("ping0_admin"))));
+
+        // POST /api/v1/items takes JSON for links and multipart for files; the path macro only knows one body type
+        if let Some(body) = openapi
+            .paths
+            .paths
+            .get_mut("/api/v1/items")
+            .and_then(|item| item.operations.get_mut(&PathItemType::Post))
+            .and_then(|op| op.request_body.as_mut())
+        {
+            body.content.insert("multipart/form-data".to_string(), Content::new(Ref::from_schema_name("CreateFileItemForm")));
+        }
+    }
+}
+
+// The document with BASE_URL as its server, so "Try it out" hits this instance
+pub fn document(base_url: &str) -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    doc.servers = Some(vec![Server::new(base_url)]);
+    doc
+}
@@ -0,0 +1,85 @@
+// Passkey ceremonies for the admin pages: turns the server's JSON options into
+// navigator.credentials calls and posts the results back.
+(function () {
+  const csrf = () => document.querySelector('meta[name="csrf-token"]').content;
+  const bytes = (s) => Uint8Array.from(atob(s.replace(/-/g, '+').replace(/_/g, '/')), (c) => c.charCodeAt(0));
+  const b64url = (buf) => btoa(String.fromCharCode(...new Uint8Array(buf))).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+
+  async function post(url, body) {
+    const resp = await fetch(url, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrf() },
+      body: JSON.stringify(body),
+    });
+    const data = await resp.json().catch(() => ({}));
+    if (!resp.ok) throw new Error(data.error || 'Request failed (' + resp.status + ')');
+    return data;
+  }
+
+  function showError(e) {
+    const el = document.getElementById('passkey-error');
+    if (el) el.textContent = e.name === 'NotAllowedError' ? 'Cancelled or timed out' : e.message;
+  }
+
+  async function register(name) {
+    const start = await post('/admin/passkeys/register/start', {});
+    const pk = start.options.publicKey;
+    pk.challenge = bytes(pk.challenge);
+    pk.user.id = bytes(pk.user.id);
+    (pk.excludeCredentials || []).forEach((c) => { c.id = bytes(c.id); });
+    const cred = await navigator.credentials.create({ publicKey: pk });
+    await post('/admin/passkeys/register/finish', {
+      ceremony: start.ceremony,
+      name: name,
+      credential: {
+        id: cred.id,
+        rawId: b64url(cred.rawId),
+        type: cred.type,
+        response: {
+          attestationObject: b64url(cred.response.attestationObject),
+          clientDataJSON: b64url(cred.response.clientDataJSON),
+        },
+        extensions: cred.getClientExtensionResults(),
+      },
+    });
+    location.reload();
+  }
+
+  async function login(username) {
+    const start = await post('/admin/login/passkey/start', { username: username });
+    const pk = start.options.publicKey;
+    pk.challenge = bytes(pk.challenge);
+    (pk.allowCredentials || []).forEach((c) => { c.id = bytes(c.id); });
+    const cred = await navigator.credentials.get({ publicKey: pk });
+    const r = cred.response;
+    const done = await post('/admin/login/passkey/finish', {
+      ceremony: start.ceremony,
+      credential: {
+        id: cred.id,
+        rawId: b64url(cred.rawId),
+        type: cred.type,
+        response: {
+          authenticatorData: b64url(r.authenticatorData),
+          clientDataJSON: b64url(r.clientDataJSON),
+          signature: b64url(r.signature),
+          userHandle: r.userHandle ? b64url(r.userHandle) : null,
+        },
+        extensions: cred.getClientExtensionResults(),
+      },
+    });
+    location.href = done.redirect;
+  }
+
+  document.addEventListener('DOMContentLoaded', () => {
+    const loginForm = document.getElementById('passkey-login');
+    if (loginForm) loginForm.addEventListener('submit', (ev) => {
+      ev.preventDefault();
+      login(loginForm.username.value).catch(showError);
+    });
+    const registerForm = document.getElementById('passkey-register');
+    if (registerForm) registerForm.addEventListener('submit', (ev) => {
+      ev.preventDefault();
+      register(registerForm.name.value).catch(showError);
+    });
+  });
+})();
@@ -0,0 +1,269 @@
+// Passkey (WebAuthn) login for admins. Admins register credentials under /admin/passkeys; the login
+// page then offers "Sign in with a passkey" for a username. The relying party is the host of BASE_URL,
+// so passkeys only work when admins open the panel at that URL (https, or http://localhost).
+// Ceremony state lives in the database for a few minutes between the start and finish requests.
+// Unknown usernames and users without passkeys get a believable challenge too, so the login form
+// doesn't tell which accounts exist.
+
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, Passkey, PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential,
+    RegisterPublicKeyCredential, RequestChallengeResponse, Url, Uuid, Webauthn, WebauthnBuilder,
+};
+use webauthn_rs_proto::AllowCredentials;
+
+use crate::admins::{AccountError, AdminUser};
+use crate::handlers::now_secs;
+
+const CEREMONY_ID_LEN: usize = 32;
+const CEREMONY_TTL_SECS: i64 = 5 * 60;
+const MAX_NAME_LEN: usize = 64;
+
+// None if BASE_URL can't be a WebAuthn origin; the panel then works with passwords only
+pub fn build(base_url: &str) -> Option<Webauthn> {
+    let origin = Url::parse(base_url).ok()?;
+    let rp_id = origin.host_str()?.to_string();
+    match WebauthnBuilder::new(&rp_id, &origin).and_then(|b| b.rp_name("ping0").build()) {
+        Ok(webauthn) => Some(webauthn),
+        Err(e) => {
+            tracing::warn!("Passkeys disabled: BASE_URL {} is not usable for WebAuthn ({})", base_url, e);
+            None
+        }
+    }
+}
+
+fn rejected<T>(message: &str) -> Result<T, AccountError> {
+    Err(AccountError::Rejected(message.to_string()))
+}
+
+pub fn any_registered(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM admin_passkeys p JOIN admin a ON a.id = p.user_id WHERE a.disabled_at IS NULL)",
+        [],
+        |r| r.get(0),
+    )
+}
+
+pub struct PasskeyRow {
+    pub id: i64,
+    pub name: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+pub fn list(conn: &Connection, user_id: i64) -> rusqlite::Result<Vec<PasskeyRow>> {
+    let mut stmt = conn.prepare("SELECT id, name, created_at, last_used_at FROM admin_passkeys WHERE user_id = ?1 ORDER BY id")?;
+    let rows = stmt.query_map(params![user_id], |r| {
+        Ok(PasskeyRow { id: r.get(0)?, name: r.get(1)?, created_at: r.get(2)?, last_used_at: r.get(3)? })
+    })?;
+    rows.collect()
+}
+
+pub fn delete(conn: &Connection, user: &AdminUser, id: i64) -> rusqlite::Result<bool> {
+    let n = conn.execute("DELETE FROM admin_passkeys WHERE id = ?1 AND user_id = ?2", params![id, user.id])?;
+    Ok(n == 1)
+}
+
+fn stored_passkeys(conn: &Connection, user_id: i64) -> rusqlite::Result<Vec<(i64, Passkey)>> {
+    let mut stmt = conn.prepare("SELECT id, passkey FROM admin_passkeys WHERE user_id = ?1")?;
+    let rows = stmt.query_map(params![user_id], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?;
+    Ok(rows
+        .flatten()
+        .filter_map(|(id, json)| serde_json::from_str(&json).ok().map(|pk| (id, pk)))
+        .collect())
+}
+
+// WebAuthn user handle: a random UUID per admin, created with their first passkey
+fn user_handle(conn: &Connection, user_id: i64) -> rusqlite::Result<Uuid> {
+    let existing: Option<String> = conn
+        .query_row("SELECT webauthn_id FROM admin WHERE id = ?1", params![user_id], |r| r.get(0))
+        .optional()?
+        .flatten();
+    if let Some(id) = existing.and_then(|s| Uuid::parse_str(&s).ok()) {
+        return Ok(id);
+    }
+    let id = Uuid::new_v4();
+    conn.execute("UPDATE admin SET webauthn_id = ?1 WHERE id = ?2", params![id.to_string(), user_id])?;
+    Ok(id)
+}
+
+fn save_ceremony(conn: &Connection, user_id: i64, kind: &str, state: &impl Serialize) -> Result<String, AccountError> {
+    conn.execute("DELETE FROM webauthn_ceremonies WHERE expires_at <= ?1", params![now_secs()])?;
+    let id: String = rand::thread_rng().sample_iter(&Alphanumeric).take(CEREMONY_ID_LEN).map(char::from).collect();
+    let state = serde_json::to_string(state).map_err(|e| AccountError::Internal(e.into()))?;
+    conn.execute(
+        "INSERT INTO webauthn_ceremonies (id, user_id, kind, state, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![id, user_id, kind, state, now_secs() + CEREMONY_TTL_SECS],
+    )?;
+    Ok(id)
+}
+
+// Ceremonies are single use: the row is gone whether or not the finish step succeeds
+fn take_ceremony<T: DeserializeOwned>(conn: &Connection, id: &str, kind: &str) -> Result<(i64, T), AccountError> {
+    let row: Option<(i64, String, i64)> = conn
+        .query_row(
+            "SELECT user_id, state, expires_at FROM webauthn_ceremonies WHERE id = ?1 AND kind = ?2",
+            params![id, kind],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )
+        .optional()?;
+    conn.execute("DELETE FROM webauthn_ceremonies WHERE id = ?1", params![id])?;
+    match row {
+        Some((user_id, state, expires_at)) if expires_at > now_secs() => {
+            let state = serde_json::from_str(&state).map_err(|e| AccountError::Internal(e.into()))?;
+            Ok((user_id, state))
+        }
+        _ => rejected("This passkey request expired, try again"),
+    }
+}
+
+// ---------- Registration ----------
+
+pub fn start_registration(webauthn: &Webauthn, conn: &Connection, user: &AdminUser) -> Result<(String, CreationChallengeResponse), AccountError> {
+    let handle = user_handle(conn, user.id)?;
+    let exclude = stored_passkeys(conn, user.id)?.into_iter().map(|(_, pk)| pk.cred_id().clone()).collect::<Vec<_>>();
+    let (challenge, state) = webauthn
+        .start_passkey_registration(handle, &user.username, &user.username, Some(exclude))
+        .map_err(|e| AccountError::Internal(e.into()))?;
+    let ceremony = save_ceremony(conn, user.id, "register", &state)?;
+    Ok((ceremony, challenge))
+}
+
+pub fn finish_registration(
+    webauthn: &Webauthn,
+    conn: &Connection,
+    user: &AdminUser,
+    ceremony: &str,
+    name: &str,
+    credential: &RegisterPublicKeyCredential,
+) -> Result<(), AccountError> {
+    let (owner, state): (i64, PasskeyRegistration) = take_ceremony(conn, ceremony, "register")?;
+    if owner != user.id {
+        return rejected("This passkey request belongs to another session");
+    }
+    let passkey = match webauthn.finish_passkey_registration(credential, &state) {
+        Ok(passkey) => passkey,
+        Err(e) => {
+            tracing::warn!("Passkey registration for '{}' failed: {}", user.username, e);
+            return rejected("The passkey could not be verified");
+        }
+    };
+    let name: String = match name.trim() {
+        "" => "Passkey".to_string(),
+        name => name.chars().take(MAX_NAME_LEN).collect(),
+    };
+    let json = serde_json::to_string(&passkey).map_err(|e| AccountError::Internal(e.into()))?;
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO admin_passkeys (user_id, name, credential_id, passkey, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![user.id, name, hex::encode(passkey.cred_id().as_ref()), json, now_secs()],
+    )?;
+    if inserted == 0 {
+        return rejected("This passkey is already registered");
+    }
+    tracing::info!("Admin '{}' registered passkey '{}'", user.username, name);
+    Ok(())
+}
+
+// ---------- Login ----------
+
+// Login ceremonies remember the username that was typed, for throttling the finish step. Decoy
+// ceremonies (user_id 0) have no state and can't succeed.
+#[derive(Serialize, Deserialize)]
+struct LoginCeremony {
+    username: String,
+    state: Option<PasskeyAuthentication>,
+}
+
+// Credential id lengths seen from common authenticators (password managers, Apple, Windows Hello)
+const DECOY_ID_LENS: [usize; 3] = [16, 20, 32];
+
+// The same made-up credential every time for a given username, so repeated requests don't give it away
+fn decoy_challenge(webauthn: &Webauthn, secret: &[u8], username: &str) -> Result<RequestChallengeResponse, AccountError> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(b"passkey-decoy:");
+    mac.update(username.as_bytes());
+    let bytes = mac.finalize().into_bytes();
+    let len = DECOY_ID_LENS[bytes[0] as usize % DECOY_ID_LENS.len()];
+    let (mut challenge, _) = webauthn.start_discoverable_authentication().map_err(|e| AccountError::Internal(e.into()))?;
+    // Shaped like start_passkey_authentication's options
+    challenge.mediation = None;
+    challenge.public_key.extensions = None;
+    challenge.public_key.allow_credentials =
+        vec![AllowCredentials { type_: "public-key".to_string(), id: bytes[..len].to_vec().into(), transports: None }];
+    Ok(challenge)
+}
+
+pub fn start_login(webauthn: &Webauthn, conn: &Connection, secret: &[u8], username: &str) -> Result<(String, RequestChallengeResponse), AccountError> {
+    let username = username.trim();
+    let user_id: Option<i64> = conn
+        .query_row("SELECT id FROM admin WHERE username = ?1 AND disabled_at IS NULL", params![username], |r| r.get(0))
+        .optional()?;
+    let passkeys = match user_id {
+        Some(id) => stored_passkeys(conn, id)?,
+        None => Vec::new(),
+    };
+    let (Some(user_id), false) = (user_id, passkeys.is_empty()) else {
+        let challenge = decoy_challenge(webauthn, secret, username)?;
+        let ceremony = save_ceremony(conn, 0, "login", &LoginCeremony { username: username.to_string(), state: None })?;
+        return Ok((ceremony, challenge));
+    };
+    let passkeys: Vec<Passkey> = passkeys.into_iter().map(|(_, pk)| pk).collect();
+    let (challenge, state) = webauthn
+        .start_passkey_authentication(&passkeys)
+        .map_err(|e| AccountError::Internal(e.into()))?;
+    let ceremony = save_ceremony(conn, user_id, "login", &LoginCeremony { username: username.to_string(), state: Some(state) })?;
+    Ok((ceremony, challenge))
+}
+
+// The username a pending login ceremony was started for
+pub fn login_username(conn: &Connection, ceremony: &str) -> rusqlite::Result<Option<String>> {
+    let state: Option<String> = conn
+        .query_row(
+            "SELECT state FROM webauthn_ceremonies WHERE id = ?1 AND kind = 'login' AND expires_at > ?2",
+            params![ceremony, now_secs()],
+            |r| r.get(0),
+        )
+        .optional()?;
+    Ok(state.and_then(|s| serde_json::from_str::<LoginCeremony>(&s).ok()).map(|c| c.username))
+}
+
+// Returns the id of the admin who signed in
+pub fn finish_login(webauthn: &Webauthn, conn: &Connection, ceremony: &str, credential: &PublicKeyCredential) -> Result<i64, AccountError> {
+    let (user_id, login): (i64, LoginCeremony) = take_ceremony(conn, ceremony, "login")?;
+    let Some(state) = login.state else {
+        return rejected("The passkey could not be verified");
+    };
+    let result = match webauthn.finish_passkey_authentication(credential, &state) {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::warn!("Passkey login for admin {} failed: {}", user_id, e);
+            return rejected("The passkey could not be verified");
+        }
+    };
+    // The user may have been disabled since the ceremony started
+    let active: bool = conn
+        .query_row("SELECT EXISTS (SELECT 1 FROM admin WHERE id = ?1 AND disabled_at IS NULL)", params![user_id], |r| r.get(0))?;
+    if !active {
+        return rejected("The passkey could not be verified");
+    }
+    for (id, mut passkey) in stored_passkeys(conn, user_id)? {
+        if passkey.cred_id() != result.cred_id() {
+            continue;
+        }
+        // Keep the signature counter and backup state current
+        let json = match passkey.update_credential(&result) {
+            Some(true) => serde_json::to_string(&passkey).ok(),
+            _ => None,
+        };
+        conn.execute(
+            "UPDATE admin_passkeys SET last_used_at = ?1, passkey = COALESCE(?2, passkey) WHERE id = ?3",
+            params![now_secs(), json, id],
+        )?;
+    }
+    Ok(user_id)
+}
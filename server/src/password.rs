@@ -0,0 +1,53 @@
+// Password hashing (Argon2id, PHC string format) for protected items and admin accounts
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+}
+
+// Argon2 takes tens of milliseconds of CPU; async code runs it on the blocking pool, outside any
+// database call so it doesn't hold a pooled connection either
+pub async fn hash_password_async(password: String) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || hash_password(&password))
+        .await
+        .unwrap_or_else(|e| Err(format!("Failed to hash password: {}", e)))
+}
+
+pub async fn verify_password_async(password: String, hash: String) -> bool {
+    tokio::task::spawn_blocking(move || verify_password(&password, &hash)).await.unwrap_or(false)
+}
+
+// Argon2id hash of a throwaway password, made with the same parameters as hash_password
+const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$VKxXXh0h3gkvHb6XKSQDPA$7Crez76jcftx2p4JdQG2VJGhLSXmoYm+0K3+7WecYpI";
+
+// Spends as long as verifying a real hash, for logins that have no hash to check (unknown users), so
+// response times don't tell which usernames exist
+pub async fn verify_dummy(password: String) {
+    verify_password_async(password, DUMMY_HASH.to_string()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dummy_hash_costs_as_much_as_a_real_one() {
+        let real = hash_password("a password").unwrap();
+        let (real, dummy) = (PasswordHash::new(&real).unwrap(), PasswordHash::new(DUMMY_HASH).unwrap());
+        assert_eq!(real.algorithm, dummy.algorithm);
+        assert_eq!(real.version, dummy.version);
+        assert_eq!(real.params, dummy.params);
+        assert!(!verify_password("a password", DUMMY_HASH));
+    }
+}
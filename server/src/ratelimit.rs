@@ -0,0 +1,398 @@
+// Rate limits for the endpoints that create items: /submit, /upload, /link, /api/upload, POST /api/v1/items
+// and resumable uploads under /api/tus. Every client has three token buckets: requests per minute, uploaded
+// bytes per hour and new items per day. Requests with a valid API key draw from that key's buckets, all
+// others from their IP's (see clientip.rs for proxies). The buckets live in memory, so a restart refills them.
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use futures::TryStreamExt;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::api::ApiError;
+use crate::apikeys;
+use crate::handlers::{api_upload_error, AppState};
+
+const REQUESTS: usize = 0;
+const BYTES: usize = 1;
+const ITEMS: usize = 2;
+const EXCEEDED: [&str; 3] = ["too many requests", "hourly upload quota used up", "daily item limit reached"];
+
+// The limit that was hit and the seconds until it allows the request, or None if the request is more
+// than the limit itself
+type Denied = (usize, Option<u64>);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Client {
+    Ip(IpAddr),
+    Key(i64),
+}
+
+#[derive(Clone, Copy)]
+struct Rate {
+    capacity: f64,
+    per_sec: f64,
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, rate: Rate, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.per_sec).min(rate.capacity);
+        self.updated = now;
+    }
+}
+
+// Set when a chunked body ran out of upload quota while the handler was reading it
+type Tripped = Arc<Mutex<Option<Denied>>>;
+
+pub struct RateLimiter {
+    // REQUESTS, BYTES and ITEMS; None when that limit is off
+    rates: [Option<Rate>; 3],
+    buckets: Mutex<HashMap<Client, [Bucket; 3]>>,
+    clock: Box<dyn Fn() -> Instant + Send + Sync>,
+}
+
+// `amount` per `period_secs` from the variable `name`; 0 turns the limit off
+fn rate(var: &impl Fn(&str) -> Option<String>, name: &str, default: u64, scale: u64, period_secs: u64) -> Option<Rate> {
+    let amount = var(name).and_then(|v| v.trim().parse().ok()).unwrap_or(default);
+    (amount > 0).then(|| {
+        let capacity = (amount * scale) as f64;
+        Rate { capacity, per_sec: capacity / period_secs as f64 }
+    })
+}
+
+impl RateLimiter {
+    pub fn from_env() -> RateLimiter {
+        RateLimiter::from_vars(|name| std::env::var(name).ok())
+    }
+
+    // from_env with the variables looked up through `var`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> RateLimiter {
+        RateLimiter {
+            rates: [
+                rate(&var, "RATE_LIMIT_REQUESTS_PER_MINUTE", 60, 1, 60),
+                rate(&var, "RATE_LIMIT_UPLOAD_MB_PER_HOUR", 2048, 1024 * 1024, 60 * 60),
+                rate(&var, "RATE_LIMIT_ITEMS_PER_DAY", 500, 1, 24 * 60 * 60),
+            ],
+            buckets: Mutex::new(HashMap::new()),
+            clock: Box::new(Instant::now),
+        }
+    }
+
+    // Takes `cost` from all buckets, or from none if one of them is short
+    fn take(&self, client: Client, cost: [f64; 3]) -> Result<(), Denied> {
+        let now = (self.clock)();
+        let mut buckets = self.buckets.lock().unwrap();
+        let buckets = buckets.entry(client).or_insert_with(|| {
+            self.rates.map(|rate| Bucket { tokens: rate.map_or(0.0, |r| r.capacity), updated: now })
+        });
+        let mut denied: Option<Denied> = None;
+        for (i, rate) in self.rates.iter().enumerate() {
+            let Some(rate) = *rate else { continue };
+            buckets[i].refill(rate, now);
+            if buckets[i].tokens >= cost[i] {
+                continue;
+            }
+            if cost[i] > rate.capacity {
+                return Err((i, None));
+            }
+            let wait = (((cost[i] - buckets[i].tokens) / rate.per_sec).ceil() as u64).max(1);
+            if denied.is_none_or(|(_, w)| w.is_some_and(|w| wait > w)) {
+                denied = Some((i, Some(wait)));
+            }
+        }
+        if let Some(denied) = denied {
+            return Err(denied);
+        }
+        for (i, rate) in self.rates.iter().enumerate() {
+            if rate.is_some() {
+                buckets[i].tokens -= cost[i];
+            }
+        }
+        Ok(())
+    }
+
+    // Charges a request to `client`'s buckets. A body with a Content-Length is charged up front; a chunked
+    // one as it is read, and once the quota runs out the body fails and the returned flag is set.
+    fn charge(self: &Arc<Self>, client: Client, creates: bool, req: Request) -> Result<(Request, Tripped), Denied> {
+        let length = req.headers().get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+        let mut cost = [0.0; 3];
+        cost[REQUESTS] = 1.0;
+        cost[BYTES] = length.unwrap_or(0) as f64;
+        cost[ITEMS] = if creates { 1.0 } else { 0.0 };
+        self.take(client, cost)?;
+        let exceeded = Tripped::default();
+        if length.is_some() || self.rates[BYTES].is_none() {
+            return Ok((req, exceeded));
+        }
+
+        let (parts, body) = req.into_parts();
+        let limiter = self.clone();
+        let flag = exceeded.clone();
+        let counted = body.into_data_stream().map_err(io::Error::other).and_then(move |chunk| {
+            let mut cost = [0.0; 3];
+            cost[BYTES] = chunk.len() as f64;
+            let charged = limiter.take(client, cost).map_err(|e| {
+                *flag.lock().unwrap() = Some(e);
+                io::Error::other(EXCEEDED[BYTES])
+            });
+            async move { charged.map(|()| chunk) }
+        });
+        Ok((Request::from_parts(parts, Body::from_stream(counted)), exceeded))
+    }
+
+    // Forgets clients whose buckets are full again
+    pub fn prune(&self) {
+        let now = (self.clock)();
+        let rates = self.rates;
+        self.buckets.lock().unwrap().retain(|_, buckets| {
+            rates.iter().zip(buckets.iter_mut()).any(|(rate, bucket)| match rate {
+                Some(rate) => {
+                    bucket.refill(*rate, now);
+                    bucket.tokens < rate.capacity
+                }
+                None => false,
+            })
+        });
+    }
+}
+
+enum ErrorStyle {
+    Text,
+    // /api/upload's `{"success": false, "error": ...}`
+    Upload,
+    Api,
+}
+
+// The style of error responses for rate-limited routes and whether the request creates an item
+fn classify(method: &Method, path: &str) -> Option<(ErrorStyle, bool)> {
+    match (method.as_str(), path) {
+        ("POST", "/submit" | "/upload" | "/link") => Some((ErrorStyle::Text, true)),
+        ("POST", "/api/upload") => Some((ErrorStyle::Upload, true)),
+        ("POST", "/api/v1/items" | "/api/tus") => Some((ErrorStyle::Api, true)),
+        ("PATCH", p) if p.starts_with("/api/tus/") => Some((ErrorStyle::Api, false)),
+        _ => None,
+    }
+}
+
+// 429 with Retry-After, or 413 for a body bigger than the whole upload quota
+fn denied(style: ErrorStyle, limit: usize, wait: Option<u64>) -> Response {
+    let (status, code, message) = match wait {
+        Some(wait) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limited",
+            format!("Rate limit exceeded: {}. Try again in {} seconds.", EXCEEDED[limit], wait),
+        ),
+        None => (StatusCode::PAYLOAD_TOO_LARGE, "quota_exceeded", "Upload is larger than the hourly upload quota".to_string()),
+    };
+    let mut resp = match style {
+        ErrorStyle::Text => (status, message).into_response(),
+        ErrorStyle::Upload => api_upload_error(status, message),
+        ErrorStyle::Api => ApiError::new(status, code, message).into_response(),
+    };
+    if let Some(wait) = wait {
+        resp.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(wait));
+    }
+    resp
+}
+
+// The buckets a request draws from: its API key's if it has a valid one, otherwise its IP's
+fn client(key: Option<i64>, ip: Option<IpAddr>) -> Option<Client> {
+    match (key, ip) {
+        (Some(id), _) => Some(Client::Key(id)),
+        (None, Some(ip)) => Some(Client::Ip(ip)),
+        (None, None) => None,
+    }
+}
+
+// Middleware: charges the request to its client's buckets before the handler runs (see
+// RateLimiter::charge), and replaces the handler's response with a 429 if a chunked body ran over
+pub async fn limit(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let Some((style, creates)) = classify(req.method(), req.uri().path()) else {
+        return next.run(req).await;
+    };
+    let headers = req.headers().clone();
+    let key = match state.db.query(move |conn| apikeys::bearer_key_id(conn, &headers)).await {
+        Ok(key) => key,
+        Err(e) => {
+            tracing::error!("rate limit: {}", e);
+            None
+        }
+    };
+    let ip = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(peer)| state.trusted_proxies.client_ip(peer.ip(), req.headers()));
+    let Some(client) = client(key, ip) else {
+        return next.run(req).await;
+    };
+    let (method, path) = (req.method().clone(), req.uri().path().to_string());
+    let (req, exceeded) = match state.rate_limiter.charge(client, creates, req) {
+        Ok(charged) => charged,
+        Err((limit, wait)) => {
+            tracing::warn!("Rate limit for {:?} on {} {}: {}", client, method, path, EXCEEDED[limit]);
+            return denied(style, limit, wait);
+        }
+    };
+    let resp = next.run(req).await;
+    let tripped = *exceeded.lock().unwrap();
+    match tripped {
+        Some((limit, wait)) => {
+            tracing::warn!("Rate limit for {:?}: {} during upload", client, EXCEEDED[limit]);
+            denied(style, limit, wait)
+        }
+        None => resp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Bytes;
+    use std::time::Duration;
+
+    const MB: usize = 1024 * 1024;
+
+    // A limiter with only the given limits on, and a clock that moves when the test says so
+    fn limiter(vars: &[(&str, &str)]) -> (Arc<RateLimiter>, Arc<Mutex<Instant>>) {
+        let mut limiter = RateLimiter::from_vars(|name| {
+            Some(vars.iter().find(|(k, _)| *k == name).map_or("0", |(_, v)| v).to_string())
+        });
+        let clock = Arc::new(Mutex::new(Instant::now()));
+        let now = clock.clone();
+        limiter.clock = Box::new(move || *now.lock().unwrap());
+        (Arc::new(limiter), clock)
+    }
+
+    fn advance(clock: &Mutex<Instant>, secs: u64) {
+        *clock.lock().unwrap() += Duration::from_secs(secs);
+    }
+
+    fn request(length: Option<usize>, body: Body) -> Request {
+        let mut req = Request::post("/api/v1/items");
+        if let Some(length) = length {
+            req = req.header(header::CONTENT_LENGTH, length);
+        }
+        req.body(body).unwrap()
+    }
+
+    fn chunked(chunks: usize, size: usize) -> Request {
+        let chunks = (0..chunks).map(move |_| Ok::<_, io::Error>(Bytes::from(vec![0u8; size])));
+        request(None, Body::from_stream(futures::stream::iter(chunks)))
+    }
+
+    fn charge(limiter: &Arc<RateLimiter>, client: Client, req: Request) -> Result<(Request, Tripped), Denied> {
+        limiter.charge(client, false, req)
+    }
+
+    const IP: Client = Client::Ip(IpAddr::V4(std::net::Ipv4Addr::new(198, 51, 100, 4)));
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let (limiter, clock) = limiter(&[("RATE_LIMIT_REQUESTS_PER_MINUTE", "2")]);
+        let request = [1.0, 0.0, 0.0];
+        assert!(limiter.take(IP, request).is_ok());
+        assert!(limiter.take(IP, request).is_ok());
+        assert_eq!(limiter.take(IP, request), Err((REQUESTS, Some(30))));
+        advance(&clock, 29);
+        assert_eq!(limiter.take(IP, request), Err((REQUESTS, Some(1))));
+        advance(&clock, 1);
+        assert!(limiter.take(IP, request).is_ok());
+        assert!(limiter.take(IP, request).is_err());
+
+        // Never more than the capacity, however long the client was away
+        advance(&clock, 3600);
+        assert!(limiter.take(IP, request).is_ok());
+        assert!(limiter.take(IP, request).is_ok());
+        assert!(limiter.take(IP, request).is_err());
+    }
+
+    #[test]
+    fn content_length_is_charged_up_front() {
+        let (limiter, clock) = limiter(&[("RATE_LIMIT_UPLOAD_MB_PER_HOUR", "1")]);
+        assert!(charge(&limiter, IP, request(Some(600_000), Body::empty())).is_ok());
+        // 151424 bytes short at 1 MiB per hour
+        assert_eq!(charge(&limiter, IP, request(Some(600_000), Body::empty())).err(), Some((BYTES, Some(520))));
+        assert_eq!(charge(&limiter, IP, request(Some(MB + 1), Body::empty())).err(), Some((BYTES, None)));
+        advance(&clock, 520);
+        assert!(charge(&limiter, IP, request(Some(600_000), Body::empty())).is_ok());
+    }
+
+    #[tokio::test]
+    async fn chunked_bodies_are_charged_as_they_are_read() {
+        let (limiter, clock) = limiter(&[("RATE_LIMIT_UPLOAD_MB_PER_HOUR", "1")]);
+        let (req, tripped) = charge(&limiter, IP, chunked(2, 400_000)).unwrap();
+        assert_eq!(axum::body::to_bytes(req.into_body(), usize::MAX).await.unwrap().len(), 800_000);
+        assert_eq!(*tripped.lock().unwrap(), None);
+
+        // The first chunk still fits in what is left, the second doesn't
+        let (req, tripped) = charge(&limiter, IP, chunked(2, 200_000)).unwrap();
+        assert!(axum::body::to_bytes(req.into_body(), usize::MAX).await.is_err());
+        assert_eq!(*tripped.lock().unwrap(), Some((BYTES, Some(520))));
+
+        advance(&clock, 520);
+        assert!(limiter.take(IP, [0.0, 200_000.0, 0.0]).is_ok());
+    }
+
+    #[test]
+    fn items_per_day() {
+        let (limiter, clock) = limiter(&[("RATE_LIMIT_ITEMS_PER_DAY", "2")]);
+        // Continuing an upload doesn't create an item
+        for _ in 0..5 {
+            assert!(limiter.charge(IP, false, request(Some(0), Body::empty())).is_ok());
+        }
+        assert!(limiter.charge(IP, true, request(Some(0), Body::empty())).is_ok());
+        assert!(limiter.charge(IP, true, request(Some(0), Body::empty())).is_ok());
+        assert_eq!(limiter.charge(IP, true, request(Some(0), Body::empty())).err(), Some((ITEMS, Some(12 * 60 * 60))));
+        advance(&clock, 12 * 60 * 60);
+        assert!(limiter.charge(IP, true, request(Some(0), Body::empty())).is_ok());
+        assert!(limiter.charge(IP, true, request(Some(0), Body::empty())).is_err());
+    }
+
+    #[test]
+    fn keys_and_ips_have_their_own_buckets() {
+        let ip = IpAddr::V4(std::net::Ipv4Addr::new(198, 51, 100, 4));
+        assert_eq!(client(Some(1), Some(ip)), Some(Client::Key(1)));
+        assert_eq!(client(Some(1), None), Some(Client::Key(1)));
+        assert_eq!(client(None, Some(ip)), Some(Client::Ip(ip)));
+        assert_eq!(client(None, None), None);
+
+        let (limiter, _) = limiter(&[("RATE_LIMIT_REQUESTS_PER_MINUTE", "1")]);
+        let request = [1.0, 0.0, 0.0];
+        assert!(limiter.take(Client::Key(1), request).is_ok());
+        assert!(limiter.take(Client::Key(1), request).is_err());
+        assert!(limiter.take(Client::Ip(ip), request).is_ok());
+        assert!(limiter.take(Client::Key(2), request).is_ok());
+        assert!(limiter.take(Client::Ip(ip), request).is_err());
+    }
+
+    #[test]
+    fn a_denied_request_costs_nothing() {
+        let (limiter, _) = limiter(&[("RATE_LIMIT_REQUESTS_PER_MINUTE", "5"), ("RATE_LIMIT_ITEMS_PER_DAY", "1")]);
+        assert!(limiter.take(IP, [1.0, 0.0, 1.0]).is_ok());
+        assert_eq!(limiter.take(IP, [1.0, 0.0, 1.0]), Err((ITEMS, Some(24 * 60 * 60))));
+        for _ in 0..4 {
+            assert!(limiter.take(IP, [1.0, 0.0, 0.0]).is_ok());
+        }
+        assert_eq!(limiter.take(IP, [1.0, 0.0, 0.0]).err().map(|(limit, _)| limit), Some(REQUESTS));
+    }
+
+    #[test]
+    fn zero_turns_a_limit_off() {
+        let (limiter, _) = limiter(&[]);
+        for _ in 0..1000 {
+            assert!(limiter.charge(IP, true, request(Some(10 * MB), Body::empty())).is_ok());
+        }
+        let defaults = RateLimiter::from_vars(|_| None);
+        assert!(defaults.rates.iter().all(Option::is_some));
+    }
+}
@@ -0,0 +1,64 @@
+// Background task that removes expired items (rows, stored files and previews),
+// abandoned resumable uploads, expired admin sessions,
+// stale login attempt counters and idle rate limit buckets.
+
+use rusqlite::params;
+use std::time::Duration;
+
+use crate::handlers::{delete_item_files, delete_item_row, now_secs, AppState};
+use crate::tus;
+
+// Items are deleted in batches so one pass never holds the database for long
+const BATCH: i64 = 500;
+
+pub fn spawn(state: AppState, every: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        loop {
+            interval.tick().await;
+            reap_expired_items(&state).await;
+            tus::purge_expired(&state).await;
+            purge_sessions(&state).await;
+            purge_login_attempts(&state).await;
+            state.rate_limiter.prune();
+        }
+    });
+}
+
+async fn reap_expired_items(state: &AppState) {
+    loop {
+        let expired: Vec<(String, String, String)> = match state.db.query(|conn| {
+            let mut stmt = conn.prepare("SELECT code, kind, value FROM items WHERE expires_at IS NOT NULL AND expires_at <= ?1 LIMIT ?2")?;
+            let rows = stmt.query_map(params![now_secs(), BATCH], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?;
+            rows.collect()
+        }).await {
+            Ok(rows) => rows,
+            Err(e) => { tracing::warn!("reaper: {}", e); return; }
+        };
+        if expired.is_empty() { return; }
+
+        for (code, kind, value) in &expired {
+            delete_item_files(state.storage.as_ref(), kind, value).await;
+            let code = code.clone();
+            if let Err(e) = state.db.query(move |conn| delete_item_row(conn, &code)).await {
+                tracing::warn!("reaper: {}", e);
+            }
+        }
+        tracing::info!("reaper: removed {} expired items", expired.len());
+        if (expired.len() as i64) < BATCH { return; }
+    }
+}
+
+async fn purge_sessions(state: &AppState) {
+    match state.db.query(|conn| crate::admins::purge_expired_sessions(conn)).await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("reaper: removed {} expired sessions", n),
+        Err(e) => tracing::warn!("reaper: {}", e),
+    }
+}
+
+async fn purge_login_attempts(state: &AppState) {
+    if let Err(e) = state.db.query(|conn| crate::throttle::purge_stale(conn)).await {
+        tracing::warn!("reaper: {}", e);
+    }
+}
@@ -0,0 +1,108 @@
+// Every route of the app with its middleware; main serves it, the integration tests drive it directly
+
+use axum::extract::DefaultBodyLimit;
+use axum::http::Method;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::json;
+use tower_http::cors::{Any, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::handlers::AppState;
+use crate::{api, csrf, handlers, openapi, ratelimit, tus};
+
+async fn health_check() -> Json<serde_json::Value> {
+    Json(json!({
+        "status": "healthy",
+        "service": "ping0"
+    }))
+}
+
+pub fn router(state: AppState) -> Router {
+    let base_url = state.base_url.clone();
+    Router::new()
+        .route("/health", get(health_check))
+        // CORS preflight: explicitly handle OPTIONS on API endpoints
+        .route("/api/upload", axum::routing::options(handlers::cors_preflight))
+        .route("/", get(handlers::index_handler))
+        .route("/submit", post(handlers::submit_handler))
+        .route("/upload", post(handlers::upload_handler))
+        .route("/link", post(handlers::link_handler))
+        .route("/api/upload", post(handlers::api_upload))
+        // Versioned JSON API
+        .route("/api/v1/items", get(api::list_items).post(api::create_item))
+        .route("/api/v1/items/:code", get(api::get_item).patch(api::update_item).delete(api::delete_item))
+        .route("/api/v1/items/:code/stats", get(api::item_stats))
+        .route("/api/v1/keys", get(api::list_keys).post(api::create_key))
+        .route("/api/v1/keys/:id", axum::routing::delete(api::revoke_key))
+        // Resumable uploads (tus 1.0)
+        .route("/api/tus", axum::routing::options(tus::tus_options).post(tus::tus_create))
+        .route(
+            "/api/tus/:id",
+            axum::routing::head(tus::tus_head)
+                .get(tus::tus_result)
+                .patch(tus::tus_patch)
+                .delete(tus::tus_delete)
+                .options(tus::tus_options),
+        )
+        .route("/r/:code", get(handlers::result_handler))
+        .route("/s/:code", get(handlers::short_handler).post(handlers::short_unlock))
+        // Admin routes
+        .route("/admin/login", get(handlers::admin_login_get))
+        .route("/admin/login", post(handlers::admin_login_post))
+        .route("/admin/login/passkey/start", post(handlers::admin_passkey_login_start))
+        .route("/admin/login/passkey/finish", post(handlers::admin_passkey_login_finish))
+        .route("/admin/passkey.js", get(handlers::admin_passkey_js))
+        .route("/admin/oidc/login", get(handlers::admin_oidc_login))
+        .route("/admin/oidc/callback", get(handlers::admin_oidc_callback))
+        .route("/admin/login/2fa", get(handlers::admin_login_code_get).post(handlers::admin_login_code_post))
+        .route("/admin/setup", get(handlers::admin_setup_get).post(handlers::admin_setup_post))
+        .route("/admin/logout", post(handlers::admin_logout))
+        .route("/admin", get(handlers::admin_home))
+        .route("/admin/items", get(handlers::admin_items))
+        .route("/admin/items/:code/delete", post(handlers::admin_delete_item))
+        .route("/admin/items/:code/expiry", post(handlers::admin_set_expiry))
+        .route("/admin/items/:code/stats", get(handlers::admin_item_stats))
+        .route("/admin/keys", get(handlers::admin_keys).post(handlers::admin_create_key))
+        .route("/admin/keys/:id/revoke", post(handlers::admin_revoke_key))
+        .route("/admin/users", get(handlers::admin_users))
+        .route("/admin/users/invite", post(handlers::admin_invite_user))
+        .route("/admin/users/invites/:id/revoke", post(handlers::admin_revoke_invite))
+        .route("/admin/users/:id/role", post(handlers::admin_set_role))
+        .route("/admin/users/:id/disable", post(handlers::admin_set_disabled))
+        .route("/admin/sessions", get(handlers::admin_sessions))
+        .route("/admin/sessions/revoke-all", post(handlers::admin_revoke_other_sessions))
+        .route("/admin/sessions/:id/revoke", post(handlers::admin_revoke_session))
+        .route("/admin/2fa", get(handlers::admin_two_factor))
+        .route("/admin/2fa/enroll", post(handlers::admin_two_factor_enroll))
+        .route("/admin/2fa/confirm", post(handlers::admin_two_factor_confirm))
+        .route("/admin/2fa/recovery", post(handlers::admin_two_factor_recovery))
+        .route("/admin/2fa/disable", post(handlers::admin_two_factor_disable))
+        .route("/admin/passkeys", get(handlers::admin_passkeys))
+        .route("/admin/passkeys/register/start", post(handlers::admin_passkey_register_start))
+        .route("/admin/passkeys/register/finish", post(handlers::admin_passkey_register_finish))
+        .route("/admin/passkeys/:id/delete", post(handlers::admin_delete_passkey))
+        .route("/admin/invite", get(handlers::admin_invite_get).post(handlers::admin_invite_post))
+        .route("/files/*key", get(handlers::files_handler))
+        // Checks the CSRF token of every POST under /admin and hands the form token to admin pages
+        .layer(axum::middleware::from_fn_with_state(state.clone(), csrf::protect))
+        // Per-IP and per-API-key limits on the endpoints that create items
+        .layer(axum::middleware::from_fn_with_state(state.clone(), ratelimit::limit))
+        .with_state(state)
+        // OpenAPI document and bundled Swagger UI (served from the binary, works offline)
+        .merge(SwaggerUi::new("/api/docs").url("/api/openapi.json", openapi::document(&base_url)))
+        // Set individual field limit to 1 GiB for multipart uploads
+        .layer(DefaultBodyLimit::max(1024 * 1024 * 1024))
+        // Raise max request body size to 1 GiB (inner layer)
+        .layer(RequestBodyLimitLayer::new(1024 * 1024 * 1024))
+        // CORS (outer layer) so even inner rejections (like 413) get CORS headers
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods([Method::GET, Method::HEAD, Method::POST, Method::PATCH, Method::DELETE, Method::OPTIONS])
+                .allow_headers(Any)
+                .expose_headers(Any)
+        )
+        .layer(axum::middleware::from_fn(tus::options_middleware))
+}
@@ -0,0 +1,129 @@
+// Creating the first admin account. The login form never creates accounts; an admin comes from
+// `ping0 admin create <username>`, from ADMIN_USERNAME/ADMIN_PASSWORD, or from the one-time setup
+// link that is logged at startup while no admin exists.
+
+use rand::{distributions::Alphanumeric, Rng};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use std::io::{BufRead, IsTerminal};
+
+use crate::admins::{admin_exists, insert_user, validate_credentials, AccountError, Role};
+use crate::apikeys::hash_token;
+
+const SETUP_TOKEN_LEN: usize = 32;
+
+fn insert_owner(conn: &Connection, username: &str, password: &str) -> anyhow::Result<()> {
+    validate_credentials(username, password).map_err(anyhow::Error::msg)?;
+    let hash = crate::password::hash_password(password).map_err(anyhow::Error::msg)?;
+    match insert_user(conn, username, &hash, Role::Owner) {
+        Ok(_) => Ok(()),
+        Err(AccountError::Rejected(e)) => Err(anyhow::Error::msg(e)),
+        Err(AccountError::Internal(e)) => Err(e),
+    }
+}
+
+// Create an owner, or set a new password if the username already exists (CLI only)
+pub fn create_or_reset_admin(conn: &Connection, username: &str, password: &str) -> anyhow::Result<bool> {
+    validate_credentials(username, password).map_err(anyhow::Error::msg)?;
+    let existing: Option<i64> = conn
+        .query_row("SELECT id FROM admin WHERE username = ?1", params![username.trim()], |r| r.get(0))
+        .optional()?;
+    match existing {
+        Some(id) => {
+            let hash = crate::password::hash_password(password).map_err(anyhow::Error::msg)?;
+            conn.execute("UPDATE admin SET password_hash = ?1, salt = '' WHERE id = ?2", params![hash, id])?;
+            conn.execute("DELETE FROM sessions WHERE user_id = ?1", params![id])?;
+            Ok(false)
+        }
+        None => {
+            insert_owner(conn, username, password)?;
+            Ok(true)
+        }
+    }
+}
+
+// Runs `ping0 admin ...` if those are the arguments; returns false to start the server instead
+pub fn run_cli(conn: &Connection, args: &[String]) -> anyhow::Result<bool> {
+    match args {
+        [] => Ok(false),
+        [cmd, sub, username] if cmd == "admin" && sub == "create" => {
+            let password = read_password()?;
+            if create_or_reset_admin(conn, username, &password)? {
+                println!("Created owner '{}'", username.trim());
+            } else {
+                println!("Updated the password of '{}' and signed out their sessions", username.trim());
+            }
+            Ok(true)
+        }
+        [cmd, sub, username] if cmd == "admin" && sub == "disable-2fa" => {
+            let id: Option<i64> = conn
+                .query_row("SELECT id FROM admin WHERE username = ?1", params![username.trim()], |r| r.get(0))
+                .optional()?;
+            let Some(id) = id else { anyhow::bail!("No admin named '{}'", username.trim()) };
+            crate::twofactor::reset(conn, id)?;
+            println!("Removed two-factor login and recovery codes of '{}'", username.trim());
+            Ok(true)
+        }
+        _ => anyhow::bail!("Usage: ping0 [migrate | admin create <username> | admin disable-2fa <username>]\n\nThe password is read from ADMIN_PASSWORD, the terminal, or stdin."),
+    }
+}
+
+fn read_password() -> anyhow::Result<String> {
+    if let Ok(password) = std::env::var("ADMIN_PASSWORD")
+        && !password.is_empty() {
+            return Ok(password);
+        }
+    if std::io::stdin().is_terminal() {
+        let password = rpassword::prompt_password("Password: ")?;
+        if rpassword::prompt_password("Repeat password: ")? != password {
+            anyhow::bail!("Passwords don't match");
+        }
+        return Ok(password);
+    }
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+// At startup: create the admin from ADMIN_USERNAME/ADMIN_PASSWORD, or, if there still is none,
+// store a fresh setup token and log the link that uses it
+pub fn bootstrap(conn: &Connection, base_url: &str) -> anyhow::Result<()> {
+    if admin_exists(conn)? {
+        conn.execute("DELETE FROM settings WHERE key = 'setup_token'", [])?;
+        return Ok(());
+    }
+    let username = std::env::var("ADMIN_USERNAME").ok().filter(|u| !u.trim().is_empty());
+    let password = std::env::var("ADMIN_PASSWORD").ok().filter(|p| !p.is_empty());
+    match (username, password) {
+        (Some(username), Some(password)) => {
+            insert_owner(conn, &username, &password).map_err(|e| anyhow::anyhow!("ADMIN_USERNAME/ADMIN_PASSWORD: {}", e))?;
+            tracing::info!("Created admin '{}' from ADMIN_USERNAME/ADMIN_PASSWORD", username.trim());
+        }
+        (None, None) => {
+            let token: String = rand::thread_rng().sample_iter(&Alphanumeric).take(SETUP_TOKEN_LEN).map(char::from).collect();
+            conn.execute(
+                "INSERT INTO settings (key, value) VALUES ('setup_token', ?1) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![hash_token(&token)],
+            )?;
+            tracing::warn!("No admin account yet. Create one at {}/admin/setup?token={}", base_url, token);
+            tracing::warn!("The link works once and only until the next restart; `ping0 admin create <username>` works too");
+        }
+        _ => anyhow::bail!("Set both ADMIN_USERNAME and ADMIN_PASSWORD, or neither"),
+    }
+    Ok(())
+}
+
+// Create the first owner with the logged setup token. Fails once any admin exists.
+pub fn complete_setup(conn: &mut Connection, token: &str, username: &str, hash: &str) -> Result<(), AccountError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let stored: Option<String> = tx
+        .query_row("SELECT value FROM settings WHERE key = 'setup_token'", [], |r| r.get(0))
+        .optional()?;
+    if admin_exists(&tx)? || stored.is_none_or(|hash| hash != hash_token(token.trim())) {
+        return Err(AccountError::Rejected("Invalid or already used setup token".to_string()));
+    }
+    insert_user(&tx, username, hash, Role::Owner)?;
+    tx.execute("DELETE FROM settings WHERE key = 'setup_token'", [])?;
+    tx.commit()?;
+    tracing::info!("Created admin '{}' with the setup token", username.trim());
+    Ok(())
+}
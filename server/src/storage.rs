@@ -0,0 +1,351 @@
+// Storage backends for uploaded files and their previews.
+//
+// Handlers only ever talk to `dyn Storage` using keys such as `{uuid}.{ext}` or
+// `previews/{stem}.jpg`; where the bytes actually live is up to the backend.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path as ObjectPath;
+use object_store::signer::Signer;
+use object_store::{Attribute, Attributes, GetOptions, GetRange, ObjectStore, PutMultipartOpts, PutOptions, WriteMultipart};
+use std::io;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+pub type ByteStream<'a> = BoxStream<'a, io::Result<Bytes>>;
+
+#[derive(Debug, Clone)]
+pub struct ObjectMeta { pub size: u64 }
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // Replaces any existing object under `key`
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()>;
+    // Returns the bytes written; nothing is left under `key` if the stream fails
+    async fn put_stream(&self, key: &str, body: ByteStream<'_>) -> io::Result<u64>;
+    // Whole object in memory, for small files and image previews only
+    async fn get(&self, key: &str) -> io::Result<Bytes>;
+    // Optionally limited to a byte range
+    async fn stream(&self, key: &str, range: Option<Range<u64>>) -> io::Result<ByteStream<'static>>;
+    // Deleting a missing object is not an error
+    async fn delete(&self, key: &str) -> io::Result<()>;
+    // None if the key doesn't exist
+    async fn stat(&self, key: &str) -> io::Result<Option<ObjectMeta>>;
+    // Time-limited direct download URL, if the backend is set up to redirect downloads
+    async fn presigned_url(&self, _key: &str) -> io::Result<Option<String>> { Ok(None) }
+}
+
+// Pick a backend from the environment: STORAGE_BACKEND=local (default) or s3
+pub fn from_env() -> anyhow::Result<Arc<dyn Storage>> {
+    let backend = std::env::var("STORAGE_BACKEND").unwrap_or_else(|_| "local".to_string());
+    match backend.trim().to_ascii_lowercase().as_str() {
+        "local" | "" => {
+            let uploads_dir = std::env::var("UPLOADS_DIR").unwrap_or_else(|_| "uploads".to_string());
+            tracing::info!("Storage: local filesystem at {}", uploads_dir);
+            Ok(Arc::new(LocalStorage::new(uploads_dir)))
+        }
+        "s3" => {
+            let s3 = S3Storage::from_env()?;
+            tracing::info!("Storage: S3 bucket {}", s3.bucket);
+            Ok(Arc::new(s3))
+        }
+        other => anyhow::bail!("unknown STORAGE_BACKEND '{}' (expected 'local' or 's3')", other),
+    }
+}
+
+// Keys are relative, '/'-separated paths. Reject anything that could escape the storage root, and
+// spellings like `a//b` or `a/./b` that a filesystem would read as `a/b` but S3 would not.
+pub fn validate_key(key: &str) -> io::Result<()> {
+    let ok = !key.contains('\\')
+        && key.split('/').all(|segment| !matches!(segment, "" | "." | ".."))
+        && Path::new(key).components().all(|c| matches!(c, Component::Normal(_)));
+    if ok { Ok(()) } else { Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid storage key: {}", key))) }
+}
+
+// Fails the stream with `FileTooLarge` once more than `max` bytes went through
+pub fn limit_stream<'a>(body: ByteStream<'a>, max: u64) -> ByteStream<'a> {
+    let mut seen: u64 = 0;
+    body.and_then(move |chunk| {
+        seen = seen.saturating_add(chunk.len() as u64);
+        let res = if seen > max { Err(io::Error::new(io::ErrorKind::FileTooLarge, "file too large")) } else { Ok(chunk) };
+        async move { res }
+    })
+    .boxed()
+}
+
+// Files under a local directory (`uploads/` by default)
+pub struct LocalStorage { root: PathBuf }
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self { Self { root: root.into() } }
+
+    fn path_for(&self, key: &str) -> io::Result<PathBuf> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+
+    async fn ensure_parent(path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() { fs::create_dir_all(parent).await?; }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        let path = self.path_for(key)?;
+        Self::ensure_parent(&path).await?;
+        fs::write(&path, &data).await
+    }
+
+    async fn put_stream(&self, key: &str, mut body: ByteStream<'_>) -> io::Result<u64> {
+        let path = self.path_for(key)?;
+        Self::ensure_parent(&path).await?;
+        // Write to a sibling temp file and rename, so readers never see a partial object
+        let tmp = path.with_file_name(format!(".{}.part", uuid::Uuid::new_v4()));
+        let mut out = fs::File::create(&tmp).await?;
+        let mut written: u64 = 0;
+        let res: io::Result<()> = async {
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk?;
+                written += chunk.len() as u64;
+                out.write_all(&chunk).await?;
+            }
+            out.flush().await?;
+            Ok(())
+        }.await;
+        drop(out);
+        match res {
+            Ok(()) => { fs::rename(&tmp, &path).await?; Ok(written) }
+            Err(e) => { let _ = fs::remove_file(&tmp).await; Err(e) }
+        }
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Bytes> {
+        fs::read(self.path_for(key)?).await.map(Bytes::from)
+    }
+
+    async fn stream(&self, key: &str, range: Option<Range<u64>>) -> io::Result<ByteStream<'static>> {
+        let mut file = fs::File::open(self.path_for(key)?).await?;
+        match range {
+            Some(r) => {
+                file.seek(io::SeekFrom::Start(r.start)).await?;
+                Ok(ReaderStream::new(file.take(r.end.saturating_sub(r.start))).boxed())
+            }
+            None => Ok(ReaderStream::new(file).boxed()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let path = self.path_for(key)?;
+        match fs::remove_file(&path).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            other => other?,
+        }
+        // Drop directories that became empty (e.g. tus part folders), never the root itself
+        if let Some(parent) = path.parent().filter(|p| *p != self.root) {
+            let _ = fs::remove_dir(parent).await;
+        }
+        Ok(())
+    }
+
+    async fn stat(&self, key: &str) -> io::Result<Option<ObjectMeta>> {
+        match fs::metadata(self.path_for(key)?).await {
+            Ok(m) if m.is_file() => Ok(Some(ObjectMeta { size: m.len() })),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Objects in an S3-compatible bucket (AWS S3, MinIO, R2, ...)
+pub struct S3Storage { store: AmazonS3, bucket: String, prefix: String, presign_ttl: Option<Duration> }
+
+impl S3Storage {
+    // S3_BUCKET is required; S3_ENDPOINT, S3_REGION, S3_ACCESS_KEY_ID, S3_SECRET_ACCESS_KEY and S3_PREFIX are optional.
+    // S3_PRESIGN_SECS > 0 makes downloads redirect to presigned URLs instead of streaming through ping0.
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    // from_env with the variables looked up through `var`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
+        let env = |name: &str| var(name).filter(|v| !v.trim().is_empty());
+        let bucket = env("S3_BUCKET").ok_or_else(|| anyhow::anyhow!("S3_BUCKET must be set when STORAGE_BACKEND=s3"))?;
+        let mut builder = AmazonS3Builder::new()
+            .with_bucket_name(&bucket)
+            .with_region(env("S3_REGION").unwrap_or_else(|| "us-east-1".to_string()));
+        if let Some(endpoint) = env("S3_ENDPOINT") {
+            // Custom endpoints (MinIO and friends) generally want path-style requests
+            builder = builder
+                .with_allow_http(endpoint.starts_with("http://"))
+                .with_virtual_hosted_style_request(false)
+                .with_endpoint(endpoint);
+        }
+        if let Some(key_id) = env("S3_ACCESS_KEY_ID") { builder = builder.with_access_key_id(key_id); }
+        if let Some(secret) = env("S3_SECRET_ACCESS_KEY") { builder = builder.with_secret_access_key(secret); }
+        let prefix = env("S3_PREFIX").map(|p| format!("{}/", p.trim_matches('/'))).unwrap_or_default();
+        let presign_ttl = env("S3_PRESIGN_SECS")
+            .map(|v| v.parse::<u64>())
+            .transpose()?
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs);
+        Ok(Self { store: builder.build()?, bucket, prefix, presign_ttl })
+    }
+
+    fn path_for(&self, key: &str) -> io::Result<ObjectPath> {
+        validate_key(key)?;
+        ObjectPath::parse(format!("{}{}", self.prefix, key)).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+}
+
+// Store the guessed MIME type so presigned downloads render inline like local files do
+fn content_type_attrs(key: &str) -> Attributes {
+    let mime = mime_guess::from_path(key).first_or_octet_stream();
+    Attributes::from_iter([(Attribute::ContentType, mime.to_string())])
+}
+
+fn s3_err(e: object_store::Error) -> io::Error {
+    match e {
+        object_store::Error::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, e),
+        other => io::Error::other(other),
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        let opts = PutOptions { attributes: content_type_attrs(key), ..Default::default() };
+        self.store.put_opts(&self.path_for(key)?, data.into(), opts).await.map(|_| ()).map_err(s3_err)
+    }
+
+    async fn put_stream(&self, key: &str, mut body: ByteStream<'_>) -> io::Result<u64> {
+        let opts = PutMultipartOpts { attributes: content_type_attrs(key), ..Default::default() };
+        let upload = self.store.put_multipart_opts(&self.path_for(key)?, opts).await.map_err(s3_err)?;
+        let mut writer = WriteMultipart::new(upload);
+        let mut written: u64 = 0;
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => { let _ = writer.abort().await; return Err(e); }
+            };
+            if let Err(e) = writer.wait_for_capacity(4).await { let _ = writer.abort().await; return Err(s3_err(e)); }
+            written += chunk.len() as u64;
+            writer.put(chunk);
+        }
+        writer.finish().await.map_err(s3_err)?;
+        Ok(written)
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Bytes> {
+        let res = self.store.get(&self.path_for(key)?).await.map_err(s3_err)?;
+        res.bytes().await.map_err(s3_err)
+    }
+
+    async fn stream(&self, key: &str, range: Option<Range<u64>>) -> io::Result<ByteStream<'static>> {
+        let opts = GetOptions { range: range.map(|r| GetRange::Bounded(r.start as usize..r.end as usize)), ..Default::default() };
+        let res = self.store.get_opts(&self.path_for(key)?, opts).await.map_err(s3_err)?;
+        Ok(res.into_stream().map_err(s3_err).boxed())
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match self.store.delete(&self.path_for(key)?).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(s3_err(e)),
+        }
+    }
+
+    async fn stat(&self, key: &str) -> io::Result<Option<ObjectMeta>> {
+        match self.store.head(&self.path_for(key)?).await {
+            Ok(meta) => Ok(Some(ObjectMeta { size: meta.size as u64 })),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(s3_err(e)),
+        }
+    }
+
+    async fn presigned_url(&self, key: &str) -> io::Result<Option<String>> {
+        let Some(ttl) = self.presign_ttl else { return Ok(None) };
+        let url = self.store.signed_url(axum::http::Method::GET, &self.path_for(key)?, ttl).await.map_err(s3_err)?;
+        Ok(Some(url.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ESCAPES: &[&str] = &["", "../secret", "a/../../secret", "..", "./a", "a/./b", "a//b", "a/", "/etc/passwd", "a\\..\\b", "C:/x/../../secret"];
+
+    fn root() -> PathBuf {
+        std::env::temp_dir().join(format!("ping0-storage-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn keys() {
+        for key in ["a.txt", "previews/a.jpg", "tus/abc/00000000000000000000-1f2e", "a..b", ".hidden"] {
+            assert!(validate_key(key).is_ok(), "{:?}", key);
+        }
+        for key in ESCAPES {
+            let err = validate_key(key).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", key);
+        }
+    }
+
+    #[tokio::test]
+    async fn local_storage_stays_under_its_root() {
+        let base = root();
+        let storage = LocalStorage::new(base.join("uploads"));
+        fs::create_dir_all(base.join("uploads")).await.unwrap();
+        fs::write(base.join("secret"), "outside").await.unwrap();
+
+        for key in ESCAPES {
+            let invalid = |r: io::Result<()>| assert_eq!(r.unwrap_err().kind(), io::ErrorKind::InvalidInput, "{:?}", key);
+            invalid(storage.put(key, Bytes::from("x")).await);
+            invalid(storage.put_stream(key, futures::stream::iter([Ok(Bytes::from("x"))]).boxed()).await.map(|_| ()));
+            invalid(storage.get(key).await.map(|_| ()));
+            invalid(storage.stream(key, None).await.map(|_| ()));
+            invalid(storage.stat(key).await.map(|_| ()));
+            invalid(storage.delete(key).await);
+        }
+        assert_eq!(fs::read_to_string(base.join("secret")).await.unwrap(), "outside");
+        let mut entries = fs::read_dir(&base).await.unwrap();
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name().into_string().unwrap());
+        }
+        names.sort();
+        assert_eq!(names, ["secret", "uploads"]);
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn local_storage_round_trip() {
+        let base = root();
+        let storage = LocalStorage::new(&base);
+        storage.put("tus/abc/part", Bytes::from("hello world")).await.unwrap();
+        assert_eq!(storage.get("tus/abc/part").await.unwrap(), "hello world");
+        assert_eq!(storage.stat("tus/abc/part").await.unwrap().map(|m| m.size), Some(11));
+        let range: Vec<Bytes> = storage.stream("tus/abc/part", Some(6..11)).await.unwrap().try_collect().await.unwrap();
+        assert_eq!(range.concat(), b"world");
+
+        // A failed stream leaves nothing behind, not even the temp file
+        let broken = futures::stream::iter([Ok(Bytes::from("partial")), Err(io::Error::other("reset"))]).boxed();
+        assert!(storage.put_stream("tus/abc/other", broken).await.is_err());
+        assert!(storage.stat("tus/abc/other").await.unwrap().is_none());
+
+        storage.delete("tus/abc/part").await.unwrap();
+        storage.delete("tus/abc/part").await.unwrap();
+        assert!(storage.stat("tus/abc/part").await.unwrap().is_none());
+        assert!(!base.join("tus/abc").exists());
+        assert!(base.exists());
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+}
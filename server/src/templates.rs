@@ -11,7 +11,7 @@ use askama::Template;
     <style>
       body{font-family:Courier New,monospace;background:#fff;color:#000}
       main{max-width:560px;margin:4rem auto;text-align:center}
-      label,input,button{display:block;margin:0.6rem auto}
+      label,input,select,button{display:block;margin:0.6rem auto}
     </style>
   </head>
   <body>
@@ -24,6 +24,26 @@ use askama::Template;
         <label>File:
           <input type="file" name="file">
         </label>
+        <label>Alias:
+          <input type="text" name="alias" pattern="[A-Za-z0-9_-]{3,64}" placeholder="optional, e.g. my-link">
+        </label>
+        <label>Expires:
+          <select name="expires_in">
+            <option value="never">Never</option>
+            <option value="1h">1 hour</option>
+            <option value="1d">1 day</option>
+            <option value="7d">7 days</option>
+          </select>
+        </label>
+        <label>Max views:
+          <input type="number" name="max_views" min="1" placeholder="unlimited">
+        </label>
+        <label>
+          <input type="checkbox" name="burn_after_reading"> Burn after reading
+        </label>
+        <label>Password:
+          <input type="password" name="password" autocomplete="new-password" placeholder="optional">
+        </label>
         <label>
           <input type="checkbox" name="qr"> Generate QR Code
         </label>
@@ -73,17 +93,21 @@ pub struct ResultTemplate { pub code: String, pub short_link: String, pub qr_svg
     <meta property="og:title" content="{{ title }}">
     <meta property="og:description" content="{{ description }}">
     <meta property="og:url" content="{{ page_url }}">
-    <meta property="og:image" content="{{ og_image_url }}">
+    {% match og_image_url %}{% when Some with (url) %}
+    <meta property="og:image" content="{{ url }}">
     <meta name="twitter:card" content="summary_large_image">
+    <meta name="twitter:image" content="{{ url }}">
+    {% when None %}
+    <meta name="twitter:card" content="summary">
+    {% endmatch %}
     <meta name="twitter:title" content="{{ title }}">
     <meta name="twitter:description" content="{{ description }}">
-    <meta name="twitter:image" content="{{ og_image_url }}">
   </head>
   <body style="font-family:Courier New,monospace;background:#fff;color:#000;text-align:center">
     <img src="{{ full_image_url }}" alt="{{ title }}" style="max-width:95vw;max-height:90vh">
   </body>
  </html>"#, ext = "html")]
-pub struct ImageOgTemplate { pub og_image_url: String, pub full_image_url: String, pub page_url: String, pub title: String, pub description: String }
+pub struct ImageOgTemplate { pub og_image_url: Option<String>, pub full_image_url: String, pub page_url: String, pub title: String, pub description: String }
 
 #[derive(Template)]
 #[template(source = r#"<!DOCTYPE html>
@@ -117,6 +141,35 @@ pub struct ImageOgTemplate { pub og_image_url: String, pub full_image_url: Strin
 </html>"#, ext = "html")]
 pub struct FileInfoTemplate { pub filename: String, pub file_url: String, pub mime: String, pub page_url: String }
 
+#[derive(Template)]
+#[template(source = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>Password required</title>
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <meta name="robots" content="noindex">
+    <style>
+      body{font-family:Courier New,monospace;background:#fff;color:#000}
+      main{max-width:420px;margin:4rem auto;text-align:center}
+      input,button{font:inherit;padding:.4rem;border:1px solid #000;background:#fff;color:#000}
+      .error{color:#b00}
+    </style>
+  </head>
+  <body>
+    <main>
+      <h1>Password required</h1>
+      <p>This link is protected.</p>
+      {% match error %}{% when Some with (msg) %}<p class="error">{{ msg }}</p>{% when None %}{% endmatch %}
+      <form action="/s/{{ code }}" method="post">
+        <input type="password" name="password" autofocus required>
+        <button type="submit">Unlock</button>
+      </form>
+    </main>
+  </body>
+</html>"#, ext = "html")]
+pub struct PasswordPromptTemplate { pub code: String, pub error: Option<String> }
+
 // ---------- Admin Templates ----------
 
 #[derive(Template)]
@@ -129,12 +182,32 @@ pub struct FileInfoTemplate { pub filename: String, pub file_url: String, pub mi
       body{font-family:Courier New,monospace;background:#fff;color:#000}
       main{max-width:480px;margin:4rem auto;text-align:center}
       label,input,button{display:block;margin:0.6rem auto}
+      summary{cursor:pointer;margin-top:1.5rem}
     </style>
+    {% if passkeys %}
+    <meta name="csrf-token" content="{{ csrf }}">
+    <script src="/admin/passkey.js"></script>
+    {% endif %}
   </head>
   <body>
     <main>
       <h1>Admin Login</h1>
-      <form action="/admin/login" method="post">
+      {% if let Some(e) = error %}<p>{{ e }}</p>{% endif %}
+      {% if let Some(provider) = sso %}
+      <p><a href="/admin/oidc/login">Sign in with {{ provider }}</a></p>
+      {% endif %}
+      {% if passkeys %}
+      <form id="passkey-login">
+        <label>Username:
+          <input type="text" name="username" autocomplete="username webauthn" required>
+        </label>
+        <button type="submit">Sign in with a passkey</button>
+      </form>
+      <p id="passkey-error"></p>
+      <details>
+        <summary>Use a password instead</summary>
+      {% endif %}
+      <form action="/admin/login" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}">
         <label>Username:
           <input type="text" name="username" required>
         </label>
@@ -143,11 +216,77 @@ pub struct FileInfoTemplate { pub filename: String, pub file_url: String, pub mi
         </label>
         <button type="submit">Login</button>
       </form>
-      <p style="font-size:0.9rem">First login sets the admin username and password.</p>
+      {% if passkeys %}
+      </details>
+      {% endif %}
+      {% if needs_setup %}
+      <p style="font-size:0.9rem">No admin account exists yet. Open the setup link from the server log, or run <code>ping0 admin create &lt;username&gt;</code>.</p>
+      {% endif %}
+    </main>
+  </body>
+</html>"#, ext = "html")]
+pub struct AdminLoginTemplate { pub csrf: String, pub needs_setup: bool, pub passkeys: bool, pub sso: Option<String>, pub error: Option<String> }
+
+#[derive(Template)]
+#[template(source = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>Admin Login</title>
+    <style>
+      body{font-family:Courier New,monospace;background:#fff;color:#000}
+      main{max-width:480px;margin:4rem auto;text-align:center}
+      label,input,button{display:block;margin:0.6rem auto}
+    </style>
+  </head>
+  <body>
+    <main>
+      <h1>Admin Login</h1>
+      {% if let Some(e) = error %}<p>{{ e }}</p>{% endif %}
+      <form action="/admin/login/2fa" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+        <label>Code from your authenticator app, or a recovery code:
+          <input type="text" name="code" autocomplete="one-time-code" autofocus required>
+        </label>
+        <button type="submit">Verify</button>
+      </form>
+      <p style="font-size:0.9rem"><a href="/admin/login">Start over</a></p>
     </main>
   </body>
 </html>"#, ext = "html")]
-pub struct AdminLoginTemplate;
+pub struct AdminLoginCodeTemplate { pub csrf: String, pub error: Option<String> }
+
+#[derive(Template)]
+#[template(source = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <meta name="referrer" content="no-referrer">
+    <title>Admin Setup</title>
+    <style>
+      body{font-family:Courier New,monospace;background:#fff;color:#000}
+      main{max-width:480px;margin:4rem auto;text-align:center}
+      label,input,button{display:block;margin:0.6rem auto}
+    </style>
+  </head>
+  <body>
+    <main>
+      <h1>Admin Setup</h1>
+      <p style="font-size:0.9rem">Create the first admin account.</p>
+      {% if let Some(e) = error %}<p>{{ e }}</p>{% endif %}
+      <form action="/admin/setup" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+        <input type="hidden" name="token" value="{{ token }}">
+        <label>Username:
+          <input type="text" name="username" required>
+        </label>
+        <label>Password (8+ characters):
+          <input type="password" name="password" minlength="8" required>
+        </label>
+        <button type="submit">Create admin</button>
+      </form>
+    </main>
+  </body>
+</html>"#, ext = "html")]
+pub struct AdminSetupTemplate { pub csrf: String, pub token: String, pub error: Option<String> }
 
 #[derive(Template)]
 #[template(source = r#"<!DOCTYPE html>
@@ -165,14 +304,27 @@ pub struct AdminLoginTemplate;
   <body>
     <main>
       <h1>Admin</h1>
+      <p>Logged in as {{ username }} ({{ role }})</p>
       <p>
         <a href="/admin/items">Items</a>
+        {% if is_owner %}
+        <span> · </span>
+        <a href="/admin/keys">API keys</a>
+        <span> · </span>
+        <a href="/admin/users">Users</a>
+        {% endif %}
+        <span> · </span>
+        <a href="/admin/sessions">Sessions</a>
+        <span> · </span>
+        <a href="/admin/2fa">Two-factor login</a>
+        <span> · </span>
+        <a href="/admin/passkeys">Passkeys</a>
       </p>
-      <form action="/admin/logout" method="post"><button type="submit">Logout</button></form>
+      <form action="/admin/logout" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}"><button type="submit">Logout</button></form>
     </main>
   </body>
 </html>"#, ext = "html")]
-pub struct AdminHomeTemplate;
+pub struct AdminHomeTemplate { pub csrf: String, pub username: String, pub role: &'static str, pub is_owner: bool }
 
 #[derive(Template)]
 #[template(source = r#"<!DOCTYPE html>
@@ -195,22 +347,41 @@ pub struct AdminHomeTemplate;
       <p>
         <a href="/admin">Home</a>
         <span> · </span>
-        <form action="/admin/logout" method="post"><button type="submit">Logout</button></form>
+        <form action="/admin/logout" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}"><button type="submit">Logout</button></form>
       </p>
       <table>
         <thead>
-          <tr><th>Code</th><th>Kind</th><th>Type</th><th>Value</th><th>When</th><th>Actions</th></tr>
+          <tr><th>Code</th><th>Kind</th><th>Type</th><th>Value</th><th>When</th><th>Views</th><th>Expires</th><th>Actions</th></tr>
         </thead>
         <tbody>
         {% for item in items %}
           <tr>
             <td><a href="/s/{{ item.code }}" target="_blank">{{ item.code }}</a></td>
-            <td>{{ item.kind }}</td>
+            <td>{{ item.kind }}{% if item.protected %} (password){% endif %}</td>
             <td>{{ item.mime.as_deref().unwrap_or("-") }}</td>
             <td style="max-width:420px;word-break:break-all">{{ item.value }}</td>
             <td>{{ item.created_at }}</td>
+            <td>{{ item.view_count }}{% match item.max_views %}{% when Some with (max) %} / {{ max }}{% when None %}{% endmatch %}</td>
+            <td>
+              {% match item.expires_at %}{% when Some with (t) %}{{ t }}{% when None %}never{% endmatch %}
+              {% if can_moderate %}
+              <form action="/admin/items/{{ item.code }}/expiry" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+                <select name="expires_in">
+                  <option value="never">never</option>
+                  <option value="1h">1h</option>
+                  <option value="1d">1d</option>
+                  <option value="7d">7d</option>
+                  <option value="30d">30d</option>
+                </select>
+                <button type="submit">Set</button>
+              </form>
+              {% endif %}
+            </td>
             <td>
-              <form action="/admin/items/{{ item.code }}/delete" method="post" onsubmit="return confirm('Delete {{ item.code }}?')"><button type="submit">Delete</button></form>
+              <a href="/admin/items/{{ item.code }}/stats">Stats</a>
+              {% if can_moderate %}
+              <form action="/admin/items/{{ item.code }}/delete" method="post" onsubmit="return confirm('Delete {{ item.code }}?')"><input type="hidden" name="csrf_token" value="{{ csrf }}"><button type="submit">Delete</button></form>
+              {% endif %}
             </td>
           </tr>
         {% endfor %}
@@ -219,6 +390,422 @@ pub struct AdminHomeTemplate;
     </main>
   </body>
 </html>"#, ext = "html")]
-pub struct AdminItemsTemplate { pub items: Vec<AdminItem> }
+pub struct AdminItemsTemplate { pub csrf: String, pub items: Vec<AdminItem>, pub can_moderate: bool }
+
+pub struct AdminItem { pub code: String, pub kind: String, pub value: String, pub created_at: i64, pub expires_at: Option<i64>, pub view_count: i64, pub max_views: Option<i64>, pub protected: bool, pub mime: Option<String> }
+
+#[derive(Template)]
+#[template(source = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>Stats · {{ code }}</title>
+    <style>
+      body{font-family:Courier New,monospace;background:#fff;color:#000}
+      main{max-width:900px;margin:2rem auto}
+      table{width:100%;border-collapse:collapse;margin-bottom:1.5rem}
+      th,td{border:1px solid #000;padding:6px;text-align:left}
+      a,button{color:#000}
+      .bar{background:#000;height:12px}
+    </style>
+  </head>
+  <body>
+    <main>
+      <h1>Stats · <a href="/s/{{ code }}" target="_blank">{{ code }}</a></h1>
+      <p><a href="/admin/items">Items</a></p>
+      <p style="word-break:break-all">{{ kind }}: {{ value }}</p>
+      <table>
+        <tr><th>Clicks</th><th>Humans</th><th>Bots</th><th>Unique visitors (per day)</th></tr>
+        <tr><td>{{ total }}</td><td>{{ humans }}</td><td>{{ bots }}</td><td>{{ unique_visitors }}</td></tr>
+      </table>
+      <h2>Top referrers</h2>
+      <table>
+        <tr><th>Host</th><th>Clicks</th></tr>
+        {% for (host, count) in referrers %}<tr><td>{{ host }}</td><td>{{ count }}</td></tr>{% endfor %}
+      </table>
+      <h2>Clients</h2>
+      <table>
+        <tr><th>Family</th><th>Clicks</th></tr>
+        {% for (family, count) in families %}<tr><td>{{ family }}</td><td>{{ count }}</td></tr>{% endfor %}
+      </table>
+      <h2>Countries</h2>
+      <table>
+        <tr><th>Country</th><th>Clicks</th></tr>
+        {% for (country, count) in countries %}<tr><td>{{ country }}</td><td>{{ count }}</td></tr>{% endfor %}
+      </table>
+      {% if !regions.is_empty() %}
+      <h2>Regions</h2>
+      <table>
+        <tr><th>Region</th><th>Clicks</th></tr>
+        {% for (region, count) in regions %}<tr><td>{{ region }}</td><td>{{ count }}</td></tr>{% endfor %}
+      </table>
+      {% endif %}
+      <h2>Last {{ days.len() }} days (humans)</h2>
+      <table>
+        {% for day in days %}
+        <tr><td style="width:8rem">{{ day.label }}</td><td style="width:4rem">{{ day.count }}</td><td><div class="bar" style="width:{{ day.width_pct }}%"></div></td></tr>
+        {% endfor %}
+      </table>
+    </main>
+  </body>
+</html>"#, ext = "html")]
+pub struct AdminStatsTemplate {
+    pub code: String,
+    pub kind: String,
+    pub value: String,
+    pub total: i64,
+    pub humans: i64,
+    pub bots: i64,
+    pub unique_visitors: i64,
+    pub referrers: Vec<(String, i64)>,
+    pub families: Vec<(String, i64)>,
+    pub countries: Vec<(String, i64)>,
+    pub regions: Vec<(String, i64)>,
+    pub days: Vec<StatsDay>,
+}
+
+pub struct StatsDay { pub label: String, pub count: i64, pub width_pct: i64 }
+
+#[derive(Template)]
+#[template(source = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>API keys</title>
+    <style>
+      body{font-family:Courier New,monospace;background:#fff;color:#000}
+      main{max-width:900px;margin:2rem auto}
+      table{width:100%;border-collapse:collapse;margin-bottom:1.5rem}
+      th,td{border:1px solid #000;padding:6px;text-align:left}
+      a,button{color:#000}
+      form{display:inline}
+      .token{border:1px solid #000;padding:1rem;word-break:break-all}
+      .error{color:#b00}
+    </style>
+  </head>
+  <body>
+    <main>
+      <h1>API keys</h1>
+      <p><a href="/admin">Home</a></p>
+      {% match new_token %}{% when Some with (token) %}
+      <div class="token">
+        <p>New key created. Copy it now, it won't be shown again:</p>
+        <code>{{ token }}</code>
+      </div>
+      {% when None %}{% endmatch %}
+      {% match error %}{% when Some with (msg) %}<p class="error">{{ msg }}</p>{% when None %}{% endmatch %}
+      <h2>Create key</h2>
+      <form action="/admin/keys" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+        <label>Name: <input type="text" name="name" required></label>
+        <label><input type="checkbox" name="scope_create" checked> create</label>
+        <label><input type="checkbox" name="scope_read" checked> read</label>
+        <label><input type="checkbox" name="scope_delete"> delete</label>
+        <label><input type="checkbox" name="scope_admin"> admin</label>
+        <button type="submit">Create</button>
+      </form>
+      <h2>Keys</h2>
+      <table>
+        <tr><th>Name</th><th>Key</th><th>Scopes</th><th>Created</th><th>Last used</th><th>Items</th><th>Status</th></tr>
+        {% for key in keys %}
+        <tr>
+          <td>{{ key.name }}</td>
+          <td>{{ key.prefix }}…</td>
+          <td>{{ key.scopes }}</td>
+          <td>{{ key.created_at }}</td>
+          <td>{% match key.last_used_at %}{% when Some with (t) %}{{ t }}{% when None %}never{% endmatch %}</td>
+          <td>{{ key.item_count }}</td>
+          <td>
+            {% if key.revoked %}revoked{% else %}
+            <form action="/admin/keys/{{ key.id }}/revoke" method="post" onsubmit="return confirm('Revoke {{ key.name }}?')"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+              <label><input type="checkbox" name="delete_items"> delete its items</label>
+              <button type="submit">Revoke</button>
+            </form>
+            {% endif %}
+          </td>
+        </tr>
+        {% endfor %}
+      </table>
+    </main>
+  </body>
+</html>"#, ext = "html")]
+pub struct AdminKeysTemplate { pub csrf: String, pub keys: Vec<AdminKey>, pub new_token: Option<String>, pub error: Option<String> }
+
+pub struct AdminKey { pub id: i64, pub name: String, pub prefix: String, pub scopes: String, pub created_at: i64, pub last_used_at: Option<i64>, pub revoked: bool, pub item_count: i64 }
+
+#[derive(Template)]
+#[template(source = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>Users</title>
+    <style>
+      body{font-family:Courier New,monospace;background:#fff;color:#000}
+      main{max-width:900px;margin:2rem auto}
+      table{width:100%;border-collapse:collapse;margin-bottom:1.5rem}
+      th,td{border:1px solid #000;padding:6px;text-align:left}
+      a,button{color:#000}
+      form{display:inline}
+      .token{border:1px solid #000;padding:1rem;word-break:break-all}
+      .error{color:#b00}
+    </style>
+  </head>
+  <body>
+    <main>
+      <h1>Users</h1>
+      <p><a href="/admin">Home</a></p>
+      <p style="font-size:0.9rem">Owners manage everything, moderators review, re-expire and delete items, viewers only see items and stats.</p>
+      {% match invite_url %}{% when Some with (url) %}
+      <div class="token">
+        <p>Invite created. Send this link to the new user; it works once, for 7 days, and won't be shown again:</p>
+        <code>{{ url }}</code>
+      </div>
+      {% when None %}{% endmatch %}
+      {% match error %}{% when Some with (msg) %}<p class="error">{{ msg }}</p>{% when None %}{% endmatch %}
+      <h2>Invite</h2>
+      <form action="/admin/users/invite" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+        <select name="role">
+          <option value="viewer">viewer</option>
+          <option value="moderator">moderator</option>
+          <option value="owner">owner</option>
+        </select>
+        <button type="submit">Create invite link</button>
+      </form>
+      {% if !invites.is_empty() %}
+      <h2>Pending invites</h2>
+      <table>
+        <tr><th>Role</th><th>Invited by</th><th>Expires</th><th></th></tr>
+        {% for invite in invites %}
+        <tr>
+          <td>{{ invite.role }}</td>
+          <td>{{ invite.created_by }}</td>
+          <td>{{ invite.expires_at }}</td>
+          <td><form action="/admin/users/invites/{{ invite.id }}/revoke" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}"><button type="submit">Revoke</button></form></td>
+        </tr>
+        {% endfor %}
+      </table>
+      {% endif %}
+      <h2>Users</h2>
+      <table>
+        <tr><th>Username</th><th>Role</th><th>Created</th><th>Status</th></tr>
+        {% for user in users %}
+        <tr>
+          <td>{{ user.username }}{% if user.is_self %} (you){% endif %}</td>
+          <td>
+            <form action="/admin/users/{{ user.id }}/role" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+              <select name="role">
+                <option value="owner"{% if user.role == "owner" %} selected{% endif %}>owner</option>
+                <option value="moderator"{% if user.role == "moderator" %} selected{% endif %}>moderator</option>
+                <option value="viewer"{% if user.role == "viewer" %} selected{% endif %}>viewer</option>
+              </select>
+              <button type="submit">Change</button>
+            </form>
+          </td>
+          <td>{% match user.created_at %}{% when Some with (t) %}{{ t }}{% when None %}-{% endmatch %}</td>
+          <td>
+            {% if user.disabled %}
+            disabled
+            <form action="/admin/users/{{ user.id }}/disable" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}"><input type="hidden" name="disabled" value="false"><button type="submit">Enable</button></form>
+            {% else %}
+            active
+            {% if !user.is_self %}
+            <form action="/admin/users/{{ user.id }}/disable" method="post" onsubmit="return confirm('Disable {{ user.username }}?')"><input type="hidden" name="csrf_token" value="{{ csrf }}"><input type="hidden" name="disabled" value="true"><button type="submit">Disable</button></form>
+            {% endif %}
+            {% endif %}
+          </td>
+        </tr>
+        {% endfor %}
+      </table>
+    </main>
+  </body>
+</html>"#, ext = "html")]
+pub struct AdminUsersTemplate { pub csrf: String, pub users: Vec<AdminUserRow>, pub invites: Vec<AdminInviteRow>, pub invite_url: Option<String>, pub error: Option<String> }
+
+pub struct AdminUserRow { pub id: i64, pub username: String, pub role: String, pub created_at: Option<i64>, pub disabled: bool, pub is_self: bool }
+
+pub struct AdminInviteRow { pub id: i64, pub role: String, pub created_by: String, pub expires_at: i64 }
+
+#[derive(Template)]
+#[template(source = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <meta name="referrer" content="no-referrer">
+    <title>Join ping0</title>
+    <style>
+      body{font-family:Courier New,monospace;background:#fff;color:#000}
+      main{max-width:480px;margin:4rem auto;text-align:center}
+      label,input,button{display:block;margin:0.6rem auto}
+    </style>
+  </head>
+  <body>
+    <main>
+      <h1>Join as {{ role }}</h1>
+      {% if let Some(e) = error %}<p>{{ e }}</p>{% endif %}
+      <form action="/admin/invite" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+        <input type="hidden" name="token" value="{{ token }}">
+        <label>Username:
+          <input type="text" name="username" required>
+        </label>
+        <label>Password (8+ characters):
+          <input type="password" name="password" minlength="8" required>
+        </label>
+        <button type="submit">Create account</button>
+      </form>
+    </main>
+  </body>
+</html>"#, ext = "html")]
+pub struct AdminInviteTemplate { pub csrf: String, pub token: String, pub role: &'static str, pub error: Option<String> }
+
+#[derive(Template)]
+#[template(source = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>Sessions</title>
+    <style>
+      body{font-family:Courier New,monospace;background:#fff;color:#000}
+      main{max-width:900px;margin:2rem auto}
+      table{width:100%;border-collapse:collapse;margin-bottom:1.5rem}
+      th,td{border:1px solid #000;padding:6px;text-align:left}
+      a,button{color:#000}
+      form{display:inline}
+    </style>
+  </head>
+  <body>
+    <main>
+      <h1>Sessions</h1>
+      <p><a href="/admin">Home</a></p>
+      <p style="font-size:0.9rem">Sessions end after {{ idle_minutes }} minutes without use and {{ max_age_hours }} hours after login.</p>
+      <form action="/admin/sessions/revoke-all" method="post" onsubmit="return confirm('Sign out all other sessions?')"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+        <button type="submit">{% if all_users %}Revoke all other sessions (every user){% else %}Revoke all my other sessions{% endif %}</button>
+      </form>
+      <table>
+        <tr>{% if all_users %}<th>User</th>{% endif %}<th>Created</th><th>Last used</th><th>IP</th><th>User agent</th><th></th></tr>
+        {% for session in sessions %}
+        <tr>
+          {% if all_users %}<td>{{ session.username }}</td>{% endif %}
+          <td>{{ session.created_at }}</td>
+          <td>{{ session.last_seen_at }}</td>
+          <td>{{ session.ip }}</td>
+          <td style="max-width:360px;word-break:break-all">{{ session.user_agent }}</td>
+          <td>
+            {% if session.current %}this session{% endif %}
+            <form action="/admin/sessions/{{ session.id }}/revoke" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}"><button type="submit">Revoke</button></form>
+          </td>
+        </tr>
+        {% endfor %}
+      </table>
+    </main>
+  </body>
+</html>"#, ext = "html")]
+pub struct AdminSessionsTemplate { pub csrf: String, pub sessions: Vec<AdminSession>, pub all_users: bool, pub idle_minutes: i64, pub max_age_hours: i64 }
+
+pub struct AdminSession { pub id: i64, pub username: String, pub created_at: i64, pub last_seen_at: i64, pub ip: String, pub user_agent: String, pub current: bool }
+
+#[derive(Template)]
+#[template(source = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <meta name="referrer" content="no-referrer">
+    <title>Two-factor login</title>
+    <style>
+      body{font-family:Courier New,monospace;background:#fff;color:#000}
+      main{max-width:640px;margin:2rem auto}
+      a,button{color:#000}
+      .qr svg{width:240px;height:240px}
+      .codes{border:1px solid #000;padding:10px;columns:2}
+    </style>
+  </head>
+  <body>
+    <main>
+      <h1>Two-factor login</h1>
+      <p><a href="/admin">Home</a></p>
+      {% if let Some(e) = error %}<p>{{ e }}</p>{% endif %}
+      {% if !recovery_codes.is_empty() %}
+      <p>Recovery codes. Each works once in place of a code from your app. They are shown only now, so store them somewhere safe.</p>
+      <div class="codes">{% for code in recovery_codes %}<div>{{ code }}</div>{% endfor %}</div>
+      {% endif %}
+      {% if enabled %}
+      <p>Two-factor login is on. {{ remaining_codes }} unused recovery codes left.</p>
+      <form action="/admin/2fa/recovery" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+        <label>Current code: <input type="text" name="code" autocomplete="one-time-code" required></label>
+        <button type="submit">New recovery codes</button>
+      </form>
+      <form action="/admin/2fa/disable" method="post" onsubmit="return confirm('Turn off two-factor login?')"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+        <label>Current code: <input type="text" name="code" autocomplete="one-time-code" required></label>
+        <button type="submit">Turn off</button>
+      </form>
+      {% else if let Some(e) = enrollment %}
+      <p>Scan this with an authenticator app, or enter the key <code>{{ e.secret }}</code> by hand. Then enter the 6-digit code it shows.</p>
+      <div class="qr">{{ e.qr_svg|safe }}</div>
+      <form action="/admin/2fa/confirm" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}">
+        <label>Code: <input type="text" name="code" inputmode="numeric" autocomplete="one-time-code" required></label>
+        <button type="submit">Turn on</button>
+      </form>
+      {% else %}
+      <p>Two-factor login is off. With it on, logging in also needs a code from an authenticator app.</p>
+      <form action="/admin/2fa/enroll" method="post"><input type="hidden" name="csrf_token" value="{{ csrf }}"><button type="submit">Set up</button></form>
+      {% endif %}
+    </main>
+  </body>
+</html>"#, ext = "html")]
+pub struct AdminTwoFactorTemplate {
+    pub csrf: String,
+    pub enabled: bool,
+    pub remaining_codes: i64,
+    pub enrollment: Option<AdminTotpEnrollment>,
+    pub recovery_codes: Vec<String>,
+    pub error: Option<String>,
+}
+
+pub struct AdminTotpEnrollment { pub secret: String, pub qr_svg: String }
+
+#[derive(Template)]
+#[template(source = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <meta name="csrf-token" content="{{ csrf }}">
+    <title>Passkeys</title>
+    <style>
+      body{font-family:Courier New,monospace;background:#fff;color:#000}
+      main{max-width:720px;margin:2rem auto}
+      table{width:100%;border-collapse:collapse;margin-bottom:1.5rem}
+      th,td{border:1px solid #000;padding:6px;text-align:left}
+      a,button{color:#000}
+      form{display:inline}
+    </style>
+    <script src="/admin/passkey.js"></script>
+  </head>
+  <body>
+    <main>
+      <h1>Passkeys</h1>
+      <p><a href="/admin">Home</a></p>
+      {% if available %}
+      <p style="font-size:0.9rem">A passkey signs you in without a password or two-factor code.</p>
+      <table>
+        <tr><th>Name</th><th>Added</th><th>Last used</th><th></th></tr>
+        {% for key in passkeys %}
+        <tr>
+          <td>{{ key.name }}</td>
+          <td>{{ key.created_at }}</td>
+          <td>{% match key.last_used_at %}{% when Some with (t) %}{{ t }}{% when None %}never{% endmatch %}</td>
+          <td><form action="/admin/passkeys/{{ key.id }}/delete" method="post" onsubmit="return confirm('Remove {{ key.name }}?')"><input type="hidden" name="csrf_token" value="{{ csrf }}"><button type="submit">Remove</button></form></td>
+        </tr>
+        {% endfor %}
+      </table>
+      <form id="passkey-register">
+        <label>Name: <input type="text" name="name" placeholder="e.g. Laptop" maxlength="64"></label>
+        <button type="submit">Add a passkey</button>
+      </form>
+      <p id="passkey-error"></p>
+      {% else %}
+      <p>Passkeys need BASE_URL to be the address this panel is opened at (https, or http://localhost).</p>
+      {% endif %}
+    </main>
+  </body>
+</html>"#, ext = "html")]
+pub struct AdminPasskeysTemplate { pub csrf: String, pub available: bool, pub passkeys: Vec<AdminPasskey> }
 
-pub struct AdminItem { pub code: String, pub kind: String, pub value: String, pub created_at: i64, pub mime: Option<String> }
+pub struct AdminPasskey { pub id: i64, pub name: String, pub created_at: i64, pub last_used_at: Option<i64> }
@@ -0,0 +1,269 @@
+// Brute-force protection for admin logins and item passwords. Failed attempts are counted per client IP
+// and per username (or per user for the two-factor step, per item for passwords). After LOGIN_MAX_ATTEMPTS failures every further failure locks
+// the key for LOGIN_BACKOFF_SECS, doubling each time up to LOGIN_LOCKOUT_SECS. Every attempt is counted
+// as a failure when it starts, in the same transaction as the lockout check, so parallel guesses can't
+// all slip past the check; a successful attempt takes it back. A successful login also clears the
+// username's counter; counters are forgotten after an hour without failures.
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use std::net::IpAddr;
+
+use crate::handlers::now_secs;
+
+const FORGET_AFTER_SECS: i64 = 60 * 60;
+
+struct Limits {
+    max_attempts: i64,
+    backoff: i64,
+    lockout: i64,
+}
+
+impl Limits {
+    fn from_env() -> Limits {
+        Limits::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Limits {
+        let positive = |name: &str, default: i64| var(name).and_then(|v| v.trim().parse().ok()).filter(|v: &i64| *v > 0).unwrap_or(default);
+        let backoff = positive("LOGIN_BACKOFF_SECS", 30);
+        Limits {
+            max_attempts: positive("LOGIN_MAX_ATTEMPTS", 5),
+            backoff,
+            lockout: positive("LOGIN_LOCKOUT_SECS", 15 * 60).max(backoff),
+        }
+    }
+}
+
+pub fn ip_key(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+pub fn user_key(username: &str) -> String {
+    format!("user:{}", username.trim())
+}
+
+pub fn item_key(code: &str) -> String {
+    format!("item:{}", code)
+}
+
+// Counts the second factor separately, by user id, since the password was already right
+pub fn second_factor_key(user_id: i64) -> String {
+    format!("2fa:{}", user_id)
+}
+
+// Seconds until the next attempt is allowed if any of `keys` is locked
+fn retry_after(conn: &Connection, keys: &[String], now: i64) -> rusqlite::Result<Option<i64>> {
+    let mut wait = None;
+    for key in keys {
+        let locked_until: Option<i64> = conn
+            .query_row("SELECT locked_until FROM login_attempts WHERE key = ?1", params![key], |r| r.get(0))
+            .optional()?
+            .flatten();
+        if let Some(until) = locked_until.filter(|until| *until > now) {
+            wait = wait.max(Some(until - now));
+        }
+    }
+    Ok(wait)
+}
+
+// Counts a failed attempt against every key, locking those over the limit
+fn record_failure(conn: &Connection, keys: &[String], limits: &Limits, now: i64) -> rusqlite::Result<()> {
+    for key in keys {
+        conn.execute(
+            "INSERT INTO login_attempts (key, failures, last_failure_at) VALUES (?1, 1, ?2)
+             ON CONFLICT(key) DO UPDATE SET
+                failures = CASE WHEN last_failure_at <= ?2 - ?3 THEN 1 ELSE failures + 1 END,
+                last_failure_at = ?2",
+            params![key, now, FORGET_AFTER_SECS],
+        )?;
+        let failures: i64 = conn.query_row("SELECT failures FROM login_attempts WHERE key = ?1", params![key], |r| r.get(0))?;
+        let over = failures - limits.max_attempts;
+        if over <= 0 {
+            continue;
+        }
+        let wait = limits.backoff.saturating_mul(1i64 << (over - 1).min(30)).min(limits.lockout);
+        conn.execute("UPDATE login_attempts SET locked_until = ?1 WHERE key = ?2", params![now + wait, key])?;
+        tracing::warn!("Login for {} locked for {}s after {} failed attempts", key, wait, failures);
+    }
+    Ok(())
+}
+
+// Starts an attempt: Some(seconds to wait) if any key is locked, otherwise the attempt is counted
+// against every key until `forgive` takes it back
+pub fn begin_attempt(conn: &mut Connection, keys: &[String]) -> rusqlite::Result<Option<i64>> {
+    begin_attempt_at(conn, keys, &Limits::from_env(), now_secs())
+}
+
+fn begin_attempt_at(conn: &mut Connection, keys: &[String], limits: &Limits, now: i64) -> rusqlite::Result<Option<i64>> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if let Some(wait) = retry_after(&tx, keys, now)? {
+        return Ok(Some(wait));
+    }
+    record_failure(&tx, keys, limits, now)?;
+    tx.commit()?;
+    Ok(None)
+}
+
+// Takes back an attempt that succeeded, including the lock it set if it was the one over the limit
+pub fn forgive(conn: &Connection, key: &str) -> rusqlite::Result<()> {
+    forgive_with(conn, key, &Limits::from_env())
+}
+
+fn forgive_with(conn: &Connection, key: &str, limits: &Limits) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE login_attempts SET
+            failures = MAX(failures - 1, 0),
+            locked_until = CASE WHEN failures - 1 <= ?2 THEN NULL ELSE locked_until END
+         WHERE key = ?1",
+        params![key, limits.max_attempts],
+    )?;
+    Ok(())
+}
+
+pub fn clear(conn: &Connection, key: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM login_attempts WHERE key = ?1", params![key])?;
+    Ok(())
+}
+
+// Counters that are neither locked nor recent enough to count any more
+pub fn purge_stale(conn: &Connection) -> rusqlite::Result<usize> {
+    purge_stale_at(conn, now_secs())
+}
+
+fn purge_stale_at(conn: &Connection, now: i64) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM login_attempts WHERE last_failure_at <= ?1 AND (locked_until IS NULL OR locked_until <= ?2)",
+        params![now - FORGET_AFTER_SECS, now],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+    const LIMITS: Limits = Limits { max_attempts: 3, backoff: 10, lockout: 60 };
+
+    fn db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::run(&mut conn).unwrap();
+        conn
+    }
+
+    fn keys() -> Vec<String> {
+        vec![ip_key("198.51.100.4".parse().unwrap()), user_key("alice")]
+    }
+
+    fn attempt(conn: &mut Connection, keys: &[String], now: i64) -> Option<i64> {
+        begin_attempt_at(conn, keys, &LIMITS, now).unwrap()
+    }
+
+    fn failures(conn: &Connection, key: &str) -> Option<i64> {
+        conn.query_row("SELECT failures FROM login_attempts WHERE key = ?1", [key], |r| r.get(0)).optional().unwrap()
+    }
+
+    #[test]
+    fn lockouts_double_up_to_the_maximum() {
+        let mut conn = db();
+        let keys = keys();
+        for _ in 0..LIMITS.max_attempts {
+            assert_eq!(attempt(&mut conn, &keys, NOW), None);
+        }
+        // The first attempt over the limit still runs, but locks the keys behind it
+        let mut now = NOW;
+        for wait in [10, 20, 40, 60, 60] {
+            assert_eq!(attempt(&mut conn, &keys, now), None);
+            assert_eq!(attempt(&mut conn, &keys, now), Some(wait));
+            assert_eq!(attempt(&mut conn, &keys, now + wait - 1), Some(1));
+            now += wait;
+        }
+        assert_eq!(failures(&conn, &keys[0]), Some(LIMITS.max_attempts + 5));
+    }
+
+    #[test]
+    fn a_lock_on_any_key_blocks_the_attempt() {
+        let mut conn = db();
+        let user = vec![user_key("alice")];
+        for _ in 0..=LIMITS.max_attempts {
+            attempt(&mut conn, &user, NOW);
+        }
+        // A fresh IP is still turned away for the locked user, and isn't charged for it
+        let other_ip = vec![ip_key("203.0.113.9".parse().unwrap()), user_key(" alice ")];
+        assert_eq!(attempt(&mut conn, &other_ip, NOW), Some(10));
+        assert_eq!(failures(&conn, &other_ip[0]), None);
+
+        clear(&conn, &user[0]).unwrap();
+        assert_eq!(attempt(&mut conn, &other_ip, NOW), None);
+    }
+
+    #[test]
+    fn success_takes_the_attempt_back() {
+        let mut conn = db();
+        let keys = keys();
+        for _ in 0..10 {
+            assert_eq!(attempt(&mut conn, &keys, NOW), None);
+            forgive_with(&conn, &keys[0], &LIMITS).unwrap();
+            forgive_with(&conn, &keys[1], &LIMITS).unwrap();
+        }
+        assert_eq!(failures(&conn, &keys[0]), Some(0));
+
+        // Including the lock, when the successful attempt was the one that set it
+        for _ in 0..LIMITS.max_attempts {
+            attempt(&mut conn, &keys, NOW);
+        }
+        assert_eq!(attempt(&mut conn, &keys, NOW), None);
+        forgive_with(&conn, &keys[0], &LIMITS).unwrap();
+        forgive_with(&conn, &keys[1], &LIMITS).unwrap();
+        assert_eq!(attempt(&mut conn, &keys, NOW), None);
+        // but not one set by an earlier failure
+        assert_eq!(attempt(&mut conn, &keys, NOW), Some(10));
+        forgive_with(&conn, &keys[0], &LIMITS).unwrap();
+        assert_eq!(attempt(&mut conn, &keys, NOW), Some(10));
+    }
+
+    #[test]
+    fn failures_are_forgotten_after_an_hour() {
+        let mut conn = db();
+        let keys = keys();
+        for _ in 0..LIMITS.max_attempts {
+            attempt(&mut conn, &keys, NOW);
+        }
+        assert_eq!(attempt(&mut conn, &keys, NOW + FORGET_AFTER_SECS), None);
+        assert_eq!(failures(&conn, &keys[0]), Some(1));
+
+        let stale = vec![item_key("old")];
+        let locked = vec![item_key("locked")];
+        attempt(&mut conn, &stale, NOW);
+        let lockout = Limits { max_attempts: 1, backoff: 2 * FORGET_AFTER_SECS, lockout: 2 * FORGET_AFTER_SECS };
+        begin_attempt_at(&mut conn, &locked, &lockout, NOW).unwrap();
+        begin_attempt_at(&mut conn, &locked, &lockout, NOW).unwrap();
+        assert_eq!(purge_stale_at(&conn, NOW + FORGET_AFTER_SECS).unwrap(), 1);
+        assert_eq!(failures(&conn, &stale[0]), None);
+        assert_eq!(failures(&conn, &locked[0]), Some(2));
+        assert_eq!(purge_stale_at(&conn, NOW + 2 * FORGET_AFTER_SECS).unwrap(), 3);
+    }
+
+    #[test]
+    fn limits_from_the_environment() {
+        let vars = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| pairs.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        };
+        let defaults = Limits::from_vars(|_| None);
+        assert_eq!((defaults.max_attempts, defaults.backoff, defaults.lockout), (5, 30, 15 * 60));
+
+        let set = Limits::from_vars(vars(&[("LOGIN_MAX_ATTEMPTS", "2"), ("LOGIN_BACKOFF_SECS", " 5 "), ("LOGIN_LOCKOUT_SECS", "100")]));
+        assert_eq!((set.max_attempts, set.backoff, set.lockout), (2, 5, 100));
+        let mut conn = db();
+        let keys = keys();
+        for _ in 0..3 {
+            assert_eq!(begin_attempt_at(&mut conn, &keys, &set, NOW).unwrap(), None);
+        }
+        assert_eq!(begin_attempt_at(&mut conn, &keys, &set, NOW).unwrap(), Some(5));
+
+        // Nonsense falls back to the defaults, and the lockout is never shorter than the backoff
+        let odd = Limits::from_vars(vars(&[("LOGIN_MAX_ATTEMPTS", "0"), ("LOGIN_BACKOFF_SECS", "120"), ("LOGIN_LOCKOUT_SECS", "60")]));
+        assert_eq!((odd.max_attempts, odd.backoff, odd.lockout), (5, 120, 120));
+        let bad = Limits::from_vars(vars(&[("LOGIN_MAX_ATTEMPTS", "-1"), ("LOGIN_BACKOFF_SECS", "soon")]));
+        assert_eq!((bad.max_attempts, bad.backoff), (5, 30));
+    }
+}
@@ -0,0 +1,436 @@
+// Resumable uploads via the tus 1.0 protocol (https://tus.io/protocols/resumable-upload)
+//
+// Supported extensions: creation, termination, expiration. Upload-Metadata carries `filename`,
+// `qr_required` and the usual item options (e.g. `expires_in`). Every PATCH is stored as its own
+// part object (`tus/{id}/{offset}-{uuid}`, listed in `tus_parts`); when the last byte arrives the
+// parts are concatenated into a regular `{uuid}.{ext}` object and an `items` row is created,
+// exactly like `/api/upload`.
+
+use axum::body::Body;
+use axum::extract::{Path, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path as StdPath;
+use std::time::{Duration, UNIX_EPOCH};
+use uuid::Uuid;
+
+use crate::db::DbError;
+use crate::handlers::{alias_taken, api_upload_error, api_upload_ok, creating_caller, save_item, InsertError, is_allowed_extension, now_secs, qr_data_url, AppState, ItemOptions, MAX_FILE_SIZE};
+use crate::storage::{limit_stream, ByteStream};
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+// Unfinished uploads are discarded this long after their last PATCH
+const UPLOAD_TTL_SECS: i64 = 24 * 60 * 60;
+
+struct TusUpload {
+    ext: String,
+    metadata: HashMap<String, String>,
+    length: i64,
+    offset: i64,
+    qr_required: bool,
+    expires_at: i64,
+    code: Option<String>,
+}
+
+fn http_date(secs: i64) -> String {
+    httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64))
+}
+
+// Unique per PATCH, so a request that loses the race for an offset never touches the winner's object
+fn part_key(id: &str, offset: i64) -> String {
+    format!("tus/{}/{:020}-{}", id, offset, Uuid::new_v4().simple())
+}
+
+// Response with the headers every tus reply carries
+fn tus_response(status: StatusCode) -> Response {
+    let mut resp = status.into_response();
+    resp.headers_mut().insert("Tus-Resumable", HeaderValue::from_static(TUS_VERSION));
+    resp.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    resp
+}
+
+fn tus_error(status: StatusCode, msg: &str) -> Response {
+    let mut resp = tus_response(status);
+    *resp.body_mut() = Body::from(msg.to_string());
+    resp
+}
+
+fn header_i64(headers: &HeaderMap, name: &str) -> Option<i64> {
+    headers.get(name).and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse().ok()).filter(|v: &i64| *v >= 0)
+}
+
+// Upload-Metadata: comma-separated `key base64value` pairs
+fn parse_metadata(headers: &HeaderMap) -> HashMap<String, String> {
+    let raw = headers.get("Upload-Metadata").and_then(|v| v.to_str().ok()).unwrap_or("");
+    raw.split(',')
+        .filter_map(|pair| {
+            let mut it = pair.trim().splitn(2, ' ');
+            let key = it.next().filter(|k| !k.is_empty())?;
+            let value = it
+                .next()
+                .and_then(|v| base64::engine::general_purpose::STANDARD.decode(v.trim()).ok())
+                .and_then(|v| String::from_utf8(v).ok())
+                .unwrap_or_default();
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+// 412 response if the client speaks a different protocol version
+fn version_mismatch(headers: &HeaderMap) -> Option<Response> {
+    match headers.get("Tus-Resumable").and_then(|v| v.to_str().ok()) {
+        Some(TUS_VERSION) => None,
+        _ => {
+            let mut resp = tus_error(StatusCode::PRECONDITION_FAILED, "Unsupported Tus-Resumable version");
+            resp.headers_mut().insert("Tus-Version", HeaderValue::from_static(TUS_VERSION));
+            Some(resp)
+        }
+    }
+}
+
+fn load_upload(conn: &Connection, id: &str) -> rusqlite::Result<Option<TusUpload>> {
+    conn.query_row(
+        "SELECT ext, upload_length, upload_offset, qr_required, expires_at, code, metadata FROM tus_uploads WHERE id = ?1",
+        params![id],
+        |r| Ok(TusUpload {
+            ext: r.get(0)?,
+            length: r.get(1)?,
+            offset: r.get(2)?,
+            qr_required: r.get::<_, i64>(3)? != 0,
+            expires_at: r.get(4)?,
+            code: r.get(5)?,
+            metadata: r.get::<_, Option<String>>(6)?
+                .and_then(|m| serde_json::from_str(&m).ok())
+                .unwrap_or_default(),
+        }),
+    ).optional()
+}
+
+fn part_keys(conn: &Connection, id: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT part_key FROM tus_parts WHERE upload_id = ?1 ORDER BY part_offset")?;
+    let rows = stmt.query_map(params![id], |r| r.get(0))?;
+    rows.collect()
+}
+
+// Remove an upload's part objects and database rows
+async fn discard_upload(state: &AppState, id: &str) {
+    let upload_id = id.to_string();
+    let keys = state.db.query(move |conn| part_keys(conn, &upload_id)).await.unwrap_or_default();
+    for key in keys {
+        let _ = state.storage.delete(&key).await;
+    }
+    let upload_id = id.to_string();
+    let _ = state.db.call(move |conn| {
+        let _ = conn.execute("DELETE FROM tus_parts WHERE upload_id = ?1", params![upload_id]);
+        let _ = conn.execute("DELETE FROM tus_uploads WHERE id = ?1", params![upload_id]);
+    }).await;
+}
+
+fn db_error(context: &str, e: DbError) -> Response {
+    tracing::error!("{}: {}", context, e);
+    tus_error(e.status(), e.public_message())
+}
+
+fn discovery_headers(h: &mut HeaderMap) {
+    h.insert("Tus-Resumable", HeaderValue::from_static(TUS_VERSION));
+    h.insert("Tus-Version", HeaderValue::from_static(TUS_VERSION));
+    h.insert("Tus-Extension", HeaderValue::from_static(TUS_EXTENSIONS));
+    h.insert("Tus-Max-Size", HeaderValue::from(MAX_FILE_SIZE as u64));
+}
+
+pub async fn tus_options() -> Response {
+    let mut resp = tus_response(StatusCode::NO_CONTENT);
+    discovery_headers(resp.headers_mut());
+    resp
+}
+
+// The CORS layer answers every OPTIONS request itself, so add the discovery headers from outside it
+pub async fn options_middleware(req: Request, next: Next) -> Response {
+    let is_discovery = req.method() == Method::OPTIONS && req.uri().path().starts_with("/api/tus");
+    let mut resp = next.run(req).await;
+    if is_discovery {
+        discovery_headers(resp.headers_mut());
+    }
+    resp
+}
+
+// Creation extension: POST with Upload-Length and optional Upload-Metadata (filename, qr_required)
+pub async fn tus_create(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(resp) = version_mismatch(&headers) { return resp; }
+    let caller = match creating_caller(&state, &headers).await {
+        Ok(caller) => caller,
+        Err((status, message)) => return tus_error(status, message),
+    };
+    let Some(length) = header_i64(&headers, "Upload-Length") else {
+        return tus_error(StatusCode::BAD_REQUEST, "Upload-Length required");
+    };
+    if length as u64 > MAX_FILE_SIZE as u64 {
+        return tus_error(StatusCode::PAYLOAD_TOO_LARGE, "File too large");
+    }
+    let meta = parse_metadata(&headers);
+    let filename = meta.get("filename").or_else(|| meta.get("name")).map(String::as_str).unwrap_or("file");
+    let ext = StdPath::new(filename).extension().and_then(|e| e.to_str()).unwrap_or("bin").to_ascii_lowercase();
+    if !is_allowed_extension(&ext) {
+        return tus_error(StatusCode::BAD_REQUEST, "File type not allowed");
+    }
+    let qr_required = meta.get("qr_required").is_some_and(|v| v.trim().eq_ignore_ascii_case("true"));
+    // Item options (expires_in, ...) are applied when the upload finishes; reject bad values now
+    let mut item_options: HashMap<String, String> = meta.into_iter().filter(|(k, _)| ItemOptions::is_option_field(k)).collect();
+    let mut probe = ItemOptions::default();
+    for (name, value) in &item_options {
+        if let Err(e) = probe.apply_field(name, value).await { return tus_error(StatusCode::BAD_REQUEST, &e); }
+    }
+    if let Some(alias) = probe.alias.clone() {
+        match state.db.query(move |conn| alias_taken(conn, &alias)).await {
+            Ok(false) => {}
+            Ok(true) => return tus_error(StatusCode::CONFLICT, &InsertError::AliasTaken(probe.alias.unwrap_or_default()).to_string()),
+            Err(e) => return db_error("tus create", e),
+        }
+    }
+    // Only the hash of an item password is kept while the upload is in progress
+    item_options.remove("password");
+    if let Some(hash) = probe.password_hash {
+        item_options.insert("password_hash".to_string(), hash);
+    }
+    if let Some(key_id) = caller.api_key_id() {
+        item_options.insert("api_key_id".to_string(), key_id.to_string());
+    }
+
+    let id = Uuid::new_v4().simple().to_string();
+    let expires_at = now_secs() + UPLOAD_TTL_SECS;
+    let row = (id.clone(), serde_json::to_string(&item_options).unwrap_or_default());
+    let inserted = state.db.query(move |conn| conn.execute(
+        "INSERT INTO tus_uploads (id, ext, upload_length, upload_offset, qr_required, created_at, expires_at, metadata) VALUES (?1, ?2, ?3, 0, ?4, strftime('%s','now'), ?5, ?6)",
+        params![row.0, ext, length, qr_required as i64, expires_at, row.1],
+    )).await;
+    if let Err(e) = inserted {
+        return db_error("tus create", e);
+    }
+
+    let mut resp = tus_response(StatusCode::CREATED);
+    let location = format!("{}/api/tus/{}", state.base_url.trim_end_matches('/'), id);
+    resp.headers_mut().insert(header::LOCATION, HeaderValue::from_str(&location).unwrap());
+    resp.headers_mut().insert("Upload-Expires", HeaderValue::from_str(&http_date(expires_at)).unwrap());
+    resp.headers_mut().insert("Upload-Offset", HeaderValue::from_static("0"));
+    tracing::info!("tus upload {} created ({} bytes)", id, length);
+    resp
+}
+
+// Look up an upload for HEAD/PATCH, mapping missing or expired uploads to 404/410
+async fn find_upload(state: &AppState, id: &str) -> Result<TusUpload, (StatusCode, &'static str)> {
+    let upload_id = id.to_string();
+    let upload = state.db.query(move |conn| load_upload(conn, &upload_id)).await;
+    match upload {
+        Ok(Some(u)) if u.code.is_none() && u.expires_at <= now_secs() => Err((StatusCode::GONE, "Upload expired")),
+        Ok(Some(u)) => Ok(u),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Upload not found")),
+        Err(e) => {
+            tracing::error!("tus lookup {}: {}", id, e);
+            Err((e.status(), e.public_message()))
+        }
+    }
+}
+
+fn offset_headers(resp: &mut Response, upload: &TusUpload) {
+    let h = resp.headers_mut();
+    h.insert("Upload-Offset", HeaderValue::from(upload.offset));
+    h.insert("Upload-Length", HeaderValue::from(upload.length));
+    if upload.code.is_none() {
+        h.insert("Upload-Expires", HeaderValue::from_str(&http_date(upload.expires_at)).unwrap());
+    }
+}
+
+pub async fn tus_head(State(state): State<AppState>, Path(id): Path<String>, headers: HeaderMap) -> Response {
+    if let Some(resp) = version_mismatch(&headers) { return resp; }
+    match find_upload(&state, &id).await {
+        Ok(upload) => {
+            let mut resp = tus_response(StatusCode::OK);
+            offset_headers(&mut resp, &upload);
+            resp
+        }
+        Err((status, msg)) => tus_error(status, msg),
+    }
+}
+
+pub async fn tus_patch(State(state): State<AppState>, Path(id): Path<String>, headers: HeaderMap, body: Body) -> Response {
+    if let Some(resp) = version_mismatch(&headers) { return resp; }
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+    if content_type != "application/offset+octet-stream" {
+        return tus_error(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Content-Type must be application/offset+octet-stream");
+    }
+    let upload = match find_upload(&state, &id).await { Ok(u) => u, Err((status, msg)) => return tus_error(status, msg) };
+    if upload.code.is_some() {
+        return tus_error(StatusCode::FORBIDDEN, "Upload already finished");
+    }
+    if header_i64(&headers, "Upload-Offset") != Some(upload.offset) {
+        let mut resp = tus_error(StatusCode::CONFLICT, "Upload-Offset mismatch");
+        offset_headers(&mut resp, &upload);
+        return resp;
+    }
+
+    // A chunk is kept only once fully received; an interrupted PATCH is resumed from the previous offset
+    let remaining = (upload.length - upload.offset) as u64;
+    let key = part_key(&id, upload.offset);
+    let stream: ByteStream<'static> = Box::pin(body.into_data_stream().map_err(io::Error::other));
+    let written = match state.storage.put_stream(&key, limit_stream(stream, remaining)).await {
+        Ok(n) => n as i64,
+        Err(e) if e.kind() == io::ErrorKind::FileTooLarge => {
+            return tus_error(StatusCode::PAYLOAD_TOO_LARGE, "Chunk exceeds Upload-Length");
+        }
+        Err(e) => {
+            tracing::warn!("tus patch {}: {}", id, e);
+            return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to store chunk");
+        }
+    };
+
+    let new_offset = upload.offset + written;
+    let expires_at = now_secs() + UPLOAD_TTL_SECS;
+    let (upload_id, old_offset, part) = (id.clone(), upload.offset, key.clone());
+    let advanced = state.db.query(move |conn| {
+        // Guard on the old offset so two concurrent PATCHes cannot both win
+        let n = conn.execute(
+            "UPDATE tus_uploads SET upload_offset = ?1, expires_at = ?2 WHERE id = ?3 AND upload_offset = ?4",
+            params![new_offset, expires_at, upload_id, old_offset],
+        )?;
+        if n == 1 && written > 0 {
+            conn.execute(
+                "INSERT INTO tus_parts (upload_id, part_offset, size, part_key) VALUES (?1, ?2, ?3, ?4)",
+                params![upload_id, old_offset, written, part],
+            )?;
+        }
+        Ok(n == 1)
+    }).await;
+    match advanced {
+        Ok(true) => {}
+        Ok(false) => {
+            let _ = state.storage.delete(&key).await;
+            return tus_error(StatusCode::CONFLICT, "Upload-Offset mismatch");
+        }
+        Err(e) => {
+            let _ = state.storage.delete(&key).await;
+            return db_error(&format!("tus patch {}", id), e);
+        }
+    }
+
+    let mut upload = TusUpload { offset: new_offset, expires_at, ..upload };
+    if upload.offset == upload.length {
+        match finish_upload(&state, &id, &upload).await {
+            Ok(code) => upload.code = Some(code),
+            Err(e) => {
+                tracing::error!("tus finish {}: {}", id, e);
+                if let Some(e) = e.downcast_ref::<InsertError>() {
+                    return tus_error(e.status(), &e.public_message());
+                }
+                if let Some(e) = e.downcast_ref::<DbError>() {
+                    return tus_error(e.status(), e.public_message());
+                }
+                return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to assemble upload");
+            }
+        }
+    }
+
+    let mut resp = tus_response(StatusCode::NO_CONTENT);
+    offset_headers(&mut resp, &upload);
+    if let Some(code) = &upload.code {
+        let short_url = format!("{}/s/{}", state.base_url, code);
+        resp.headers_mut().insert("Ping0-Short-Url", HeaderValue::from_str(&short_url).unwrap());
+    }
+    resp
+}
+
+// Concatenate the parts into the final object and create the short link
+async fn finish_upload(state: &AppState, id: &str, upload: &TusUpload) -> anyhow::Result<String> {
+    let upload_id = id.to_string();
+    let keys = state.db.query(move |conn| part_keys(conn, &upload_id)).await?;
+    let filename_saved = format!("{}.{}", Uuid::new_v4(), upload.ext);
+    let storage = state.storage.clone();
+    let parts = stream::iter(keys.clone())
+        .then(move |key| {
+            let storage = storage.clone();
+            async move { storage.stream(&key, None).await }
+        })
+        .try_flatten()
+        .boxed();
+    let size = state.storage.put_stream(&filename_saved, parts).await?;
+    anyhow::ensure!(size as i64 == upload.length, "assembled {} bytes, expected {}", size, upload.length);
+
+    let mut opts = ItemOptions::default();
+    for (name, value) in &upload.metadata {
+        opts.apply_field(name, value).await.map_err(|e| anyhow::anyhow!(e))?;
+    }
+    opts.password_hash = upload.metadata.get("password_hash").cloned();
+    opts.api_key_id = upload.metadata.get("api_key_id").and_then(|id| id.parse().ok());
+    let short_code = match save_item(state, "file", format!("file:{}", filename_saved), opts).await {
+        Ok(code) => code,
+        Err(e) => {
+            let _ = state.storage.delete(&filename_saved).await;
+            return Err(e.into());
+        }
+    };
+    let (upload_id, code) = (id.to_string(), short_code.clone());
+    state.db.query(move |conn| {
+        conn.execute("UPDATE tus_uploads SET code = ?1 WHERE id = ?2", params![code, upload_id])?;
+        conn.execute("DELETE FROM tus_parts WHERE upload_id = ?1", params![upload_id])
+    }).await?;
+    for key in keys {
+        let _ = state.storage.delete(&key).await;
+    }
+    tracing::info!("tus upload {} finished: {}", id, filename_saved);
+    Ok(short_code)
+}
+
+// Result of a finished upload in the same shape as `/api/upload`
+pub async fn tus_result(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let upload = match find_upload(&state, &id).await { Ok(u) => u, Err((status, msg)) => return tus_error(status, msg) };
+    match upload.code {
+        Some(code) => {
+            let short_url = format!("{}/s/{}", state.base_url, code);
+            let qr_code_data = if upload.qr_required { qr_data_url(&state.base_url, &short_url) } else { None };
+            api_upload_ok(short_url, qr_code_data)
+        }
+        None => api_upload_error(StatusCode::CONFLICT, "Upload not finished"),
+    }
+}
+
+// Termination extension
+pub async fn tus_delete(State(state): State<AppState>, Path(id): Path<String>, headers: HeaderMap) -> Response {
+    if let Some(resp) = version_mismatch(&headers) { return resp; }
+    let upload_id = id.clone();
+    let exists = state.db.query(move |conn| load_upload(conn, &upload_id)).await;
+    match exists {
+        Ok(Some(u)) if u.code.is_none() => {
+            discard_upload(&state, &id).await;
+            tus_response(StatusCode::NO_CONTENT)
+        }
+        Ok(Some(_)) => tus_error(StatusCode::FORBIDDEN, "Upload already finished"),
+        Ok(None) => tus_error(StatusCode::NOT_FOUND, "Upload not found"),
+        Err(e) => db_error(&format!("tus delete {}", id), e),
+    }
+}
+
+// Drop unfinished uploads past their Upload-Expires time
+pub async fn purge_expired(state: &AppState) {
+    let ids: Vec<String> = match state.db.query(|conn| {
+        let mut stmt = conn.prepare("SELECT id FROM tus_uploads WHERE code IS NULL AND expires_at <= ?1")?;
+        let rows = stmt.query_map(params![now_secs()], |r| r.get(0))?;
+        rows.collect()
+    }).await {
+        Ok(ids) => ids,
+        Err(e) => { tracing::warn!("tus purge: {}", e); return; }
+    };
+    for id in &ids {
+        discard_upload(state, id).await;
+    }
+    // Finished uploads only keep their row around for `tus_result`
+    let _ = state.db.query(|conn| conn.execute("DELETE FROM tus_uploads WHERE code IS NOT NULL AND expires_at <= ?1", params![now_secs()])).await;
+    if !ids.is_empty() {
+        tracing::info!("tus purge: removed {} expired uploads", ids.len());
+    }
+}
@@ -0,0 +1,339 @@
+// Two-factor login for admins: RFC 6238 TOTP (SHA-1, 6 digits, 30 s steps, one step of clock drift
+// either way) and single-use recovery codes, stored as SHA-256 hashes. After the password check a
+// user with TOTP gets a short-lived signed `ping0_2fa` cookie instead of a session, and only a code
+// entered at /admin/login/2fa turns that into a session.
+
+use axum::http::HeaderMap;
+use axum_extra::headers::{Cookie, HeaderMapExt};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use sha2::Sha256;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::admins::{AccountError, AdminUser};
+use crate::apikeys::hash_token;
+use crate::handlers::{now_secs, AppState};
+
+const ISSUER: &str = "ping0";
+const STEP_SECS: u64 = 30;
+const RECOVERY_CODES: usize = 10;
+// Shown as two groups of five, e.g. "k3h9x-2mpqa"
+const RECOVERY_CODE_LEN: usize = 10;
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const CHALLENGE_COOKIE: &str = "ping0_2fa";
+const CHALLENGE_TTL_SECS: i64 = 5 * 60;
+
+fn totp(secret: &str, username: &str) -> Option<TOTP> {
+    let bytes = Secret::Encoded(secret.to_string()).to_bytes().ok()?;
+    TOTP::new(Algorithm::SHA1, 6, 0, STEP_SECS, bytes, Some(ISSUER.to_string()), username.replace(':', "_")).ok()
+}
+
+// The time step `code` belongs to at `now`, allowing one step of drift either way
+fn matching_step(totp: &TOTP, code: &str, now: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != 6 || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let step = now as u64 / STEP_SECS;
+    [step + 1, step, step.saturating_sub(1)]
+        .into_iter()
+        .find(|s| totp.check(code, s * STEP_SECS))
+        .map(|s| s as i64)
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.trim().to_ascii_lowercase().replace(['-', ' '], "")
+}
+
+pub fn is_enabled(conn: &Connection, user_id: i64) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT totp_secret IS NOT NULL FROM admin WHERE id = ?1", params![user_id], |r| r.get(0))
+        .optional()
+        .map(|enabled| enabled.unwrap_or(false))
+}
+
+pub fn remaining_recovery_codes(conn: &Connection, user_id: i64) -> rusqlite::Result<i64> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM admin_recovery_codes WHERE user_id = ?1 AND used_at IS NULL",
+        params![user_id],
+        |r| r.get(0),
+    )
+}
+
+// ---------- Enrollment ----------
+
+pub struct Enrollment {
+    // Base32, for typing into an authenticator by hand
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+// Starts (or restarts) enrollment with a new secret; logins only need a code once it is confirmed
+pub fn begin_enrollment(conn: &Connection, user: &AdminUser) -> rusqlite::Result<Option<Enrollment>> {
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    conn.execute("UPDATE admin SET totp_pending = ?1 WHERE id = ?2", params![secret, user.id])?;
+    Ok(totp(&secret, &user.username).map(|t| Enrollment { otpauth_url: t.get_url(), secret }))
+}
+
+pub fn pending_enrollment(conn: &Connection, user: &AdminUser) -> rusqlite::Result<Option<Enrollment>> {
+    let secret: Option<String> = conn
+        .query_row("SELECT totp_pending FROM admin WHERE id = ?1", params![user.id], |r| r.get(0))
+        .optional()?
+        .flatten();
+    Ok(secret.and_then(|secret| totp(&secret, &user.username).map(|t| Enrollment { otpauth_url: t.get_url(), secret })))
+}
+
+fn replace_recovery_codes(conn: &Connection, user_id: i64) -> rusqlite::Result<Vec<String>> {
+    conn.execute("DELETE FROM admin_recovery_codes WHERE user_id = ?1", params![user_id])?;
+    let mut rng = rand::thread_rng();
+    let mut codes = Vec::with_capacity(RECOVERY_CODES);
+    for _ in 0..RECOVERY_CODES {
+        let raw: String = (0..RECOVERY_CODE_LEN)
+            .map(|_| RECOVERY_ALPHABET[rng.gen_range(0..RECOVERY_ALPHABET.len())] as char)
+            .collect();
+        conn.execute(
+            "INSERT INTO admin_recovery_codes (user_id, code_hash, created_at) VALUES (?1, ?2, ?3)",
+            params![user_id, hash_token(&raw), now_secs()],
+        )?;
+        codes.push(format!("{}-{}", &raw[..RECOVERY_CODE_LEN / 2], &raw[RECOVERY_CODE_LEN / 2..]));
+    }
+    Ok(codes)
+}
+
+// Turns TOTP on once a code from the pending secret checks out; returns the recovery codes to show once
+pub fn confirm_enrollment(conn: &mut Connection, user: &AdminUser, code: &str) -> Result<Vec<String>, AccountError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let pending: Option<String> = tx
+        .query_row("SELECT totp_pending FROM admin WHERE id = ?1", params![user.id], |r| r.get(0))
+        .optional()?
+        .flatten();
+    let Some(secret) = pending else {
+        return Err(AccountError::Rejected("Start the enrollment first".to_string()));
+    };
+    let Some(step) = totp(&secret, &user.username).and_then(|t| matching_step(&t, code, now_secs())) else {
+        return Err(AccountError::Rejected("Wrong code. Check that the clock of your device is correct.".to_string()));
+    };
+    tx.execute(
+        "UPDATE admin SET totp_secret = totp_pending, totp_pending = NULL, totp_last_step = ?1 WHERE id = ?2",
+        params![step, user.id],
+    )?;
+    let codes = replace_recovery_codes(&tx, user.id)?;
+    tx.commit()?;
+    tracing::info!("Admin '{}' enabled two-factor login", user.username);
+    Ok(codes)
+}
+
+// New recovery codes; the old ones stop working
+pub fn regenerate_recovery_codes(conn: &mut Connection, user: &AdminUser, code: &str) -> Result<Vec<String>, AccountError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if !verify_code(&tx, user.id, code)? {
+        return Err(AccountError::Rejected("Wrong code".to_string()));
+    }
+    let codes = replace_recovery_codes(&tx, user.id)?;
+    tx.commit()?;
+    Ok(codes)
+}
+
+pub fn disable(conn: &mut Connection, user: &AdminUser, code: &str) -> Result<(), AccountError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if !verify_code(&tx, user.id, code)? {
+        return Err(AccountError::Rejected("Wrong code".to_string()));
+    }
+    reset(&tx, user.id)?;
+    tx.commit()?;
+    tracing::info!("Admin '{}' disabled two-factor login", user.username);
+    Ok(())
+}
+
+// Removes TOTP and recovery codes (also used by `ping0 admin disable-2fa`)
+pub fn reset(conn: &Connection, user_id: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE admin SET totp_secret = NULL, totp_pending = NULL, totp_last_step = NULL WHERE id = ?1",
+        params![user_id],
+    )?;
+    conn.execute("DELETE FROM admin_recovery_codes WHERE user_id = ?1", params![user_id])?;
+    Ok(())
+}
+
+// ---------- Login ----------
+
+// A TOTP code newer than the last one accepted, or an unused recovery code (which is then spent)
+pub fn verify_code(conn: &Connection, user_id: i64, code: &str) -> rusqlite::Result<bool> {
+    verify_code_at(conn, user_id, code, now_secs())
+}
+
+fn verify_code_at(conn: &Connection, user_id: i64, code: &str, now: i64) -> rusqlite::Result<bool> {
+    let row: Option<(String, Option<String>)> = conn
+        .query_row("SELECT username, totp_secret FROM admin WHERE id = ?1", params![user_id], |r| Ok((r.get(0)?, r.get(1)?)))
+        .optional()?;
+    let Some((username, Some(secret))) = row else { return Ok(false) };
+    if let Some(step) = totp(&secret, &username).and_then(|t| matching_step(&t, code, now)) {
+        // A code can't be replayed within its validity window
+        let updated = conn.execute(
+            "UPDATE admin SET totp_last_step = ?1 WHERE id = ?2 AND (totp_last_step IS NULL OR totp_last_step < ?1)",
+            params![step, user_id],
+        )?;
+        return Ok(updated == 1);
+    }
+    let recovery = normalize_recovery_code(code);
+    if recovery.len() != RECOVERY_CODE_LEN {
+        return Ok(false);
+    }
+    let used = conn.execute(
+        "UPDATE admin_recovery_codes SET used_at = ?1 WHERE user_id = ?2 AND code_hash = ?3 AND used_at IS NULL",
+        params![now, user_id, hash_token(&recovery)],
+    )?;
+    if used == 1 {
+        tracing::warn!("Admin '{}' logged in with a recovery code", username);
+    }
+    Ok(used == 1)
+}
+
+// Challenge cookies are "<user id>.<expiry>.<hmac>". The MAC covers the password hash, so a password
+// change also ends pending logins.
+fn challenge_mac(secret: &[u8], user_id: i64, expires: i64, password_hash: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(format!("2fa:{}:{}:", user_id, expires).as_bytes());
+    mac.update(password_hash.as_bytes());
+    mac
+}
+
+fn password_hash(conn: &Connection, user_id: i64) -> rusqlite::Result<Option<String>> {
+    conn.query_row("SELECT password_hash FROM admin WHERE id = ?1 AND disabled_at IS NULL", params![user_id], |r| r.get(0))
+        .optional()
+}
+
+fn challenge_value(secret: &[u8], conn: &Connection, user_id: i64, now: i64) -> rusqlite::Result<String> {
+    let hash = password_hash(conn, user_id)?.unwrap_or_default();
+    let expires = now + CHALLENGE_TTL_SECS;
+    let sig = hex::encode(challenge_mac(secret, user_id, expires, &hash).finalize().into_bytes());
+    Ok(format!("{}.{}.{}", user_id, expires, sig))
+}
+
+fn challenge_value_user(secret: &[u8], conn: &Connection, value: &str, now: i64) -> Option<i64> {
+    let mut parts = value.splitn(3, '.');
+    let user_id: i64 = parts.next()?.parse().ok()?;
+    let expires: i64 = parts.next()?.parse().ok()?;
+    let sig = hex::decode(parts.next()?).ok()?;
+    if expires <= now {
+        return None;
+    }
+    let hash = password_hash(conn, user_id).ok()??;
+    challenge_mac(secret, user_id, expires, &hash).verify_slice(&sig).ok()?;
+    Some(user_id)
+}
+
+// Set-Cookie value for a user who passed the password check
+pub fn challenge_cookie(state: &AppState, conn: &Connection, user_id: i64) -> rusqlite::Result<String> {
+    let value = challenge_value(&state.cookie_secret, conn, user_id, now_secs())?;
+    let secure = if state.base_url.starts_with("https://") { "; Secure" } else { "" };
+    Ok(format!(
+        "{}={}; HttpOnly; SameSite=Lax; Path=/admin/login; Max-Age={}{}",
+        CHALLENGE_COOKIE, value, CHALLENGE_TTL_SECS, secure
+    ))
+}
+
+pub fn clear_challenge_cookie() -> String {
+    format!("{}=; Max-Age=0; Path=/admin/login", CHALLENGE_COOKIE)
+}
+
+// The user waiting for their second factor, if the request carries a valid challenge cookie
+pub fn challenge_user(state: &AppState, conn: &Connection, headers: &HeaderMap) -> Option<i64> {
+    let cookie = headers.typed_get::<Cookie>()?;
+    challenge_value_user(&state.cookie_secret, conn, cookie.get(CHALLENGE_COOKIE)?, now_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+    // The start of a time step
+    const NOW: i64 = 1_700_000_010;
+
+    fn code_at(time: i64) -> String {
+        totp(SECRET, "alice").unwrap().generate(time as u64)
+    }
+
+    fn db() -> (Connection, i64) {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::run(&mut conn).unwrap();
+        conn.execute("INSERT INTO admin (username, password_hash, salt, totp_secret) VALUES ('alice', 'hash-1', '', ?1)", [SECRET])
+            .unwrap();
+        let id = conn.last_insert_rowid();
+        (conn, id)
+    }
+
+    #[test]
+    fn one_step_of_drift_either_way() {
+        let totp = totp(SECRET, "alice").unwrap();
+        let step = NOW / STEP_SECS as i64;
+        assert_eq!(matching_step(&totp, &code_at(NOW), NOW), Some(step));
+        assert_eq!(matching_step(&totp, &code_at(NOW), NOW + 29), Some(step));
+        assert_eq!(matching_step(&totp, &code_at(NOW - 30), NOW), Some(step - 1));
+        assert_eq!(matching_step(&totp, &code_at(NOW + 30), NOW), Some(step + 1));
+        assert_eq!(matching_step(&totp, &code_at(NOW - 60), NOW), None);
+        assert_eq!(matching_step(&totp, &code_at(NOW + 60), NOW), None);
+        assert_eq!(matching_step(&totp, &format!(" {} ", code_at(NOW)), NOW), Some(step));
+        for bad in ["", "12345", "1234567", "12345a"] {
+            assert_eq!(matching_step(&totp, bad, NOW), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn codes_are_not_accepted_twice() {
+        let (conn, id) = db();
+        assert!(verify_code_at(&conn, id, &code_at(NOW), NOW).unwrap());
+        assert!(!verify_code_at(&conn, id, &code_at(NOW), NOW).unwrap());
+        // Still valid 30 s later thanks to the drift allowance, but already used
+        assert!(!verify_code_at(&conn, id, &code_at(NOW), NOW + 30).unwrap());
+        // Nor is an older one that is still within the window
+        assert!(!verify_code_at(&conn, id, &code_at(NOW - 30), NOW).unwrap());
+        let last: i64 = conn.query_row("SELECT totp_last_step FROM admin WHERE id = ?1", [id], |r| r.get(0)).unwrap();
+        assert_eq!(last, NOW / STEP_SECS as i64);
+
+        assert!(verify_code_at(&conn, id, &code_at(NOW + 30), NOW + 30).unwrap());
+        assert!(!verify_code_at(&conn, id, &code_at(NOW + 120), NOW + 60).unwrap());
+    }
+
+    #[test]
+    fn recovery_codes_are_single_use() {
+        let (conn, id) = db();
+        let codes = replace_recovery_codes(&conn, id).unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert_eq!(remaining_recovery_codes(&conn, id).unwrap(), RECOVERY_CODES as i64);
+
+        assert!(verify_code_at(&conn, id, &codes[0], NOW).unwrap());
+        assert!(!verify_code_at(&conn, id, &codes[0], NOW).unwrap());
+        // Case, dashes and spaces don't matter
+        let typed = format!(" {} ", codes[1].to_ascii_uppercase().replace('-', " "));
+        assert!(verify_code_at(&conn, id, &typed, NOW).unwrap());
+        assert!(!verify_code_at(&conn, id, &codes[1], NOW).unwrap());
+        assert_eq!(remaining_recovery_codes(&conn, id).unwrap(), RECOVERY_CODES as i64 - 2);
+
+        // Regenerating invalidates the rest
+        replace_recovery_codes(&conn, id).unwrap();
+        assert!(!verify_code_at(&conn, id, &codes[2], NOW).unwrap());
+    }
+
+    #[test]
+    fn challenge_ends_with_a_password_change() {
+        let (conn, id) = db();
+        let key = [7u8; 32];
+        let value = challenge_value(&key, &conn, id, NOW).unwrap();
+        assert_eq!(challenge_value_user(&key, &conn, &value, NOW), Some(id));
+        assert_eq!(challenge_value_user(&key, &conn, &value, NOW + CHALLENGE_TTL_SECS - 1), Some(id));
+        assert_eq!(challenge_value_user(&key, &conn, &value, NOW + CHALLENGE_TTL_SECS), None);
+        assert_eq!(challenge_value_user(&[8u8; 32], &conn, &value, NOW), None);
+        let other = value.replacen(&format!("{}.", id), &format!("{}.", id + 1), 1);
+        assert_eq!(challenge_value_user(&key, &conn, &other, NOW), None);
+
+        conn.execute("UPDATE admin SET password_hash = 'hash-2' WHERE id = ?1", [id]).unwrap();
+        assert_eq!(challenge_value_user(&key, &conn, &value, NOW), None);
+        let fresh = challenge_value(&key, &conn, id, NOW).unwrap();
+        assert_eq!(challenge_value_user(&key, &conn, &fresh, NOW), Some(id));
+
+        conn.execute("UPDATE admin SET disabled_at = ?1 WHERE id = ?2", [NOW, id]).unwrap();
+        assert_eq!(challenge_value_user(&key, &conn, &fresh, NOW), None);
+    }
+}
@@ -0,0 +1,250 @@
+// Shared setup for the integration tests: the full router over a throwaway database and upload
+// directory, driven in-process, and a minimal browser that keeps cookies and CSRF tokens.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::body::{to_bytes, Body};
+use axum::extract::ConnectInfo;
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::Router;
+use tower::ServiceExt;
+
+use ping0::admins::{self, Role};
+use ping0::clientip::TrustedProxies;
+use ping0::db::Db;
+use ping0::handlers::AppState;
+use ping0::ratelimit::RateLimiter;
+use ping0::storage::LocalStorage;
+use ping0::{migrations, passkeys, routes};
+
+pub const BASE_URL: &str = "http://localhost:8080";
+pub const CLIENT_ADDR: ([u8; 4], u16) = ([203, 0, 113, 7], 40000);
+
+pub struct TestApp {
+    pub state: AppState,
+    router: Router,
+    dir: PathBuf,
+}
+
+impl TestApp {
+    pub fn new() -> TestApp {
+        TestApp::with(|_| {})
+    }
+
+    // `configure` can adjust the state (e.g. add an OIDC provider) before the router is built
+    pub fn with(configure: impl FnOnce(&mut AppState)) -> TestApp {
+        let dir = std::env::temp_dir().join(format!("ping0-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = Db::open(dir.join("ping0.db").to_str().unwrap()).unwrap();
+        migrations::run(&mut db.get().unwrap()).unwrap();
+        let secret: [u8; 32] = rand::random();
+        let mut state = AppState {
+            db,
+            base_url: BASE_URL.to_string(),
+            storage: Arc::new(LocalStorage::new(dir.join("uploads"))),
+            cookie_secret: secret.to_vec().into(),
+            geoip: None,
+            webauthn: passkeys::build(BASE_URL).map(Arc::new),
+            oidc: None,
+            rate_limiter: Arc::new(RateLimiter::from_env()),
+            trusted_proxies: Arc::new(TrustedProxies::default()),
+        };
+        configure(&mut state);
+        let router = routes::router(state.clone());
+        TestApp { state, router, dir }
+    }
+
+    pub fn create_admin(&self, username: &str, password: &str, role: Role) -> i64 {
+        let conn = self.state.db.get().unwrap();
+        let hash = ping0::password::hash_password(password).unwrap();
+        match admins::insert_user(&conn, username, &hash, role) {
+            Ok(id) => id,
+            Err(_) => panic!("could not create admin {}", username),
+        }
+    }
+
+    // Root of the app's local storage
+    pub fn uploads_dir(&self) -> PathBuf {
+        self.dir.join("uploads")
+    }
+
+    pub fn browser(&self) -> Browser<'_> {
+        Browser { app: self, cookies: HashMap::new(), csrf: None }
+    }
+
+    // Every request comes from CLIENT_ADDR, as if served with connect info
+    pub async fn send(&self, mut req: Request<Body>) -> TestResponse {
+        req.extensions_mut().insert(ConnectInfo(SocketAddr::from(CLIENT_ADDR)));
+        let resp = self.router.clone().oneshot(req).await.unwrap();
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        TestResponse { status, headers, body: String::from_utf8_lossy(&body).into_owned() }
+    }
+}
+
+// multipart/form-data request with text fields and an optional file (field name, file name, content)
+pub fn multipart(path: &str, fields: &[(&str, &str)], file: Option<(&str, &str, &[u8])>) -> Request<Body> {
+    let boundary = format!("ping0-test-{}", uuid::Uuid::new_v4().simple());
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", boundary, name, value).as_bytes());
+    }
+    if let Some((name, filename, data)) = file {
+        body.extend_from_slice(
+            format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n", boundary, name, filename).as_bytes(),
+        );
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    Request::post(path)
+        .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+// tus creation request; `metadata` values are base64-encoded here
+pub fn tus_create(length: usize, metadata: &[(&str, &str)]) -> Request<Body> {
+    use base64::Engine;
+    let metadata = metadata
+        .iter()
+        .map(|(k, v)| format!("{} {}", k, base64::engine::general_purpose::STANDARD.encode(v)))
+        .collect::<Vec<_>>()
+        .join(",");
+    Request::post("/api/tus")
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Length", length.to_string())
+        .header("Upload-Metadata", metadata)
+        .body(Body::empty())
+        .unwrap()
+}
+
+pub fn tus_patch(path: &str, offset: usize, chunk: impl Into<Body>) -> Request<Body> {
+    Request::patch(path)
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Offset", offset.to_string())
+        .header(header::CONTENT_TYPE, "application/offset+octet-stream")
+        .body(chunk.into())
+        .unwrap()
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl TestResponse {
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, self.body))
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.headers.get(header::LOCATION).and_then(|v| v.to_str().ok())
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    // Value of a cookie set by this response
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        set_cookies(&self.headers).find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+}
+
+fn set_cookies(headers: &HeaderMap) -> impl Iterator<Item = (String, String)> + '_ {
+    headers.get_all(header::SET_COOKIE).iter().filter_map(|v| {
+        let pair = v.to_str().ok()?.split(';').next()?;
+        let (name, value) = pair.split_once('=')?;
+        Some((name.trim().to_string(), value.trim().to_string()))
+    })
+}
+
+// Keeps cookies across requests and sends the CSRF token with admin POSTs
+pub struct Browser<'a> {
+    app: &'a TestApp,
+    cookies: HashMap<String, String>,
+    csrf: Option<String>,
+}
+
+impl Browser<'_> {
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies.get(name).map(String::as_str)
+    }
+
+    pub async fn send(&mut self, mut req: Request<Body>) -> TestResponse {
+        if !self.cookies.is_empty() {
+            let cookie = self.cookies.iter().map(|(n, v)| format!("{}={}", n, v)).collect::<Vec<_>>().join("; ");
+            req.headers_mut().insert(header::COOKIE, cookie.parse().unwrap());
+        }
+        let resp = self.app.send(req).await;
+        for (name, value) in set_cookies(&resp.headers) {
+            if value.is_empty() {
+                self.cookies.remove(&name);
+            } else {
+                self.cookies.insert(name, value);
+            }
+        }
+        resp
+    }
+
+    pub async fn get(&mut self, path: &str) -> TestResponse {
+        self.send(Request::get(path).body(Body::empty()).unwrap()).await
+    }
+
+    // The token admin forms carry, taken from the login page
+    pub async fn csrf_token(&mut self) -> String {
+        if let Some(token) = &self.csrf {
+            return token.clone();
+        }
+        let page = self.get("/admin/login").await;
+        let start = page.body.find("name=\"csrf_token\" value=\"").expect("login page has a CSRF field") + 25;
+        let token = page.body[start..].split('"').next().unwrap().to_string();
+        self.csrf = Some(token.clone());
+        token
+    }
+
+    pub async fn post_form(&mut self, path: &str, fields: &[(&str, &str)]) -> TestResponse {
+        let mut form = url::form_urlencoded::Serializer::new(String::new());
+        if path.starts_with("/admin") {
+            form.append_pair("csrf_token", &self.csrf_token().await);
+        }
+        form.extend_pairs(fields);
+        let req = Request::post(path)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form.finish()))
+            .unwrap();
+        self.send(req).await
+    }
+
+    pub async fn send_json(&mut self, method: Method, path: &str, body: &serde_json::Value) -> TestResponse {
+        let mut req = Request::builder().method(method).uri(path).header(header::CONTENT_TYPE, "application/json");
+        if path.starts_with("/admin") {
+            req = req.header("x-csrf-token", self.csrf_token().await);
+        }
+        self.send(req.body(Body::from(body.to_string())).unwrap()).await
+    }
+
+    pub async fn post_json(&mut self, path: &str, body: &serde_json::Value) -> TestResponse {
+        self.send_json(Method::POST, path, body).await
+    }
+
+    // Password login; panics unless it ends in a session
+    pub async fn login(&mut self, username: &str, password: &str) {
+        let resp = self.post_form("/admin/login", &[("username", username), ("password", password)]).await;
+        assert_eq!(resp.status, StatusCode::SEE_OTHER, "login failed: {}", resp.body);
+        assert!(self.cookie("ping0_admin").is_some());
+    }
+}
@@ -0,0 +1,89 @@
+// Browser sessions calling the JSON API: reading needs only the cookie, changing anything also needs the
+// admin panel's CSRF token, as admin forms do. API keys need neither.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use serde_json::json;
+
+use common::{Browser, TestApp, TestResponse};
+use ping0::admins::Role;
+use ping0::apikeys::{self, Scope};
+
+async fn call(browser: &mut Browser<'_>, method: Method, path: &str, body: serde_json::Value, token: Option<&str>) -> TestResponse {
+    let mut req = Request::builder().method(method).uri(path).header(header::CONTENT_TYPE, "application/json");
+    if let Some(token) = token {
+        req = req.header("x-csrf-token", token);
+    }
+    browser.send(req.body(Body::from(body.to_string())).unwrap()).await
+}
+
+fn assert_csrf_rejected(resp: &TestResponse) {
+    assert_eq!(resp.status, StatusCode::FORBIDDEN, "{}", resp.body);
+    assert_eq!(resp.json()["error"]["code"], "csrf_failed");
+}
+
+#[tokio::test]
+async fn session_changes_need_the_csrf_token() {
+    let app = TestApp::new();
+    app.create_admin("owner", "correct horse battery", Role::Owner);
+    let mut browser = app.browser();
+    browser.login("owner", "correct horse battery").await;
+    let token = browser.csrf_token().await;
+    let link = json!({ "url": "https://example.com/" });
+
+    for bad in [None, Some("00"), Some("not hex")] {
+        assert_csrf_rejected(&call(&mut browser, Method::POST, "/api/v1/items", link.clone(), bad).await);
+    }
+    assert_eq!(app.state.db.get().unwrap().query_row("SELECT COUNT(*) FROM items", [], |r| r.get::<_, i64>(0)).unwrap(), 0);
+
+    let created = call(&mut browser, Method::POST, "/api/v1/items", link.clone(), Some(&token)).await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let item = format!("/api/v1/items/{}", created.json()["code"].as_str().unwrap());
+
+    // Reading is safe without it
+    assert_eq!(call(&mut browser, Method::GET, "/api/v1/items", json!(null), None).await.status, StatusCode::OK);
+    assert_eq!(call(&mut browser, Method::GET, &item, json!(null), None).await.status, StatusCode::OK);
+
+    let patch = json!({ "max_views": 5 });
+    assert_csrf_rejected(&call(&mut browser, Method::PATCH, &item, patch.clone(), None).await);
+    assert_eq!(call(&mut browser, Method::PATCH, &item, patch, Some(&token)).await.status, StatusCode::OK);
+
+    let key = json!({ "name": "ci", "scopes": ["create"] });
+    assert_csrf_rejected(&call(&mut browser, Method::POST, "/api/v1/keys", key.clone(), None).await);
+    let created_key = call(&mut browser, Method::POST, "/api/v1/keys", key, Some(&token)).await;
+    assert_eq!(created_key.status, StatusCode::CREATED, "{}", created_key.body);
+    let key_path = format!("/api/v1/keys/{}", created_key.json()["id"]);
+    assert_csrf_rejected(&call(&mut browser, Method::DELETE, &key_path, json!(null), None).await);
+    assert_eq!(call(&mut browser, Method::DELETE, &key_path, json!(null), Some(&token)).await.status, StatusCode::NO_CONTENT);
+
+    assert_csrf_rejected(&call(&mut browser, Method::DELETE, &item, json!(null), None).await);
+    // Another browser's token doesn't fit this browser's cookie
+    let foreign = app.browser().csrf_token().await;
+    assert_csrf_rejected(&call(&mut browser, Method::DELETE, &item, json!(null), Some(&foreign)).await);
+    assert_eq!(call(&mut browser, Method::DELETE, &item, json!(null), Some(&token)).await.status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn api_keys_and_anonymous_uploads_need_no_token() {
+    let app = TestApp::new();
+    let (_, key) = apikeys::create_key(&app.state.db.get().unwrap(), "test", &[Scope::Admin]).unwrap();
+    let mut browser = app.browser();
+    let anonymous = call(&mut browser, Method::POST, "/api/v1/items", json!({ "url": "https://example.com/" }), None).await;
+    assert_eq!(anonymous.status, StatusCode::CREATED, "{}", anonymous.body);
+
+    let req = Request::delete(format!("/api/v1/items/{}", anonymous.json()["code"].as_str().unwrap()))
+        .header(header::AUTHORIZATION, format!("Bearer {}", key))
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(app.send(req).await.status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn csrf_cookie_reaches_the_api() {
+    let app = TestApp::new();
+    let page = app.send(Request::get("/admin/login").body(Body::empty()).unwrap()).await;
+    let cookie = page.headers.get_all(header::SET_COOKIE).iter().map(|v| v.to_str().unwrap()).find(|v| v.starts_with("ping0_csrf=")).unwrap();
+    assert!(cookie.contains("; Path=/;") || cookie.ends_with("; Path=/"), "{}", cookie);
+}
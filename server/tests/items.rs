@@ -0,0 +1,71 @@
+// Item lifecycle through the public API and the short links it hands out.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use serde_json::json;
+
+use common::{TestApp, TestResponse};
+use ping0::apikeys::{self, Scope};
+
+async fn api(app: &TestApp, token: &str, method: Method, path: &str, body: serde_json::Value) -> TestResponse {
+    let req = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    app.send(req).await
+}
+
+async fn view(app: &TestApp, code: &str) -> TestResponse {
+    app.send(Request::get(format!("/s/{}", code)).body(Body::empty()).unwrap()).await
+}
+
+#[tokio::test]
+async fn turning_on_burn_after_reading_allows_one_more_view() {
+    let app = TestApp::new();
+    let (_, token) = apikeys::create_key(&app.state.db.get().unwrap(), "test", &[Scope::Admin]).unwrap();
+
+    let created = api(&app, &token, Method::POST, "/api/v1/items", json!({ "url": "https://example.com/" })).await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let code = created.json()["code"].as_str().unwrap().to_string();
+
+    let patched = api(&app, &token, Method::PATCH, &format!("/api/v1/items/{}", code), json!({ "burn_after_reading": true })).await;
+    assert_eq!(patched.status, StatusCode::OK, "{}", patched.body);
+    assert_eq!(patched.json()["max_views"], 1);
+
+    let first = view(&app, &code).await;
+    assert_eq!(first.status, StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(first.location(), Some("https://example.com/"));
+    assert_eq!(view(&app, &code).await.status, StatusCode::GONE);
+}
+
+fn clicks(app: &TestApp, code: &str) -> i64 {
+    app.state.db.get().unwrap().query_row("SELECT COUNT(*) FROM clicks WHERE code = ?1", [code], |r| r.get(0)).unwrap()
+}
+
+#[tokio::test]
+async fn only_served_visits_are_clicks() {
+    let app = TestApp::new();
+    let (_, token) = apikeys::create_key(&app.state.db.get().unwrap(), "test", &[Scope::Admin]).unwrap();
+    let body = json!({ "url": "https://example.com/", "password": "open sesame", "max_views": 1 });
+    let created = api(&app, &token, Method::POST, "/api/v1/items", body).await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let code = created.json()["code"].as_str().unwrap().to_string();
+
+    let mut browser = app.browser();
+    let prompt = browser.get(&format!("/s/{}", code)).await;
+    assert!(prompt.body.contains("name=\"password\""), "{}", prompt.body);
+    assert_eq!(browser.post_form(&format!("/s/{}", code), &[("password", "not it")]).await.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(clicks(&app, &code), 0);
+
+    assert_eq!(browser.post_form(&format!("/s/{}", code), &[("password", "open sesame")]).await.status, StatusCode::SEE_OTHER);
+    assert_eq!(browser.get(&format!("/s/{}", code)).await.status, StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(clicks(&app, &code), 1);
+
+    assert_eq!(browser.get(&format!("/s/{}", code)).await.status, StatusCode::GONE);
+    assert_eq!(clicks(&app, &code), 1);
+}
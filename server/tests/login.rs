@@ -0,0 +1,60 @@
+// Admin password login: legacy hashes, upgrades and what a failed login gives away.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use axum::http::StatusCode;
+use sha2::{Digest, Sha256};
+
+use common::{Browser, TestApp, TestResponse};
+use ping0::admins::Role;
+
+#[tokio::test]
+async fn legacy_sha256_hash_logs_in_and_is_upgraded() {
+    let app = TestApp::new();
+    let salt = "0123456789abcdef";
+    let legacy = hex::encode(Sha256::digest(format!("{}{}", salt, "old password").as_bytes()));
+    app.state
+        .db
+        .get()
+        .unwrap()
+        .execute("INSERT INTO admin (username, password_hash, salt, role) VALUES ('legacy', ?1, ?2, 'owner')", [&legacy, salt])
+        .unwrap();
+
+    let mut browser = app.browser();
+    let wrong = browser.post_form("/admin/login", &[("username", "legacy"), ("password", "old passworD")]).await;
+    assert_eq!(wrong.status, StatusCode::UNAUTHORIZED);
+    browser.login("legacy", "old password").await;
+
+    let (hash, salt): (String, String) = app
+        .state
+        .db
+        .get()
+        .unwrap()
+        .query_row("SELECT password_hash, salt FROM admin WHERE username = 'legacy'", [], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap();
+    assert!(hash.starts_with("$argon2id$"), "{}", hash);
+    assert_eq!(salt, "");
+    app.browser().login("legacy", "old password").await;
+}
+
+async fn timed_login(browser: &mut Browser<'_>, username: &str) -> (TestResponse, Duration) {
+    let started = Instant::now();
+    let resp = browser.post_form("/admin/login", &[("username", username), ("password", "wrong password")]).await;
+    (resp, started.elapsed())
+}
+
+#[tokio::test]
+async fn unknown_usernames_look_like_wrong_passwords() {
+    let app = TestApp::new();
+    app.create_admin("erin", "correct horse battery", Role::Owner);
+    let mut browser = app.browser();
+    browser.csrf_token().await;
+
+    let (known, known_time) = timed_login(&mut browser, "erin").await;
+    let (unknown, unknown_time) = timed_login(&mut browser, "nobody").await;
+    assert_eq!((known.status, &known.body), (unknown.status, &unknown.body));
+    // Both pay for an Argon2 verification; without it the unknown user answers in well under a millisecond
+    assert!(unknown_time * 4 > known_time, "unknown {:?}, known {:?}", unknown_time, known_time);
+}
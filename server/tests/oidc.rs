@@ -0,0 +1,277 @@
+// Single sign-on against a mock identity provider: a local axum server with discovery, JWKS and a
+// token endpoint that checks the PKCE verifier. The test plays the browser at the authorize step.
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Form, Json, Router};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use common::{Browser, TestApp, TestResponse, BASE_URL};
+use ping0::admins::Role;
+use ping0::oidc::OidcConfig;
+
+const CLIENT_ID: &str = "ping0";
+const KEY_ID: &str = "test-key";
+
+struct Idp {
+    issuer: String,
+    key: SigningKey,
+    // Authorization code -> PKCE challenge and ID token claims
+    grants: Mutex<HashMap<String, (String, Value)>>,
+}
+
+#[derive(Clone)]
+struct MockIdp(Arc<Idp>);
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+fn b64(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+async fn discovery(State(idp): State<MockIdp>) -> Json<Value> {
+    let issuer = &idp.0.issuer;
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/authorize", issuer),
+        "token_endpoint": format!("{}/token", issuer),
+        "jwks_uri": format!("{}/jwks", issuer),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["ES256"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+async fn jwks(State(idp): State<MockIdp>) -> Json<Value> {
+    let point = idp.0.key.verifying_key().to_encoded_point(false);
+    Json(json!({ "keys": [{
+        "kty": "EC",
+        "crv": "P-256",
+        "x": b64(point.x().unwrap()),
+        "y": b64(point.y().unwrap()),
+        "kid": KEY_ID,
+        "use": "sig",
+        "alg": "ES256",
+    }] }))
+}
+
+async fn token(State(idp): State<MockIdp>, Form(form): Form<HashMap<String, String>>) -> Response {
+    let invalid_grant = (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_grant" }))).into_response();
+    let Some((challenge, claims)) = form.get("code").and_then(|code| idp.0.grants.lock().unwrap().remove(code)) else {
+        return invalid_grant;
+    };
+    let verifier = form.get("code_verifier").map(String::as_str).unwrap_or_default();
+    if b64(&Sha256::digest(verifier.as_bytes())) != challenge {
+        return invalid_grant;
+    }
+    Json(json!({ "access_token": "mock-access-token", "token_type": "Bearer", "expires_in": 300, "id_token": idp.id_token(&claims) })).into_response()
+}
+
+impl MockIdp {
+    async fn start() -> MockIdp {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let idp = MockIdp(Arc::new(Idp { issuer, key: SigningKey::random(&mut rand::rngs::OsRng), grants: Mutex::default() }));
+        let router = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(idp.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        idp
+    }
+
+    fn config(&self) -> OidcConfig {
+        let issuer = self.0.issuer.clone();
+        let var = move |name: &str| match name {
+            "OIDC_ISSUER" => Some(issuer.clone()),
+            "OIDC_CLIENT_ID" => Some(CLIENT_ID.to_string()),
+            "OIDC_ROLE_MAP" => Some("ping0-owners=owner,ping0-staff=viewer".to_string()),
+            _ => None,
+        };
+        OidcConfig::from_vars(BASE_URL, var).unwrap().expect("OIDC is configured")
+    }
+
+    fn id_token(&self, claims: &Value) -> String {
+        let header = b64(json!({ "alg": "ES256", "kid": KEY_ID, "typ": "JWT" }).to_string().as_bytes());
+        let payload = b64(claims.to_string().as_bytes());
+        let signature: Signature = self.0.key.sign(format!("{}.{}", header, payload).as_bytes());
+        format!("{}.{}.{}", header, payload, b64(&signature.to_bytes()))
+    }
+
+    // The authorize step: the user signs in at the IdP, which sends the browser back to the
+    // callback with a code. `user` holds the ID token claims beyond the standard ones (and may
+    // override them).
+    fn authorize(&self, authorize_url: &str, user: Value) -> String {
+        let url = Url::parse(authorize_url).unwrap();
+        assert!(authorize_url.starts_with(&format!("{}/authorize?", self.0.issuer)), "{}", authorize_url);
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(query["response_type"], "code");
+        assert_eq!(query["client_id"], CLIENT_ID);
+        assert_eq!(query["redirect_uri"], format!("{}/admin/oidc/callback", BASE_URL));
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert!(query["scope"].split(' ').any(|s| s == "openid"));
+
+        let mut claims = json!({
+            "iss": self.0.issuer,
+            "aud": CLIENT_ID,
+            "iat": now(),
+            "exp": now() + 300,
+            "nonce": query["nonce"],
+        });
+        claims.as_object_mut().unwrap().extend(user.as_object().unwrap().clone());
+        let code = uuid::Uuid::new_v4().to_string();
+        self.0.grants.lock().unwrap().insert(code.clone(), (query["code_challenge"].clone(), claims));
+        format!("/admin/oidc/callback?code={}&state={}", code, urlencoding::encode(&query["state"]))
+    }
+
+    fn subject(&self, sub: &str) -> String {
+        format!("{}|{}", self.0.issuer, sub)
+    }
+}
+
+fn app_with(idp: &MockIdp) -> TestApp {
+    let config = Arc::new(idp.config());
+    TestApp::with(move |state| state.oidc = Some(config))
+}
+
+// Starts SSO in a fresh browser and returns it with the IdP's authorize URL
+async fn start_sso(app: &TestApp) -> (Browser<'_>, String) {
+    let mut browser = app.browser();
+    let start = browser.get("/admin/oidc/login").await;
+    assert_eq!(start.status, StatusCode::SEE_OTHER, "{}", start.body);
+    assert!(browser.cookie("ping0_oidc").is_some());
+    let location = start.location().unwrap().to_string();
+    (browser, location)
+}
+
+async fn sso_login<'a>(app: &'a TestApp, idp: &MockIdp, user: Value) -> (Browser<'a>, TestResponse) {
+    let (mut browser, authorize_url) = start_sso(app).await;
+    let callback = idp.authorize(&authorize_url, user);
+    let resp = browser.get(&callback).await;
+    (browser, resp)
+}
+
+fn assert_logged_in(browser: &Browser<'_>, resp: &TestResponse) {
+    assert_eq!(resp.status, StatusCode::SEE_OTHER, "{}", resp.body);
+    assert_eq!(resp.location(), Some("/admin"));
+    assert!(browser.cookie("ping0_admin").is_some());
+}
+
+fn assert_refused(browser: &Browser<'_>, resp: &TestResponse, message: &str) {
+    assert_eq!(resp.status, StatusCode::FORBIDDEN);
+    assert!(resp.body.contains(message), "expected '{}' in {}", message, resp.body);
+    assert!(browser.cookie("ping0_admin").is_none());
+}
+
+fn sso_role(app: &TestApp, subject: &str) -> Option<String> {
+    let conn = app.state.db.get().unwrap();
+    conn.query_row("SELECT role FROM admin WHERE oidc_subject = ?1", [subject], |r| r.get(0)).ok()
+}
+
+#[tokio::test]
+async fn sso_login_round_trip_creates_a_session() {
+    let idp = MockIdp::start().await;
+    let app = app_with(&idp);
+    app.create_admin("root", "correct horse battery", Role::Owner);
+
+    let (mut browser, resp) = sso_login(&app, &idp, json!({ "sub": "u-1", "preferred_username": "dana", "groups": ["ping0-staff"] })).await;
+    assert_logged_in(&browser, &resp);
+    assert_eq!(browser.get("/admin").await.status, StatusCode::OK);
+    assert_eq!(sso_role(&app, &idp.subject("u-1")).as_deref(), Some("viewer"));
+
+    // The authorization code is single use
+    assert!(idp.0.grants.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn roles_follow_the_idp_groups_on_every_login() {
+    let idp = MockIdp::start().await;
+    let app = app_with(&idp);
+    app.create_admin("root", "correct horse battery", Role::Owner);
+    let subject = idp.subject("u-2");
+
+    // The highest role among the groups wins
+    let (browser, resp) = sso_login(&app, &idp, json!({ "sub": "u-2", "groups": ["ping0-staff", "ping0-owners"] })).await;
+    assert_logged_in(&browser, &resp);
+    assert_eq!(sso_role(&app, &subject).as_deref(), Some("owner"));
+
+    let (browser, resp) = sso_login(&app, &idp, json!({ "sub": "u-2", "groups": "ping0-staff" })).await;
+    assert_logged_in(&browser, &resp);
+    assert_eq!(sso_role(&app, &subject).as_deref(), Some("viewer"));
+
+    let (browser, resp) = sso_login(&app, &idp, json!({ "sub": "u-3", "groups": ["marketing"] })).await;
+    assert_refused(&browser, &resp, "has no ping0 role");
+    assert_eq!(sso_role(&app, &idp.subject("u-3")), None);
+}
+
+#[tokio::test]
+async fn the_last_owner_keeps_the_owner_role() {
+    let idp = MockIdp::start().await;
+    let app = app_with(&idp);
+    let subject = idp.subject("u-4");
+
+    let (browser, resp) = sso_login(&app, &idp, json!({ "sub": "u-4", "groups": ["ping0-owners"] })).await;
+    assert_logged_in(&browser, &resp);
+    let (browser, resp) = sso_login(&app, &idp, json!({ "sub": "u-4", "groups": ["ping0-staff"] })).await;
+    assert_logged_in(&browser, &resp);
+    assert_eq!(sso_role(&app, &subject).as_deref(), Some("owner"));
+}
+
+#[tokio::test]
+async fn callback_with_another_state_is_refused() {
+    let idp = MockIdp::start().await;
+    let app = app_with(&idp);
+    let (mut browser, authorize_url) = start_sso(&app).await;
+    let callback = idp.authorize(&authorize_url, json!({ "sub": "u-5", "groups": ["ping0-owners"] }));
+    let (path, _) = callback.split_once("&state=").unwrap();
+
+    let resp = browser.get(&format!("{}&state=forged", path)).await;
+    assert_refused(&browser, &resp, "started in another browser");
+    // The flow cookie is gone, so even the right state no longer works
+    let resp = browser.get(&callback).await;
+    assert_refused(&browser, &resp, "started in another browser");
+    assert_eq!(sso_role(&app, &idp.subject("u-5")), None);
+}
+
+#[tokio::test]
+async fn id_token_with_another_nonce_is_refused() {
+    let idp = MockIdp::start().await;
+    let app = app_with(&idp);
+    let (browser, resp) = sso_login(&app, &idp, json!({ "sub": "u-6", "groups": ["ping0-owners"], "nonce": "replayed" })).await;
+    assert_refused(&browser, &resp, "could not be verified");
+    assert_eq!(sso_role(&app, &idp.subject("u-6")), None);
+}
+
+#[tokio::test]
+async fn code_is_only_redeemed_with_the_pkce_verifier() {
+    let idp = MockIdp::start().await;
+    let app = app_with(&idp);
+    let (mut browser, authorize_url) = start_sso(&app).await;
+    let callback = idp.authorize(&authorize_url, json!({ "sub": "u-7", "groups": ["ping0-owners"] }));
+    // The IdP remembers a different challenge, as if the code had been issued to another client
+    for (challenge, _) in idp.0.grants.lock().unwrap().values_mut() {
+        *challenge = b64(&Sha256::digest(b"someone else's verifier"));
+    }
+
+    let resp = browser.get(&callback).await;
+    assert_refused(&browser, &resp, "did not accept the sign-in");
+    assert_eq!(sso_role(&app, &idp.subject("u-7")), None);
+}
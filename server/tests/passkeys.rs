@@ -0,0 +1,266 @@
+// Passkey registration and login through the router, with a software authenticator standing in for
+// the browser: it builds the attestation and assertion a platform authenticator would return.
+
+mod common;
+
+use axum::http::StatusCode;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use common::{Browser, TestApp, BASE_URL};
+use ping0::admins::Role;
+
+// Just enough CBOR for an attestation object and a COSE key
+enum Cbor<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    Text(&'a str),
+    Map(Vec<(Cbor<'a>, Cbor<'a>)>),
+}
+
+impl Cbor<'_> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Cbor::Int(n) if *n >= 0 => head(out, 0, *n as u64),
+            Cbor::Int(n) => head(out, 1, (-1 - *n) as u64),
+            Cbor::Bytes(b) => {
+                head(out, 2, b.len() as u64);
+                out.extend_from_slice(b);
+            }
+            Cbor::Text(s) => {
+                head(out, 3, s.len() as u64);
+                out.extend_from_slice(s.as_bytes());
+            }
+            Cbor::Map(entries) => {
+                head(out, 5, entries.len() as u64);
+                for (k, v) in entries {
+                    k.encode(out);
+                    v.encode(out);
+                }
+            }
+        }
+    }
+}
+
+fn head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    match n {
+        0..=23 => out.push(major | n as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, n as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 26);
+            out.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+    }
+}
+
+fn b64(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// Authenticator data flags
+const USER_PRESENT: u8 = 0x01;
+const USER_VERIFIED: u8 = 0x04;
+const ATTESTED_CREDENTIAL: u8 = 0x40;
+
+// One ES256 credential, as a platform authenticator would hold it
+struct SoftAuthenticator {
+    key: SigningKey,
+    cred_id: Vec<u8>,
+    counter: u32,
+}
+
+impl SoftAuthenticator {
+    fn new() -> SoftAuthenticator {
+        SoftAuthenticator { key: SigningKey::random(&mut rand::rngs::OsRng), cred_id: rand::random::<[u8; 16]>().to_vec(), counter: 0 }
+    }
+
+    fn auth_data(&mut self, rp_id: &str, flags: u8) -> Vec<u8> {
+        self.counter += 1;
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.counter.to_be_bytes());
+        data
+    }
+
+    fn client_data(kind: &str, options: &Value) -> Vec<u8> {
+        let challenge = options["publicKey"]["challenge"].as_str().expect("options carry a challenge");
+        json!({ "type": kind, "challenge": challenge, "origin": BASE_URL, "crossOrigin": false }).to_string().into_bytes()
+    }
+
+    // navigator.credentials.create, serialized the way passkey.js posts it
+    fn create(&mut self, options: &Value) -> Value {
+        let rp_id = options["publicKey"]["rp"]["id"].as_str().unwrap().to_string();
+        let point = self.key.verifying_key().to_encoded_point(false);
+        let mut cose_key = Vec::new();
+        Cbor::Map(vec![
+            (Cbor::Int(1), Cbor::Int(2)),
+            (Cbor::Int(3), Cbor::Int(-7)),
+            (Cbor::Int(-1), Cbor::Int(1)),
+            (Cbor::Int(-2), Cbor::Bytes(point.x().unwrap())),
+            (Cbor::Int(-3), Cbor::Bytes(point.y().unwrap())),
+        ])
+        .encode(&mut cose_key);
+
+        let mut auth_data = self.auth_data(&rp_id, USER_PRESENT | USER_VERIFIED | ATTESTED_CREDENTIAL);
+        auth_data.extend_from_slice(&[0; 16]);
+        auth_data.extend_from_slice(&(self.cred_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.cred_id);
+        auth_data.extend_from_slice(&cose_key);
+        let mut attestation = Vec::new();
+        Cbor::Map(vec![
+            (Cbor::Text("fmt"), Cbor::Text("none")),
+            (Cbor::Text("attStmt"), Cbor::Map(Vec::new())),
+            (Cbor::Text("authData"), Cbor::Bytes(&auth_data)),
+        ])
+        .encode(&mut attestation);
+
+        json!({
+            "id": b64(&self.cred_id),
+            "rawId": b64(&self.cred_id),
+            "type": "public-key",
+            "response": {
+                "attestationObject": b64(&attestation),
+                "clientDataJSON": b64(&Self::client_data("webauthn.create", options)),
+            },
+            "extensions": {},
+        })
+    }
+
+    // navigator.credentials.get for this credential, whatever the options allow
+    fn get(&mut self, options: &Value) -> Value {
+        let rp_id = options["publicKey"]["rpId"].as_str().unwrap().to_string();
+        let auth_data = self.auth_data(&rp_id, USER_PRESENT | USER_VERIFIED);
+        let client_data = Self::client_data("webauthn.get", options);
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = self.key.sign(&signed);
+        json!({
+            "id": b64(&self.cred_id),
+            "rawId": b64(&self.cred_id),
+            "type": "public-key",
+            "response": {
+                "authenticatorData": b64(&auth_data),
+                "clientDataJSON": b64(&client_data),
+                "signature": b64(signature.to_der().as_bytes()),
+                "userHandle": null,
+            },
+            "extensions": {},
+        })
+    }
+}
+
+async fn register_passkey(browser: &mut Browser<'_>, authenticator: &mut SoftAuthenticator) {
+    let start = browser.post_json("/admin/passkeys/register/start", &json!({})).await;
+    assert_eq!(start.status, StatusCode::OK, "{}", start.body);
+    let start = start.json();
+    let credential = authenticator.create(&start["options"]);
+    let finish = browser
+        .post_json("/admin/passkeys/register/finish", &json!({ "ceremony": start["ceremony"], "name": "laptop", "credential": credential }))
+        .await;
+    assert_eq!(finish.status, StatusCode::NO_CONTENT, "{}", finish.body);
+}
+
+async fn login_start(browser: &mut Browser<'_>, username: &str) -> Value {
+    let start = browser.post_json("/admin/login/passkey/start", &json!({ "username": username })).await;
+    assert_eq!(start.status, StatusCode::OK, "{}", start.body);
+    start.json()
+}
+
+fn session_count(app: &TestApp, admin_id: i64) -> i64 {
+    let conn = app.state.db.get().unwrap();
+    conn.query_row("SELECT COUNT(*) FROM sessions WHERE user_id = ?1", [admin_id], |r| r.get(0)).unwrap()
+}
+
+#[tokio::test]
+async fn passkey_login_creates_a_session() {
+    let app = TestApp::new();
+    let admin_id = app.create_admin("alice", "correct horse battery", Role::Owner);
+    let mut authenticator = SoftAuthenticator::new();
+    let mut setup = app.browser();
+    setup.login("alice", "correct horse battery").await;
+    register_passkey(&mut setup, &mut authenticator).await;
+    assert_eq!(session_count(&app, admin_id), 1);
+
+    let mut browser = app.browser();
+    let start = login_start(&mut browser, "alice").await;
+    let allowed = start["options"]["publicKey"]["allowCredentials"].as_array().unwrap();
+    assert_eq!(allowed.len(), 1);
+    assert_eq!(allowed[0]["id"], b64(&authenticator.cred_id));
+
+    let credential = authenticator.get(&start["options"]);
+    let finish = browser.post_json("/admin/login/passkey/finish", &json!({ "ceremony": start["ceremony"], "credential": credential })).await;
+    assert_eq!(finish.status, StatusCode::OK, "{}", finish.body);
+    assert_eq!(finish.json()["redirect"], "/admin");
+    assert!(finish.cookie("ping0_admin").is_some());
+    assert_eq!(session_count(&app, admin_id), 2);
+    assert_eq!(browser.get("/admin").await.status, StatusCode::OK);
+
+    // A ceremony is single use
+    let replay = browser.post_json("/admin/login/passkey/finish", &json!({ "ceremony": start["ceremony"], "credential": credential })).await;
+    assert_eq!(replay.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn unknown_users_get_a_stable_decoy_challenge() {
+    let app = TestApp::new();
+    app.create_admin("bob", "correct horse battery", Role::Owner);
+    let mut browser = app.browser();
+
+    let first = login_start(&mut browser, "nobody").await;
+    let second = login_start(&mut browser, "nobody").await;
+    let allowed = |start: &Value| start["options"]["publicKey"]["allowCredentials"].clone();
+    assert_eq!(allowed(&first).as_array().map(Vec::len), Some(1));
+    assert_eq!(allowed(&first), allowed(&second));
+    assert_ne!(first["options"]["publicKey"]["challenge"], second["options"]["publicKey"]["challenge"]);
+
+    // A user without passkeys looks the same as one who doesn't exist
+    let no_passkeys = login_start(&mut browser, "bob").await;
+    assert_eq!(allowed(&no_passkeys).as_array().map(Vec::len), Some(1));
+
+    let credential = SoftAuthenticator::new().get(&first["options"]);
+    let finish = browser.post_json("/admin/login/passkey/finish", &json!({ "ceremony": first["ceremony"], "credential": credential })).await;
+    assert_eq!(finish.status, StatusCode::UNAUTHORIZED);
+    assert!(browser.cookie("ping0_admin").is_none());
+}
+
+#[tokio::test]
+async fn failed_passkey_logins_are_throttled() {
+    let app = TestApp::new();
+    app.create_admin("carol", "correct horse battery", Role::Owner);
+    let mut authenticator = SoftAuthenticator::new();
+    let mut setup = app.browser();
+    setup.login("carol", "correct horse battery").await;
+    register_passkey(&mut setup, &mut authenticator).await;
+
+    // Same credential id, wrong key: every signature fails
+    let mut impostor = SoftAuthenticator::new();
+    impostor.cred_id = authenticator.cred_id.clone();
+    let mut browser = app.browser();
+    let mut statuses = Vec::new();
+    for _ in 0..10 {
+        let start = login_start(&mut browser, "carol").await;
+        let credential = impostor.get(&start["options"]);
+        let finish = browser.post_json("/admin/login/passkey/finish", &json!({ "ceremony": start["ceremony"], "credential": credential })).await;
+        statuses.push(finish.status);
+        if finish.status == StatusCode::TOO_MANY_REQUESTS {
+            break;
+        }
+    }
+    assert_eq!(statuses.last(), Some(&StatusCode::TOO_MANY_REQUESTS), "{:?}", statuses);
+    assert!(statuses[..statuses.len() - 1].iter().all(|s| *s == StatusCode::UNAUTHORIZED), "{:?}", statuses);
+
+    // The lock holds even for the real passkey
+    let start = login_start(&mut browser, "carol").await;
+    let credential = authenticator.get(&start["options"]);
+    let finish = browser.post_json("/admin/login/passkey/finish", &json!({ "ceremony": start["ceremony"], "credential": credential })).await;
+    assert_eq!(finish.status, StatusCode::TOO_MANY_REQUESTS);
+}
@@ -0,0 +1,45 @@
+// Rate limits as clients see them: a 429 with Retry-After, counted per API key or per IP.
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use serde_json::json;
+
+use common::{TestApp, TestResponse};
+use ping0::apikeys::{self, Scope};
+use ping0::ratelimit::RateLimiter;
+
+async fn create(app: &TestApp, token: Option<&str>) -> TestResponse {
+    let mut req = Request::post("/api/v1/items").header(header::CONTENT_TYPE, "application/json");
+    if let Some(token) = token {
+        req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    app.send(req.body(Body::from(json!({ "url": "https://example.com/" }).to_string())).unwrap()).await
+}
+
+#[tokio::test]
+async fn over_the_limit_is_a_429_with_retry_after() {
+    let app = TestApp::with(|state| {
+        state.rate_limiter = Arc::new(RateLimiter::from_vars(|name| (name == "RATE_LIMIT_REQUESTS_PER_MINUTE").then(|| "2".to_string())));
+    });
+    let (_, token) = apikeys::create_key(&app.state.db.get().unwrap(), "test", &[Scope::Create]).unwrap();
+
+    for _ in 0..2 {
+        assert_eq!(create(&app, None).await.status, StatusCode::CREATED);
+    }
+    let limited = create(&app, None).await;
+    assert_eq!(limited.status, StatusCode::TOO_MANY_REQUESTS, "{}", limited.body);
+    assert_eq!(limited.header("Retry-After"), Some("30"));
+    assert_eq!(limited.json()["error"]["code"], "rate_limited");
+
+    // The key has buckets of its own, and using it up doesn't touch the IP's any further
+    for _ in 0..2 {
+        assert_eq!(create(&app, Some(&token)).await.status, StatusCode::CREATED);
+    }
+    let limited = create(&app, Some(&token)).await;
+    assert_eq!(limited.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(limited.header("Retry-After"), Some("30"));
+}
@@ -0,0 +1,73 @@
+// S3Storage against a real S3-compatible server. Ignored unless PING0_TEST_S3_ENDPOINT is set (see
+// build.rs), together with PING0_TEST_S3_BUCKET and the PING0_TEST_S3_ACCESS_KEY_ID /
+// PING0_TEST_S3_SECRET_ACCESS_KEY credentials. With the MinIO container from the README:
+//
+//   PING0_TEST_S3_ENDPOINT=http://localhost:9000 PING0_TEST_S3_BUCKET=ping0 \
+//     PING0_TEST_S3_ACCESS_KEY_ID=minio PING0_TEST_S3_SECRET_ACCESS_KEY=minio123 cargo test --test storage
+//
+// Every run writes under its own random S3_PREFIX and deletes what it wrote.
+
+use std::io;
+
+use bytes::Bytes;
+use futures::{stream, StreamExt, TryStreamExt};
+
+use ping0::storage::{S3Storage, Storage};
+
+fn s3(presign_secs: &str) -> S3Storage {
+    let prefix = format!("ping0-test-{}", uuid::Uuid::new_v4());
+    S3Storage::from_vars(|name| match name {
+        "S3_PREFIX" => Some(prefix.clone()),
+        "S3_PRESIGN_SECS" => Some(presign_secs.to_string()),
+        _ => std::env::var(format!("PING0_TEST_{}", name)).ok(),
+    })
+    .expect("PING0_TEST_S3_BUCKET must be set as well")
+}
+
+async fn read(storage: &S3Storage, key: &str, range: Option<std::ops::Range<u64>>) -> Vec<u8> {
+    let chunks: Vec<Bytes> = storage.stream(key, range).await.unwrap().try_collect().await.unwrap();
+    chunks.concat()
+}
+
+#[tokio::test]
+#[cfg_attr(not(s3_test), ignore = "set PING0_TEST_S3_ENDPOINT to run against an S3-compatible server")]
+async fn s3_put_get_range_delete_presign() {
+    let storage = s3("60");
+
+    storage.put("notes.txt", Bytes::from("hello world")).await.unwrap();
+    assert_eq!(storage.get("notes.txt").await.unwrap(), "hello world");
+    assert_eq!(storage.stat("notes.txt").await.unwrap().map(|m| m.size), Some(11));
+    assert_eq!(read(&storage, "notes.txt", None).await, b"hello world");
+    assert_eq!(read(&storage, "notes.txt", Some(6..11)).await, b"world");
+    assert_eq!(read(&storage, "notes.txt", Some(0..1)).await, b"h");
+
+    // Large enough for more than one multipart part
+    let big: Vec<u8> = (0..12 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let chunks = big.chunks(1024 * 1024).map(|c| Ok(Bytes::copy_from_slice(c))).collect::<Vec<_>>();
+    let written = storage.put_stream("tus/upload/part", stream::iter(chunks).boxed()).await.unwrap();
+    assert_eq!(written, big.len() as u64);
+    assert_eq!(read(&storage, "tus/upload/part", Some(5_000_000..5_000_100)).await, &big[5_000_000..5_000_100]);
+
+    // A stream that fails leaves no object
+    let broken = stream::iter([Ok(Bytes::from("partial")), Err(io::Error::other("reset"))]).boxed();
+    assert!(storage.put_stream("broken.bin", broken).await.is_err());
+    assert!(storage.stat("broken.bin").await.unwrap().is_none());
+
+    // The presigned URL serves the object without credentials, with its content type
+    let url = storage.presigned_url("notes.txt").await.unwrap().expect("S3_PRESIGN_SECS is set");
+    assert!(url.contains("X-Amz-Signature="), "{}", url);
+    let resp = openidconnect::reqwest::get(&url).await.unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "text/plain");
+    assert_eq!(resp.text().await.unwrap(), "hello world");
+    assert_eq!(s3("0").presigned_url("notes.txt").await.unwrap(), None);
+
+    for key in ["notes.txt", "tus/upload/part"] {
+        storage.delete(key).await.unwrap();
+        assert!(storage.stat(key).await.unwrap().is_none());
+        assert_eq!(storage.get(key).await.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+    // Deleting what isn't there is fine; escaping the prefix isn't
+    storage.delete("notes.txt").await.unwrap();
+    assert_eq!(storage.put("../outside", Bytes::from("x")).await.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
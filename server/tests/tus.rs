@@ -0,0 +1,118 @@
+// Resumable uploads: offsets, interrupted chunks and PATCHes racing for the same offset.
+
+mod common;
+
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::http::{Request, StatusCode};
+use futures::channel::mpsc;
+use futures::{stream, SinkExt};
+
+use common::{tus_create, tus_patch, TestApp, TestResponse, BASE_URL};
+
+async fn create(app: &TestApp, length: usize) -> String {
+    let created = app.send(tus_create(length, &[("filename", "notes.txt")])).await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    created.location().unwrap().strip_prefix(BASE_URL).unwrap().to_string()
+}
+
+async fn offset(app: &TestApp, path: &str) -> String {
+    let head = app.send(Request::head(path).header("Tus-Resumable", "1.0.0").body(Body::empty()).unwrap()).await;
+    assert_eq!(head.status, StatusCode::OK);
+    head.header("Upload-Offset").unwrap().to_string()
+}
+
+// Content of the file behind a finished upload
+async fn served(app: &TestApp, finished: &TestResponse) -> String {
+    let code = finished.header("Ping0-Short-Url").expect("upload finished").rsplit('/').next().unwrap().to_string();
+    let value: String = app.state.db.get().unwrap().query_row("SELECT value FROM items WHERE code = ?1", [&code], |r| r.get(0)).unwrap();
+    let file = app.send(Request::get(format!("/files/{}", value.strip_prefix("file:").unwrap())).body(Body::empty()).unwrap()).await;
+    assert_eq!(file.status, StatusCode::OK);
+    file.body
+}
+
+fn count_files(dir: &Path) -> usize {
+    std::fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|e| if e.path().is_dir() { count_files(&e.path()) } else { 1 }).sum())
+        .unwrap_or(0)
+}
+
+#[tokio::test]
+async fn upload_in_chunks() {
+    let app = TestApp::new();
+    let path = create(&app, 11).await;
+    let first = app.send(tus_patch(&path, 0, "hello ")).await;
+    assert_eq!(first.status, StatusCode::NO_CONTENT);
+    assert_eq!(first.header("Upload-Offset"), Some("6"));
+    assert_eq!(offset(&app, &path).await, "6");
+
+    let last = app.send(tus_patch(&path, 6, "world")).await;
+    assert_eq!(last.status, StatusCode::NO_CONTENT, "{}", last.body);
+    assert_eq!(served(&app, &last).await, "hello world");
+    assert_eq!(count_files(&app.uploads_dir().join("tus")), 0);
+}
+
+#[tokio::test]
+async fn offset_mismatch_is_a_conflict() {
+    let app = TestApp::new();
+    let path = create(&app, 11).await;
+    assert_eq!(app.send(tus_patch(&path, 0, "hello ")).await.status, StatusCode::NO_CONTENT);
+
+    for stale in [0, 3, 11] {
+        let resp = app.send(tus_patch(&path, stale, "world")).await;
+        assert_eq!(resp.status, StatusCode::CONFLICT);
+        assert_eq!(resp.header("Upload-Offset"), Some("6"));
+    }
+    assert_eq!(offset(&app, &path).await, "6");
+}
+
+#[tokio::test]
+async fn interrupted_patch_is_resumed_from_the_last_offset() {
+    let app = TestApp::new();
+    let path = create(&app, 11).await;
+    assert_eq!(app.send(tus_patch(&path, 0, "hello ")).await.status, StatusCode::NO_CONTENT);
+
+    // The connection drops halfway through the second chunk
+    let broken = stream::iter([Ok(Bytes::from("wo")), Err(io::Error::new(io::ErrorKind::ConnectionReset, "connection reset"))]);
+    let resp = app.send(tus_patch(&path, 6, Body::from_stream(broken))).await;
+    assert!(resp.status.is_server_error(), "{}", resp.status);
+    assert_eq!(offset(&app, &path).await, "6");
+
+    let resumed = app.send(tus_patch(&path, 6, "world")).await;
+    assert_eq!(resumed.status, StatusCode::NO_CONTENT, "{}", resumed.body);
+    assert_eq!(served(&app, &resumed).await, "hello world");
+    assert_eq!(count_files(&app.uploads_dir().join("tus")), 0);
+}
+
+#[tokio::test]
+async fn concurrent_patches_at_one_offset_keep_the_winners_part() {
+    let app = TestApp::new();
+    let path = create(&app, 11).await;
+
+    // Both requests pass the offset check before either body is complete
+    let (mut tx_a, rx_a) = mpsc::channel::<io::Result<Bytes>>(1);
+    let (mut tx_b, rx_b) = mpsc::channel::<io::Result<Bytes>>(1);
+    let feed = async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        tx_a.send(Ok(Bytes::from("hello "))).await.unwrap();
+        drop(tx_a);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        tx_b.send(Ok(Bytes::from("HELLO "))).await.unwrap();
+    };
+    let (a, b, ()) = tokio::join!(
+        app.send(tus_patch(&path, 0, Body::from_stream(rx_a))),
+        app.send(tus_patch(&path, 0, Body::from_stream(rx_b))),
+        feed,
+    );
+    assert_eq!(a.status, StatusCode::NO_CONTENT, "{}", a.body);
+    assert_eq!(b.status, StatusCode::CONFLICT, "{}", b.body);
+    assert_eq!(offset(&app, &path).await, "6");
+    assert_eq!(count_files(&app.uploads_dir().join("tus")), 1);
+
+    let last = app.send(tus_patch(&path, 6, "world")).await;
+    assert_eq!(last.status, StatusCode::NO_CONTENT, "{}", last.body);
+    assert_eq!(served(&app, &last).await, "hello world");
+}
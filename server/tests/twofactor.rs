@@ -0,0 +1,56 @@
+// Two-factor login end to end: the password alone only gets a challenge, the code turns it into a session.
+
+mod common;
+
+use axum::http::StatusCode;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use common::TestApp;
+use ping0::admins::Role;
+
+const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+fn current_code() -> String {
+    let bytes = Secret::Encoded(SECRET.to_string()).to_bytes().unwrap();
+    TOTP::new(Algorithm::SHA1, 6, 0, 30, bytes, None, "alice".to_string()).unwrap().generate_current().unwrap()
+}
+
+#[tokio::test]
+async fn password_then_code_then_session() {
+    let app = TestApp::new();
+    let id = app.create_admin("alice", "correct horse battery", Role::Owner);
+    app.state.db.get().unwrap().execute("UPDATE admin SET totp_secret = ?1 WHERE id = ?2", (SECRET, id)).unwrap();
+
+    let mut browser = app.browser();
+    let challenged = browser.post_form("/admin/login", &[("username", "alice"), ("password", "correct horse battery")]).await;
+    assert_eq!(challenged.status, StatusCode::OK, "{}", challenged.body);
+    assert!(challenged.body.contains("action=\"/admin/login/2fa\""), "{}", challenged.body);
+    assert!(browser.cookie("ping0_2fa").is_some());
+    assert!(browser.cookie("ping0_admin").is_none());
+    assert_eq!(browser.get("/admin").await.location(), Some("/admin/login"));
+
+    let wrong = browser.post_form("/admin/login/2fa", &[("code", "not a code")]).await;
+    assert_eq!(wrong.status, StatusCode::UNAUTHORIZED);
+    assert!(browser.cookie("ping0_admin").is_none());
+
+    let code = current_code();
+    let done = browser.post_form("/admin/login/2fa", &[("code", &code)]).await;
+    assert_eq!(done.status, StatusCode::SEE_OTHER, "{}", done.body);
+    assert_eq!(done.location(), Some("/admin"));
+    assert!(browser.cookie("ping0_admin").is_some());
+    assert!(browser.cookie("ping0_2fa").is_none());
+    assert_eq!(browser.get("/admin").await.status, StatusCode::OK);
+
+    // The same code doesn't get a second browser in
+    let mut other = app.browser();
+    other.post_form("/admin/login", &[("username", "alice"), ("password", "correct horse battery")]).await;
+    let replayed = other.post_form("/admin/login/2fa", &[("code", &code)]).await;
+    assert_eq!(replayed.status, StatusCode::UNAUTHORIZED);
+    assert!(other.cookie("ping0_admin").is_none());
+
+    // Nor does skipping the password step
+    let mut direct = app.browser();
+    let skipped = direct.post_form("/admin/login/2fa", &[("code", &current_code())]).await;
+    assert_eq!(skipped.location(), Some("/admin/login"));
+    assert!(direct.cookie("ping0_admin").is_none());
+}
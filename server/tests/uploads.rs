@@ -0,0 +1,93 @@
+// Password-protected items created through every upload entry point: each one must store an
+// Argon2id hash and put the item behind the unlock prompt.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use serde_json::json;
+
+use common::{multipart, tus_create, tus_patch, TestApp};
+
+const PASSWORD: &str = "open sesame";
+
+fn code_of(url: &str) -> String {
+    url.trim_end_matches('/').rsplit('/').next().unwrap().split('?').next().unwrap().to_string()
+}
+
+async fn assert_password_protected(app: &TestApp, code: &str) {
+    let hash: Option<String> = app
+        .state
+        .db
+        .get()
+        .unwrap()
+        .query_row("SELECT password_hash FROM items WHERE code = ?1", [code], |r| r.get(0))
+        .unwrap();
+    assert!(hash.as_deref().is_some_and(|h| h.starts_with("$argon2id$")), "{:?}", hash);
+
+    let mut browser = app.browser();
+    let prompt = browser.get(&format!("/s/{}", code)).await;
+    assert_eq!(prompt.status, StatusCode::OK);
+    assert!(prompt.body.contains("name=\"password\""), "{}", prompt.body);
+    let wrong = browser.post_form(&format!("/s/{}", code), &[("password", "not it")]).await;
+    assert_eq!(wrong.status, StatusCode::UNAUTHORIZED);
+    let right = browser.post_form(&format!("/s/{}", code), &[("password", PASSWORD)]).await;
+    assert_eq!(right.status, StatusCode::SEE_OTHER, "{}", right.body);
+    assert_eq!(right.location(), Some(format!("/s/{}", code).as_str()));
+}
+
+#[tokio::test]
+async fn submit_form() {
+    let app = TestApp::new();
+    let link = app.send(multipart("/submit", &[("link", "https://example.com/"), ("password", PASSWORD)], None)).await;
+    assert_eq!(link.status, StatusCode::SEE_OTHER, "{}", link.body);
+    assert_password_protected(&app, &code_of(link.location().unwrap())).await;
+
+    let file = app.send(multipart("/submit", &[("password", PASSWORD)], Some(("file", "notes.txt", b"secret notes")))).await;
+    assert_eq!(file.status, StatusCode::SEE_OTHER, "{}", file.body);
+    assert_password_protected(&app, &code_of(file.location().unwrap())).await;
+}
+
+#[tokio::test]
+async fn legacy_api_upload() {
+    let app = TestApp::new();
+    let link = app.send(multipart("/api/upload", &[("content", "https://example.com/"), ("password", PASSWORD)], None)).await;
+    assert_eq!(link.status, StatusCode::OK, "{}", link.body);
+    assert_password_protected(&app, &code_of(link.json()["short_url"].as_str().unwrap())).await;
+
+    let file = app.send(multipart("/api/upload", &[("password", PASSWORD)], Some(("content", "notes.txt", b"secret notes")))).await;
+    assert_eq!(file.status, StatusCode::OK, "{}", file.body);
+    assert_password_protected(&app, &code_of(file.json()["short_url"].as_str().unwrap())).await;
+}
+
+#[tokio::test]
+async fn v1_items_api() {
+    let app = TestApp::new();
+    let req = Request::post("/api/v1/items")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "url": "https://example.com/", "password": PASSWORD }).to_string()))
+        .unwrap();
+    let link = app.send(req).await;
+    assert_eq!(link.status, StatusCode::CREATED, "{}", link.body);
+    assert_password_protected(&app, link.json()["code"].as_str().unwrap()).await;
+
+    let file = app.send(multipart("/api/v1/items", &[("password", PASSWORD)], Some(("file", "notes.txt", b"secret notes")))).await;
+    assert_eq!(file.status, StatusCode::CREATED, "{}", file.body);
+    assert_password_protected(&app, file.json()["code"].as_str().unwrap()).await;
+}
+
+#[tokio::test]
+async fn tus_upload() {
+    let app = TestApp::new();
+    let data = b"secret notes";
+    let created = app.send(tus_create(data.len(), &[("filename", "notes.txt"), ("password", PASSWORD)])).await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let path = created.location().unwrap().strip_prefix(common::BASE_URL).unwrap().to_string();
+    // Only the hash is kept while the upload is in progress
+    let metadata: String = app.state.db.get().unwrap().query_row("SELECT metadata FROM tus_uploads", [], |r| r.get(0)).unwrap();
+    assert!(!metadata.contains(PASSWORD) && metadata.contains("$argon2id$"), "{}", metadata);
+
+    let finished = app.send(tus_patch(&path, 0, data.to_vec())).await;
+    assert_eq!(finished.status, StatusCode::NO_CONTENT, "{}", finished.body);
+    assert_password_protected(&app, &code_of(finished.header("Ping0-Short-Url").unwrap())).await;
+}